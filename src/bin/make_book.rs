@@ -0,0 +1,109 @@
+// Builds a Polyglot opening book from PGN files:
+//
+//   make_book [options] -o <book.bin> <games.pgn>...
+use rusty_chess::{BookBuilder, BookOptions};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process;
+
+const USAGE: &str = "usage: make_book [options] -o <book.bin> <games.pgn>...
+
+options:
+  --max-ply <n>       only add the first n plies of each game (default 30)
+  --min-rating <elo>  only add moves of players rated at least this
+  --min-games <n>     leave out moves played in fewer games (default 1)
+  --exclude-losses    do not add moves of the side that lost";
+
+fn main() {
+    let mut options = BookOptions::default();
+    let mut output = None;
+    let mut inputs = Vec::new();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut number = |name: &str| -> u32 {
+            match args.next().map(|value| value.parse()) {
+                Some(Ok(value)) => value,
+                _ => fail(&format!("{} expects a number", name)),
+            }
+        };
+        match arg.as_str() {
+            "-o" | "--output" => output = args.next(),
+            "--max-ply" => options.max_ply = number(&arg) as usize,
+            "--min-rating" => options.min_rating = Some(number(&arg)),
+            "--min-games" => options.min_games = number(&arg),
+            "--exclude-losses" => options.exclude_losses = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if arg.starts_with('-') => fail(&format!("unknown option {}", arg)),
+            _ => inputs.push(arg),
+        }
+    }
+    let Some(output) = output else {
+        fail("no output file given");
+    };
+    if inputs.is_empty() {
+        fail("no PGN files given");
+    }
+
+    let mut builder = BookBuilder::new(options);
+    for input in &inputs {
+        let text = read_pgn_file(input)
+            .unwrap_or_else(|error| fail(&format!("could not read {}: {}", input, error)));
+        match builder.add_pgn(&text) {
+            (used, 0) => println!("{}: {} games", input, used),
+            (used, skipped) => println!(
+                "{}: {} games, {} malformed games skipped",
+                input, used, skipped
+            ),
+        }
+    }
+    let book = builder.build();
+    if let Err(error) = book.save(&output) {
+        fail(&format!("could not write {}: {}", output, error));
+    }
+    println!(
+        "{} entries from {} games written to {}",
+        book.len(),
+        builder.games(),
+        output
+    );
+}
+
+// Many PGN collections are in Latin-1, bytes that are not UTF-8 are
+// replaced so that the games can still be read.
+fn read_pgn_file(path: impl AsRef<Path>) -> io::Result<String> {
+    let bytes = fs::read(path)?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("make_book: {}", message);
+    eprintln!("{}", USAGE);
+    process::exit(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_latin1_pgn() {
+        let path = env::temp_dir().join(format!("rusty_chess_latin1_{}.pgn", process::id()));
+        // "Réti" in Latin-1
+        fs::write(
+            &path,
+            b"[White \"R\xe9ti\"]\n[Result \"1-0\"]\n\n1. Nf3 d5 1-0\n",
+        )
+        .unwrap();
+        let text = read_pgn_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(text.contains("[White \"R\u{FFFD}ti\"]"));
+        let mut builder = BookBuilder::new(BookOptions::default());
+        assert_eq!(builder.add_pgn(&text), (1, 0));
+    }
+}
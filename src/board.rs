@@ -1,4 +1,11 @@
 use crate::constants::Piece;
+use crate::evaluation::PIECE_VALUES;
+use crate::fen::FenError;
+use crate::magic;
+use crate::zobrist::Zobrist;
+
+// the rooks in the corners, as in standard chess
+pub const STANDARD_CASTLING_ROOKS: [u8; 4] = [7, 0, 63, 56];
 
 #[derive(Clone, Copy)]
 pub struct Board {
@@ -21,6 +28,15 @@ pub struct Board {
     king_moves: [u64; 64],
 
     pub piece_list: [Piece; 64], // Maps square index to piece type
+
+    // Squares the castling rooks start from, indexed like the bits of the
+    // castling rights. In Chess960 they are not always in the corners.
+    pub castling_rooks: [u8; 4],
+
+    // Zobrist hash of the piece placement, updated whenever a piece is added
+    // or removed. Castling rights, en passant and side to move are part of the
+    // game state and hashed there.
+    zobrist_hash: u64,
 }
 
 impl Board {
@@ -36,10 +52,12 @@ impl Board {
             knights: 0,
             pawns: 0,
             piece_list: [Piece::Empty; 64],
+            castling_rooks: STANDARD_CASTLING_ROOKS,
             white_pawn_attacks: [0; 64],
             black_pawn_attacks: [0; 64],
             knight_moves: [0; 64],
             king_moves: [0; 64],
+            zobrist_hash: 0,
         };
         board.init_move_tables();
         board
@@ -85,7 +103,7 @@ impl Board {
             for &pattern in &patterns {
                 let new_rank = rank + pattern[0];
                 let new_file = file + pattern[1];
-                if new_rank >= 0 && new_rank <= 7 && new_file >= 0 && new_file <= 7 {
+                if (0..=7).contains(&new_rank) && (0..=7).contains(&new_file) {
                     bitboard[square as usize] |= 1 << (new_rank * 8 + new_file);
                 }
             }
@@ -94,6 +112,8 @@ impl Board {
     }
 
     fn init_piece_list(&mut self) {
+        let keys = Zobrist::keys();
+        self.zobrist_hash = 0;
         for i in 0..64 {
             let field: u64 = 1 << i;
             if self.occupied_squares & field == 0 {
@@ -135,6 +155,7 @@ impl Board {
                     self.piece_list[i] = Piece::BlackPawn;
                 }
             }
+            self.zobrist_hash ^= keys.piece_key(self.piece_list[i], i);
         }
     }
 
@@ -175,14 +196,27 @@ impl Board {
         (from, to, promotion, en_passant, castle)
     }
 
-    fn encode_move(&self, from: usize, to: usize, special: usize) -> u16 {
+    fn encode_move(&self, from: usize, to: usize, special: u16) -> u16 {
         let mut mv: u16 = 0;
         mv |= from as u16;
         mv |= (to as u16) << 6;
-        mv |= (special as u16) << 12;
+        mv |= special;
         mv
     }
 
+    // The squares the rook of a castling move comes from and goes to. The
+    // move itself only holds the king's squares, the king ends up on the g
+    // or c file and the rook next to it.
+    pub fn castling_rook_squares(&self, mv: u16) -> (usize, usize) {
+        let from = (mv & 0x3F) as usize;
+        let to = ((mv >> 6) & 0x3F) as usize;
+        let kingside = mv & 0xF000 == 0x2000;
+        let color = if from < 8 { 0 } else { 2 };
+        let right = if kingside { color } else { color + 1 };
+        let rook_to = if kingside { to - 1 } else { to + 1 };
+        (self.castling_rooks[right] as usize, rook_to)
+    }
+
     // remove piece from a square (used for moving pieces and captures)
     fn remove_piece(&mut self, square: usize) {
         let mask: u64 = 1 << square;
@@ -203,6 +237,7 @@ impl Board {
         }
         self.occupied_squares &= !mask;
         self.piece_list[square] = Piece::Empty;
+        self.zobrist_hash ^= Zobrist::keys().piece_key(piece, square);
     }
 
     // add piece to a square (used for moving pieces and undoing captures)
@@ -224,9 +259,10 @@ impl Board {
             self.black_pieces |= mask;
         }
         self.piece_list[square] = piece;
+        self.zobrist_hash ^= Zobrist::keys().piece_key(piece, square);
     }
 
-    fn bitboard_to_moves(&self, bitboard: u64, from: usize, special: usize) -> Vec<u16> {
+    fn bitboard_to_moves(&self, bitboard: u64, from: usize, special: u16) -> Vec<u16> {
         let mut moves = Vec::new();
         let mut bb = bitboard;
         while bb != 0 {
@@ -237,12 +273,32 @@ impl Board {
         moves
     }
 
-    fn gen_pawn_moves(&self, square: usize, is_white: bool) -> Vec<u16> {
+    // split a target bitboard into quiet moves and captures
+    fn targets_to_moves(&self, targets: u64, from: usize, is_white: bool) -> Vec<u16> {
+        let enemy_pieces = if is_white {
+            self.black_pieces
+        } else {
+            self.white_pieces
+        };
+        let mut moves = self.bitboard_to_moves(targets & !self.occupied_squares, from, 0x0000);
+        moves.extend_from_slice(&self.bitboard_to_moves(targets & enemy_pieces, from, 0x4000));
+        moves
+    }
+
+    // `mask` restricts the destination squares (used for check evasions and pins),
+    // with `captures_only` quiet pushes are skipped unless they promote
+    fn gen_pawn_moves(
+        &self,
+        square: usize,
+        is_white: bool,
+        mask: u64,
+        captures_only: bool,
+    ) -> Vec<u16> {
         // add pawn attacks (which have to capture)
         let pawn_attacks = if is_white {
-            self.white_pawn_attacks[square] & self.black_pieces
+            self.white_pawn_attacks[square] & self.black_pieces & mask
         } else {
-            self.black_pawn_attacks[square] & self.white_pieces
+            self.black_pawn_attacks[square] & self.white_pieces & mask
         };
 
         // add quiet moves
@@ -272,10 +328,13 @@ impl Board {
 
         let is_promotion = if is_white { square >= 48 } else { square <= 15 };
 
+        let single_push =
+            single_push.filter(|&to| mask & (1 << to) != 0 && (is_promotion || !captures_only));
+        let double_push = double_push.filter(|&to| mask & (1 << to) != 0 && !captures_only);
+
         let mut moves = Vec::new();
         if is_promotion {
-            if single_push.is_some() {
-                let to = single_push.unwrap();
+            if let Some(to) = single_push {
                 moves.push(self.encode_move(square, to, 0x8000));
                 moves.push(self.encode_move(square, to, 0x9000));
                 moves.push(self.encode_move(square, to, 0xA000));
@@ -286,12 +345,10 @@ impl Board {
             moves.extend_from_slice(&self.bitboard_to_moves(pawn_attacks, square, 0xA000 | 0x4000));
             moves.extend_from_slice(&self.bitboard_to_moves(pawn_attacks, square, 0xB000 | 0x4000));
         } else {
-            if single_push.is_some() {
-                let to = single_push.unwrap();
+            if let Some(to) = single_push {
                 moves.push(self.encode_move(square, to, 0x0000));
             }
-            if double_push.is_some() {
-                let to = double_push.unwrap();
+            if let Some(to) = double_push {
                 moves.push(self.encode_move(square, to, 0x1000));
             }
             moves.extend_from_slice(&self.bitboard_to_moves(pawn_attacks, square, 0x4000));
@@ -299,7 +356,55 @@ impl Board {
         moves
     }
 
-    fn gen_knight_moves(&self, square: usize, is_white: bool) -> Vec<u16> {
+    fn gen_en_passant_moves(
+        &self,
+        ep_square: usize,
+        is_white: bool,
+        king_square: usize,
+    ) -> Vec<u16> {
+        let (own_pieces, enemy_pieces, capture_square) = if is_white {
+            (self.white_pieces, self.black_pieces, ep_square - 8)
+        } else {
+            (self.black_pieces, self.white_pieces, ep_square + 8)
+        };
+        let enemy_pawn = if is_white {
+            Piece::BlackPawn
+        } else {
+            Piece::WhitePawn
+        };
+        if self.piece_list[capture_square] != enemy_pawn
+            || self.piece_list[ep_square] != Piece::Empty
+        {
+            return Vec::new();
+        }
+
+        // our pawns that attack the en passant square
+        let mut candidates = if is_white {
+            self.black_pawn_attacks[ep_square]
+        } else {
+            self.white_pawn_attacks[ep_square]
+        } & self.pawns
+            & own_pieces;
+
+        let mut moves = Vec::new();
+        while candidates != 0 {
+            let from = candidates.trailing_zeros() as usize;
+            candidates &= candidates - 1;
+
+            // en passant removes two pieces from the same rank, so instead of
+            // reasoning about pins we recompute the attacks on our king
+            let occupied =
+                (self.occupied_squares ^ (1 << from) ^ (1 << capture_square)) | (1 << ep_square);
+            let attackers =
+                self.attackers_to(king_square, occupied) & enemy_pieces & !(1 << capture_square);
+            if attackers == 0 {
+                moves.push(self.encode_move(from, ep_square, 0x5000));
+            }
+        }
+        moves
+    }
+
+    fn gen_knight_moves(&self, square: usize, is_white: bool, mask: u64) -> Vec<u16> {
         let mut knight_moves = self.knight_moves[square];
         if is_white {
             knight_moves &= !self.white_pieces;
@@ -307,100 +412,369 @@ impl Board {
             knight_moves &= !self.black_pieces;
         }
 
-        self.bitboard_to_moves(knight_moves, square, 0)
+        self.targets_to_moves(knight_moves & mask, square, is_white)
     }
 
-    // helper function for bishop, rook and queen moves
-    fn gen_ray_moves(&self, patterns: [[i16; 2]; 4], square: usize, is_white: bool) -> Vec<u16> {
-        let mut moves = Vec::new();
+    // sliding attacks are looked up in the precomputed magic bitboard tables
+    fn bishop_attacks(&self, square: usize, occupied: u64) -> u64 {
+        magic::bishop_attacks(square, occupied)
+    }
 
-        for pattern in patterns {
-            let mut rank = (square / 8) as i16 + pattern[0];
-            let mut file = (square % 8) as i16 + pattern[1];
+    fn rook_attacks(&self, square: usize, occupied: u64) -> u64 {
+        magic::rook_attacks(square, occupied)
+    }
 
-            while rank >= 0 && rank < 8 && file >= 0 && file < 8 {
-                let to = (rank * 8 + file) as usize;
-                if self.piece_list[to] == Piece::Empty {
-                    moves.push(self.encode_move(square, to, 0x0000));
-                    rank += pattern[0];
-                    file += pattern[1];
-                    continue;
-                }
-                // check capture, then stop
-                if is_white && self.piece_list[to] as u8 > Piece::WhitePawn as u8 {
-                    moves.push(self.encode_move(square, to, 0x4000));
-                } else if !is_white && self.piece_list[to] as u8 <= Piece::WhitePawn as u8 {
-                    moves.push(self.encode_move(square, to, 0x4000));
-                }
-                break;
+    fn queen_attacks(&self, square: usize, occupied: u64) -> u64 {
+        self.bishop_attacks(square, occupied) | self.rook_attacks(square, occupied)
+    }
+
+    fn gen_bishop_moves(&self, square: usize, is_white: bool, mask: u64) -> Vec<u16> {
+        let targets = self.bishop_attacks(square, self.occupied_squares);
+        self.targets_to_moves(targets & mask, square, is_white)
+    }
+
+    fn gen_rook_moves(&self, square: usize, is_white: bool, mask: u64) -> Vec<u16> {
+        let targets = self.rook_attacks(square, self.occupied_squares);
+        self.targets_to_moves(targets & mask, square, is_white)
+    }
+
+    fn gen_queen_moves(&self, square: usize, is_white: bool, mask: u64) -> Vec<u16> {
+        let targets = self.queen_attacks(square, self.occupied_squares);
+        self.targets_to_moves(targets & mask, square, is_white)
+    }
+
+    // `attacked` holds every square the opponent attacks with our king removed
+    // from the board, so the king cannot step back along a checking ray
+    fn gen_king_moves(
+        &self,
+        square: usize,
+        is_white: bool,
+        attacked: u64,
+        castling_rights: u8,
+        captures_only: bool,
+    ) -> Vec<u16> {
+        let mut king_moves = self.king_moves[square] & !attacked;
+        if is_white {
+            king_moves &= if captures_only {
+                self.black_pieces
+            } else {
+                !self.white_pieces
+            };
+        } else {
+            king_moves &= if captures_only {
+                self.white_pieces
+            } else {
+                !self.black_pieces
+            };
+        }
+
+        let mut moves = self.targets_to_moves(king_moves, square, is_white);
+
+        // add castling moves: the king may not castle out of, through or into check
+        let in_check = attacked & (1 << square) != 0;
+        if in_check || captures_only {
+            return moves;
+        }
+        let (rank, first_right, rook) = if is_white {
+            (0, 0, Piece::WhiteRook)
+        } else {
+            (56, 2, Piece::BlackRook)
+        };
+        let enemy_pieces = if is_white {
+            self.black_pieces
+        } else {
+            self.white_pieces
+        };
+        for (right, king_to, rook_to, flag) in [
+            (first_right, rank + 6, rank + 5, 0x2000),
+            (first_right + 1, rank + 2, rank + 3, 0x3000),
+        ] {
+            let rook_from = self.castling_rooks[right] as usize;
+            if castling_rights & (1 << right) == 0 || self.piece_list[rook_from] != rook {
+                continue;
+            }
+            // apart from the king and the rook themselves, nothing may stand
+            // between them and their target squares
+            let king_path = self.between(square, king_to) | (1 << king_to);
+            let rook_path = self.between(rook_from, rook_to) | (1 << rook_to);
+            let occupied = self.occupied_squares & !(1 << square) & !(1 << rook_from);
+            if (king_path | rook_path) & occupied != 0 {
+                continue;
+            }
+            // in Chess960 the rook may shield the king's target square, so the
+            // attacks are found without it
+            let mut path = king_path;
+            let mut is_safe = true;
+            while path != 0 && is_safe {
+                let target = path.trailing_zeros() as usize;
+                path &= path - 1;
+                is_safe = self.attackers_to(target, occupied) & enemy_pieces == 0;
+            }
+            if is_safe {
+                moves.push(self.encode_move(square, king_to, flag));
             }
         }
 
         moves
     }
 
-    fn gen_bishop_moves(&self, square: usize, is_white: bool) -> Vec<u16> {
-        self.gen_ray_moves([[1, 1], [1, -1], [-1, 1], [-1, -1]], square, is_white)
+    // squares strictly between two squares on a common rank, file or diagonal
+    fn between(&self, a: usize, b: usize) -> u64 {
+        let rank_delta = (b / 8) as i16 - (a / 8) as i16;
+        let file_delta = (b % 8) as i16 - (a % 8) as i16;
+        if a == b || (rank_delta != 0 && file_delta != 0 && rank_delta.abs() != file_delta.abs()) {
+            return 0;
+        }
+        let step = rank_delta.signum() * 8 + file_delta.signum();
+        let mut squares = 0;
+        let mut square = a as i16 + step;
+        while square != b as i16 {
+            squares |= 1 << square;
+            square += step;
+        }
+        squares
     }
 
-    fn gen_rook_moves(&self, square: usize, is_white: bool) -> Vec<u16> {
-        self.gen_ray_moves([[0, 1], [0, -1], [1, 0], [-1, 0]], square, is_white)
+    // all pieces of both colors attacking `square`, given the occupancy `occupied`
+    fn attackers_to(&self, square: usize, occupied: u64) -> u64 {
+        let rooks_queens = (self.rooks | self.queens) & occupied;
+        let bishops_queens = (self.bishops | self.queens) & occupied;
+        (self.white_pawn_attacks[square] & self.pawns & self.black_pieces)
+            | (self.black_pawn_attacks[square] & self.pawns & self.white_pieces)
+            | (self.knight_moves[square] & self.knights)
+            | (self.king_moves[square] & self.kings)
+            | (self.rook_attacks(square, occupied) & rooks_queens)
+            | (self.bishop_attacks(square, occupied) & bishops_queens)
     }
 
-    fn gen_queen_moves(&self, square: usize, is_white: bool) -> Vec<u16> {
-        // queen moves = bishop moves + rook moves
-        let mut moves = self.gen_bishop_moves(square, is_white);
-        moves.extend_from_slice(&self.gen_rook_moves(square, is_white));
-        moves
+    // every square attacked by the given side on the occupancy `occupied`
+    fn attacked_squares(&self, by_white: bool, occupied: u64) -> u64 {
+        let pieces = if by_white {
+            self.white_pieces
+        } else {
+            self.black_pieces
+        };
+        let mut attacked = 0;
+        let mut bb = pieces;
+        while bb != 0 {
+            let square = bb.trailing_zeros() as usize;
+            bb &= bb - 1;
+            attacked |= match self.piece_list[square] {
+                Piece::WhitePawn => self.white_pawn_attacks[square],
+                Piece::BlackPawn => self.black_pawn_attacks[square],
+                Piece::WhiteKnight | Piece::BlackKnight => self.knight_moves[square],
+                Piece::WhiteBishop | Piece::BlackBishop => self.bishop_attacks(square, occupied),
+                Piece::WhiteRook | Piece::BlackRook => self.rook_attacks(square, occupied),
+                Piece::WhiteQueen | Piece::BlackQueen => self.queen_attacks(square, occupied),
+                Piece::WhiteKing | Piece::BlackKing => self.king_moves[square],
+                Piece::Empty => 0,
+            };
+        }
+        attacked
     }
 
-    fn gen_king_moves(&self, square: usize, is_white: bool) -> Vec<u16> {
-        let mut king_moves = self.king_moves[square];
-        if is_white {
-            king_moves &= !self.white_pieces;
+    pub fn zobrist_hash(&self) -> u64 {
+        self.zobrist_hash
+    }
+
+    pub fn is_square_attacked(&self, square: usize, by_white: bool) -> bool {
+        let attackers = if by_white {
+            self.white_pieces
         } else {
-            king_moves &= !self.black_pieces;
-        }
+            self.black_pieces
+        };
+        self.attackers_to(square, self.occupied_squares) & attackers != 0
+    }
 
-        let mut moves = self.bitboard_to_moves(king_moves, square, 0);
+    // Static exchange evaluation: whether the legal move `mv` wins at least
+    // `threshold` centipawns once both sides have made all the captures on
+    // its target square that pay off, cheapest attacker first. Sliders behind
+    // a capturing piece join in as it leaves. Pins are not taken into
+    // account.
+    pub fn see(&self, mv: u16, threshold: i32) -> bool {
+        let from = (mv & 0x3F) as usize;
+        let to = ((mv >> 6) & 0x3F) as usize;
+        // castling moves nothing into reach of the opponent
+        if mv & 0xE000 == 0x2000 {
+            return threshold <= 0;
+        }
+        let mut occupied = (self.occupied_squares & !(1 << from)) | (1 << to);
+        let mut gain = PIECE_VALUES[self.piece_list[to] as usize];
+        if mv & 0xF000 == 0x5000 {
+            // en passant takes the pawn behind the target square
+            gain = PIECE_VALUES[Piece::WhitePawn as usize];
+            occupied &= !(1 << (to ^ 8));
+        }
+        let mut moved = PIECE_VALUES[self.piece_list[from] as usize];
+        if mv & 0x8000 != 0 {
+            moved = PIECE_VALUES[match mv & 0x3000 {
+                0x0000 => Piece::WhiteQueen,
+                0x1000 => Piece::WhiteRook,
+                0x2000 => Piece::WhiteBishop,
+                _ => Piece::WhiteKnight,
+            } as usize];
+            gain += moved - PIECE_VALUES[Piece::WhitePawn as usize];
+        }
 
-        // add castling moves
-        // TODO: we are not checking for check conditions here
-        if is_white && square == 4 {
-            // kingside castle
-            if self.piece_list[5] == Piece::Empty
-                && self.piece_list[6] == Piece::Empty
-                && self.piece_list[7] == Piece::WhiteRook
-            {
-                moves.push(self.encode_move(4, 6, 0x2000));
+        // The sides take turns capturing on `to`. `swap` is the margin by
+        // which the side to capture next falls short if it does not, and
+        // `result` is 1 while the moving side reaches the threshold. A side
+        // stops as soon as capturing cannot change the outcome.
+        let mut swap = gain - threshold;
+        if swap < 0 {
+            return false;
+        }
+        swap = moved - swap;
+        if swap <= 0 {
+            return true;
+        }
+        let mut white = (self.piece_list[from] as u8) <= Piece::WhitePawn as u8;
+        let mut attackers = self.attackers_to(to, occupied);
+        let mut result = 1;
+        loop {
+            white = !white;
+            attackers &= occupied;
+            let own_pieces = if white {
+                self.white_pieces
+            } else {
+                self.black_pieces
+            };
+            let own_attackers = attackers & own_pieces;
+            if own_attackers == 0 {
+                break;
             }
-            // queenside castle
-            if self.piece_list[3] == Piece::Empty
-                && self.piece_list[2] == Piece::Empty
-                && self.piece_list[1] == Piece::Empty
-                && self.piece_list[0] == Piece::WhiteRook
-            {
-                moves.push(self.encode_move(4, 2, 0x3000));
+            result ^= 1;
+            let (attacker, value) = [
+                (self.pawns, Piece::WhitePawn),
+                (self.knights, Piece::WhiteKnight),
+                (self.bishops, Piece::WhiteBishop),
+                (self.rooks, Piece::WhiteRook),
+                (self.queens, Piece::WhiteQueen),
+            ]
+            .into_iter()
+            .map(|(pieces, piece)| (pieces & own_attackers, PIECE_VALUES[piece as usize]))
+            .find(|&(attacker, _)| attacker != 0)
+            .unwrap_or((0, 0));
+            if attacker == 0 {
+                // the king may only capture if the opponent has no attackers left
+                return if attackers & !own_pieces != 0 {
+                    result ^ 1 != 0
+                } else {
+                    result != 0
+                };
             }
-        } else if !is_white && square == 60 {
-            // kingside castle
-            if self.piece_list[61] == Piece::Empty
-                && self.piece_list[62] == Piece::Empty
-                && self.piece_list[63] == Piece::BlackRook
-            {
-                moves.push(self.encode_move(60, 62, 0x2000));
+            swap = value - swap;
+            if swap < result {
+                break;
             }
-            // queenside castle
-            if self.piece_list[59] == Piece::Empty
-                && self.piece_list[58] == Piece::Empty
-                && self.piece_list[57] == Piece::Empty
-                && self.piece_list[56] == Piece::BlackRook
-            {
-                moves.push(self.encode_move(60, 58, 0x3000));
+            occupied ^= attacker & attacker.wrapping_neg();
+            // sliders behind the capturing piece
+            attackers |= self.bishop_attacks(to, occupied) & (self.bishops | self.queens);
+            attackers |= self.rook_attacks(to, occupied) & (self.rooks | self.queens);
+        }
+        result != 0
+    }
+
+    // Pieces of the given side, other than the king, that the opponent wins
+    // material from by capturing them.
+    pub fn hanging_pieces(&self, white: bool) -> u64 {
+        let (own_pieces, enemy_pieces) = if white {
+            (self.white_pieces, self.black_pieces)
+        } else {
+            (self.black_pieces, self.white_pieces)
+        };
+        let mut hanging = 0;
+        let mut pieces = own_pieces & !self.kings;
+        while pieces != 0 {
+            let square = pieces.trailing_zeros() as usize;
+            pieces &= pieces - 1;
+            let attackers = self.attackers_to(square, self.occupied_squares);
+            let enemy_attackers = attackers & enemy_pieces;
+            // the king can only take an undefended piece
+            let candidates = if attackers & own_pieces != 0 {
+                enemy_attackers & !self.kings
+            } else {
+                enemy_attackers
+            };
+            let Some(attacker) = [
+                self.pawns,
+                self.knights,
+                self.bishops,
+                self.rooks,
+                self.queens,
+            ]
+            .into_iter()
+            .chain([self.kings])
+            .map(|pieces| pieces & candidates)
+            .find(|&attacker| attacker != 0) else {
+                continue;
+            };
+            let capture = attacker.trailing_zeros() as u16 | (square as u16) << 6 | 0x4000;
+            if self.see(capture, 1) {
+                hanging |= 1 << square;
             }
         }
+        hanging
+    }
 
-        moves
+    pub fn king_square(&self, white: bool) -> Option<usize> {
+        let own_pieces = if white {
+            self.white_pieces
+        } else {
+            self.black_pieces
+        };
+        let king = self.kings & own_pieces;
+        if king == 0 {
+            None
+        } else {
+            Some(king.trailing_zeros() as usize)
+        }
+    }
+
+    // whether a pawn of the given side attacks the en passant square
+    pub fn can_capture_en_passant(&self, ep_square: usize, white: bool) -> bool {
+        let (attackers, own_pieces) = if white {
+            (self.black_pawn_attacks[ep_square], self.white_pieces)
+        } else {
+            (self.white_pawn_attacks[ep_square], self.black_pieces)
+        };
+        attackers & self.pawns & own_pieces != 0
+    }
+
+    // Neither side can checkmate by any sequence of legal moves: king against
+    // king with at most one minor piece, or only bishops on squares of one color.
+    pub fn is_insufficient_material(&self) -> bool {
+        if self.pawns | self.rooks | self.queens != 0 {
+            return false;
+        }
+        if (self.knights | self.bishops).count_ones() <= 1 {
+            return true;
+        }
+        const LIGHT_SQUARES: u64 = 0x55AA55AA55AA55AA;
+        self.knights == 0
+            && (self.bishops & LIGHT_SQUARES == 0 || self.bishops & !LIGHT_SQUARES == 0)
+    }
+
+    // number of pieces on the board, kings and pawns included
+    pub fn piece_count(&self) -> u32 {
+        self.occupied_squares.count_ones()
+    }
+
+    // without pieces besides pawns and king a side is prone to zugzwang
+    pub fn has_non_pawn_material(&self, white: bool) -> bool {
+        let own = if white {
+            self.white_pieces
+        } else {
+            self.black_pieces
+        };
+        own & (self.queens | self.rooks | self.bishops | self.knights) != 0
+    }
+
+    pub fn in_check(&self, white: bool) -> bool {
+        match self.king_square(white) {
+            Some(square) => self.is_square_attacked(square, !white),
+            None => false,
+        }
     }
 
     pub fn starting_position() -> Self {
@@ -418,16 +792,17 @@ impl Board {
         board
     }
 
-    pub fn from_fen(fen: &str) -> Result<Self, String> {
-        // Implementation to parse FEN string and initialize the board
+    // Parses the piece placement of a FEN string. Only the syntax is checked
+    // here, whether the position is legal is validated by `Game::from_fen`.
+    pub fn from_fen(fen: &str) -> Result<Self, FenError> {
         let parts: Vec<&str> = fen.split_whitespace().collect();
         if parts.len() != 6 {
-            return Err("Invalid FEN string".to_string());
+            return Err(FenError::WrongFieldCount(parts.len()));
         }
 
         let pieces: Vec<&str> = parts[0].split('/').collect();
         if pieces.len() != 8 {
-            return Err("Invalid FEN string".to_string());
+            return Err(FenError::WrongRankCount(pieces.len()));
         }
 
         // Initialize the board
@@ -435,23 +810,29 @@ impl Board {
 
         // Parse piece placement
         for (rank, piece_row) in pieces.iter().rev().enumerate() {
-            if piece_row.len() > 8 {
-                return Err("Invalid FEN string".to_string());
-            }
-            let mut square = rank * 8;
+            let mut file = 0;
+            let mut previous_was_digit = false;
             for piece in piece_row.chars() {
-                if piece.is_digit(10) {
-                    let empty_squares = piece.to_digit(10).unwrap() as usize;
-                    square += empty_squares;
+                if let Some(empty_squares) = piece.to_digit(10) {
+                    // "44" or "0" are not valid ways to describe empty squares
+                    if previous_was_digit || empty_squares == 0 || file + empty_squares > 8 {
+                        return Err(FenError::BadRankLength(rank + 1));
+                    }
+                    file += empty_squares;
+                    previous_was_digit = true;
                     continue;
                 }
+                previous_was_digit = false;
+                if file >= 8 {
+                    return Err(FenError::BadRankLength(rank + 1));
+                }
+                let square = rank * 8 + file as usize;
 
-                board.occupied_squares |= 1 << square;
                 // match color
                 match piece {
                     'R' | 'B' | 'N' | 'Q' | 'K' | 'P' => board.white_pieces |= 1 << square,
                     'r' | 'b' | 'n' | 'q' | 'k' | 'p' => board.black_pieces |= 1 << square,
-                    _ => return Err("Invalid FEN string".to_string()),
+                    _ => return Err(FenError::InvalidPiece(piece)),
                 }
                 // match piece type
                 match piece {
@@ -461,9 +842,13 @@ impl Board {
                     'Q' | 'q' => board.queens |= 1 << square,
                     'K' | 'k' => board.kings |= 1 << square,
                     'P' | 'p' => board.pawns |= 1 << square,
-                    _ => return Err("Invalid FEN string".to_string()),
+                    _ => return Err(FenError::InvalidPiece(piece)),
                 }
-                square += 1;
+                board.occupied_squares |= 1 << square;
+                file += 1;
+            }
+            if file != 8 {
+                return Err(FenError::BadRankLength(rank + 1));
             }
         }
         board.init_piece_list();
@@ -471,11 +856,71 @@ impl Board {
         Ok(board)
     }
 
+    // an ASCII diagram of the board with rank 8 on top
+    pub fn to_diagram(self) -> String {
+        let separator = " +---+---+---+---+---+---+---+---+\n";
+        let mut diagram = String::from(separator);
+        for rank in (0..8).rev() {
+            for file in 0..8 {
+                let piece = match self.piece_list[rank * 8 + file] {
+                    Piece::Empty => ' ',
+                    piece => piece_to_char(piece),
+                };
+                diagram.push_str(&format!(" | {}", piece));
+            }
+            diagram.push_str(&format!(" | {}\n", rank + 1));
+            diagram.push_str(separator);
+        }
+        diagram.push_str("   a   b   c   d   e   f   g   h\n");
+        diagram
+    }
+
+    // the piece placement field of a FEN string
+    pub fn to_fen(self) -> String {
+        let mut fen = String::new();
+        for rank in (0..8).rev() {
+            let mut empty_squares = 0;
+            for file in 0..8 {
+                let piece = match self.piece_list[rank * 8 + file] {
+                    Piece::Empty => {
+                        empty_squares += 1;
+                        continue;
+                    }
+                    piece => piece_to_char(piece),
+                };
+                if empty_squares > 0 {
+                    fen.push_str(&empty_squares.to_string());
+                    empty_squares = 0;
+                }
+                fen.push(piece);
+            }
+            if empty_squares > 0 {
+                fen.push_str(&empty_squares.to_string());
+            }
+            if rank > 0 {
+                fen.push('/');
+            }
+        }
+        fen
+    }
+
     pub fn make_move(&mut self, mv: u16) -> Piece {
         let (from, to, promotion, en_passant, castle) = self.decode_move(mv);
+        if castle {
+            // in Chess960 the king may stay where it is or end up on the rook's
+            // square, so both are taken off the board before they are put back
+            let (rook_from, rook_to) = self.castling_rook_squares(mv);
+            let king = self.piece_list[from];
+            let rook = self.piece_list[rook_from];
+            self.remove_piece(from);
+            self.remove_piece(rook_from);
+            self.add_piece(to, king);
+            self.add_piece(rook_to, rook);
+            return Piece::Empty;
+        }
 
-        let moving_piece = self.piece_list[from as usize];
-        let captured_piece = self.piece_list[to as usize];
+        let moving_piece = self.piece_list[from];
+        let captured_piece = self.piece_list[to];
 
         // Remove piece from 'from' square
         self.remove_piece(from);
@@ -503,8 +948,8 @@ impl Board {
                 match promoted_piece {
                     0 => Piece::WhiteQueen,
                     1 => Piece::WhiteRook,
-                    2 => Piece::WhiteKnight,
-                    3 => Piece::WhiteBishop,
+                    2 => Piece::WhiteBishop,
+                    3 => Piece::WhiteKnight,
                     _ => Piece::Empty,
                 }
             } else {
@@ -512,8 +957,8 @@ impl Board {
                 match promoted_piece {
                     0 => Piece::BlackQueen,
                     1 => Piece::BlackRook,
-                    2 => Piece::BlackKnight,
-                    3 => Piece::BlackBishop,
+                    2 => Piece::BlackBishop,
+                    3 => Piece::BlackKnight,
                     _ => Piece::Empty,
                 }
             }
@@ -522,46 +967,30 @@ impl Board {
         };
         self.add_piece(to, moving_piece);
 
-        // Handle castling - move the rook as well
-        if castle {
-            match to {
-                2 => {
-                    // white queenside
-                    self.remove_piece(0);
-                    self.add_piece(3, Piece::WhiteRook);
-                }
-                6 => {
-                    // white kingside
-                    self.remove_piece(7);
-                    self.add_piece(5, Piece::WhiteRook);
-                }
-                58 => {
-                    // black queenside
-                    self.remove_piece(56);
-                    self.add_piece(59, Piece::BlackRook);
-                }
-                62 => {
-                    // black kingside
-                    self.remove_piece(63);
-                    self.add_piece(61, Piece::BlackRook);
-                }
-                _ => {}
-            }
-        }
-
         captured_piece
     }
 
     pub fn undo_move(&mut self, mv: u16, captured_piece: Piece) {
         let (from, to, promotion, en_passant, castle) = self.decode_move(mv);
-        let moving_piece = if promotion != None {
+        if castle {
+            let (rook_from, rook_to) = self.castling_rook_squares(mv);
+            let king = self.piece_list[to];
+            let rook = self.piece_list[rook_to];
+            self.remove_piece(to);
+            self.remove_piece(rook_to);
+            self.add_piece(from, king);
+            self.add_piece(rook_from, rook);
+            return;
+        }
+
+        let moving_piece = if promotion.is_some() {
             if to >= 56 {
                 Piece::WhitePawn
             } else {
                 Piece::BlackPawn
             }
         } else {
-            self.piece_list[to as usize]
+            self.piece_list[to]
         };
 
         // Add piece back to 'from' square
@@ -589,61 +1018,153 @@ impl Board {
             };
             self.add_piece(ep_capture_square, captured_pawn);
         }
+    }
 
-        // Handle castling - move the rook back as well
-        if castle {
-            match to {
-                2 => {
-                    // white queenside
-                    self.remove_piece(3);
-                    self.add_piece(0, Piece::WhiteRook);
+    // Generates all legal moves for the side to move. Castling rights and the
+    // en passant square are part of the game state, so they are passed in.
+    pub fn generate_legal_moves(
+        &self,
+        white_to_move: bool,
+        castling_rights: u8,
+        en_passant_square: Option<u8>,
+    ) -> Vec<u16> {
+        self.generate_moves(white_to_move, castling_rights, en_passant_square, false)
+    }
+
+    // Generates only the legal captures (including en passant) and promotions,
+    // used by the quiescence search.
+    pub fn generate_captures(
+        &self,
+        white_to_move: bool,
+        en_passant_square: Option<u8>,
+    ) -> Vec<u16> {
+        self.generate_moves(white_to_move, 0, en_passant_square, true)
+    }
+
+    fn generate_moves(
+        &self,
+        white_to_move: bool,
+        castling_rights: u8,
+        en_passant_square: Option<u8>,
+        captures_only: bool,
+    ) -> Vec<u16> {
+        let (own_pieces, enemy_pieces) = if white_to_move {
+            (self.white_pieces, self.black_pieces)
+        } else {
+            (self.black_pieces, self.white_pieces)
+        };
+        let king_square = match self.king_square(white_to_move) {
+            Some(square) => square,
+            None => return Vec::new(),
+        };
+
+        // king moves are checked against the full attack map of the opponent
+        let attacked =
+            self.attacked_squares(!white_to_move, self.occupied_squares & !(1 << king_square));
+        let mut moves = self.gen_king_moves(
+            king_square,
+            white_to_move,
+            attacked,
+            castling_rights,
+            captures_only,
+        );
+
+        let checkers = self.attackers_to(king_square, self.occupied_squares) & enemy_pieces;
+        if checkers.count_ones() > 1 {
+            // double check, only the king can move
+            return moves;
+        }
+
+        // in check, other pieces have to capture the checker or block the ray
+        let check_mask = if checkers != 0 {
+            let checker = checkers.trailing_zeros() as usize;
+            checkers | self.between(king_square, checker)
+        } else {
+            !0
+        };
+
+        // find pinned pieces: enemy sliders that would attack our king if exactly
+        // one of our pieces was removed from the ray between them
+        let mut pin_masks = [!0u64; 64];
+        let mut snipers = (self.rook_attacks(king_square, 0) & (self.rooks | self.queens)
+            | self.bishop_attacks(king_square, 0) & (self.bishops | self.queens))
+            & enemy_pieces;
+        while snipers != 0 {
+            let sniper = snipers.trailing_zeros() as usize;
+            snipers &= snipers - 1;
+            let ray = self.between(king_square, sniper);
+            let blockers = ray & self.occupied_squares;
+            if blockers.count_ones() == 1 && blockers & own_pieces != 0 {
+                pin_masks[blockers.trailing_zeros() as usize] = ray | (1 << sniper);
+            }
+        }
+
+        let mut pieces = own_pieces & !self.kings;
+        while pieces != 0 {
+            let square = pieces.trailing_zeros() as usize;
+            pieces &= pieces - 1;
+            let mask = check_mask & pin_masks[square];
+            // pawns decide themselves which pushes to keep, for all other pieces
+            // a capture is a move to a square occupied by the opponent
+            let piece_mask = if captures_only {
+                mask & enemy_pieces
+            } else {
+                mask
+            };
+            let piece_moves = match self.piece_list[square] {
+                Piece::WhitePawn | Piece::BlackPawn => {
+                    self.gen_pawn_moves(square, white_to_move, mask, captures_only)
                 }
-                6 => {
-                    // white kingside
-                    self.remove_piece(5);
-                    self.add_piece(7, Piece::WhiteRook);
+                Piece::WhiteKnight | Piece::BlackKnight => {
+                    self.gen_knight_moves(square, white_to_move, piece_mask)
                 }
-                58 => {
-                    // black queenside
-                    self.remove_piece(59);
-                    self.add_piece(56, Piece::BlackRook);
+                Piece::WhiteBishop | Piece::BlackBishop => {
+                    self.gen_bishop_moves(square, white_to_move, piece_mask)
                 }
-                62 => {
-                    // black kingside
-                    self.remove_piece(61);
-                    self.add_piece(63, Piece::BlackRook);
+                Piece::WhiteRook | Piece::BlackRook => {
+                    self.gen_rook_moves(square, white_to_move, piece_mask)
+                }
+                Piece::WhiteQueen | Piece::BlackQueen => {
+                    self.gen_queen_moves(square, white_to_move, piece_mask)
                 }
-                _ => {}
-            }
-        }
-    }
-
-    pub fn generate_legal_moves(&self, white_to_move: bool) -> Vec<u16> {
-        let mut moves: Vec<u16> = Vec::new();
-        for square in 0..64 {
-            let piece = self.piece_list[square];
-            if piece == Piece::Empty {
-                continue;
-            }
-            let is_white = piece as u8 <= Piece::WhitePawn as u8;
-            if is_white != white_to_move {
-                continue;
-            }
-            let piece_moves = match piece {
-                Piece::WhitePawn | Piece::BlackPawn => self.gen_pawn_moves(square, is_white),
-                Piece::WhiteKnight | Piece::BlackKnight => self.gen_knight_moves(square, is_white),
-                Piece::WhiteBishop | Piece::BlackBishop => self.gen_bishop_moves(square, is_white),
-                Piece::WhiteRook | Piece::BlackRook => self.gen_rook_moves(square, is_white),
-                Piece::WhiteQueen | Piece::BlackQueen => self.gen_queen_moves(square, is_white),
-                Piece::WhiteKing | Piece::BlackKing => self.gen_king_moves(square, is_white),
                 _ => Vec::new(),
             };
             moves.extend_from_slice(&piece_moves);
         }
+
+        if let Some(ep_square) = en_passant_square {
+            moves.extend_from_slice(&self.gen_en_passant_moves(
+                ep_square as usize,
+                white_to_move,
+                king_square,
+            ));
+        }
+
         moves
     }
 }
 
+// the letter of the piece as used in FEN strings
+fn piece_to_char(piece: Piece) -> char {
+    match piece {
+        Piece::Empty => '.',
+        Piece::WhiteKing => 'K',
+        Piece::WhiteQueen => 'Q',
+        Piece::WhiteRook => 'R',
+        Piece::WhiteBishop => 'B',
+        Piece::WhiteKnight => 'N',
+        Piece::WhitePawn => 'P',
+        Piece::BlackKing => 'k',
+        Piece::BlackQueen => 'q',
+        Piece::BlackRook => 'r',
+        Piece::BlackBishop => 'b',
+        Piece::BlackKnight => 'n',
+        Piece::BlackPawn => 'p',
+    }
+}
+
+// debugging aid, the engine itself does not use it
+#[allow(dead_code)]
 pub fn print_bitboard(bitboard: u64) -> String {
     let mut board = String::new();
     for rank in (0..8).rev() {
@@ -661,6 +1182,7 @@ pub fn print_bitboard(bitboard: u64) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::game::Game;
 
     #[test]
     fn test_starting_position() {
@@ -888,17 +1410,173 @@ mod tests {
     #[test]
     fn test_generate_legal_moves() {
         let board = Board::starting_position();
-        let moves = board.generate_legal_moves(true);
+        let moves = board.generate_legal_moves(true, 0x0F, None);
         assert_eq!(moves.len(), 20);
     }
 
     #[test]
     fn test_generate_legal_moves_black() {
         let board = Board::starting_position();
-        let moves = board.generate_legal_moves(false);
+        let moves = board.generate_legal_moves(false, 0x0F, None);
         assert_eq!(moves.len(), 20);
     }
 
+    #[test]
+    fn test_generate_legal_moves_check_evasion() {
+        // black rook on e8 gives check, white can only block, capture or move the king
+        let board = Board::from_fen("4r2k/8/8/8/8/8/3B4/R3K3 w Q - 0 1").unwrap();
+        let moves = board.generate_legal_moves(true, 0x02, None);
+        // Kd1, Kf1, Kf2 and the block Bd2-e3, no castling out of check
+        assert_eq!(moves.len(), 4);
+        assert!(!moves.contains(&(0x3000 | (2 << 6) | 4)));
+    }
+
+    #[test]
+    fn test_generate_legal_moves_pinned_piece() {
+        // the white knight on e2 is pinned by the rook on e8 and cannot move
+        let board = Board::from_fen("4r2k/8/8/8/8/8/4N3/4K3 w - - 0 1").unwrap();
+        let moves = board.generate_legal_moves(true, 0, None);
+        assert!(moves.iter().all(|mv| mv & 0x3F != 12));
+        assert_eq!(moves.len(), 4);
+    }
+
+    #[test]
+    fn test_generate_legal_moves_castling_through_check() {
+        // the bishop on c4 attacks f1, so white may only castle queenside
+        let board = Board::from_fen("r3k2r/8/8/8/2b5/8/8/R3K2R w KQkq - 0 1").unwrap();
+        let moves = board.generate_legal_moves(true, 0x0F, None);
+        assert!(!moves.contains(&(0x2000 | (6 << 6) | 4)));
+        assert!(moves.contains(&(0x3000 | (2 << 6) | 4)));
+    }
+
+    #[test]
+    fn test_generate_legal_moves_en_passant_discovered_check() {
+        // capturing en passant would expose the white king on a5 to the rook on h5
+        let board = Board::from_fen("8/8/8/KPp4r/8/8/8/7k w - c6 0 1").unwrap();
+        let moves = board.generate_legal_moves(true, 0, Some(42));
+        assert!(!moves.contains(&(0x5000 | (42 << 6) | 33)));
+    }
+
+    #[test]
+    fn test_generate_legal_moves_en_passant() {
+        let board = Board::from_fen("4k3/8/8/KPp5/8/8/8/8 w - c6 0 1").unwrap();
+        let moves = board.generate_legal_moves(true, 0, Some(42));
+        assert!(moves.contains(&(0x5000 | (42 << 6) | 33)));
+    }
+
+    #[test]
+    fn test_generate_captures() {
+        // Kiwipete has 8 captures, none of them promotions
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let board = Board::from_fen(fen).unwrap();
+        let captures = board.generate_captures(true, None);
+        assert_eq!(captures.len(), 8);
+        assert!(captures.iter().all(|mv| mv & 0x4000 != 0));
+    }
+
+    #[test]
+    fn test_generate_captures_promotions_and_en_passant() {
+        // quiet promotion b7b8, capture promotion b7xa8 and en passant e5xd6
+        let board = Board::from_fen("r3k3/1P6/8/3pP3/8/8/8/4K3 w - d6 0 1").unwrap();
+        let captures = board.generate_captures(true, Some(43));
+        assert_eq!(captures.len(), 9);
+        assert!(captures.contains(&(0x5000 | (43 << 6) | 36)));
+        assert!(captures.contains(&(0x8000 | (57 << 6) | 49)));
+        assert!(!captures.iter().any(|mv| mv & 0xF000 == 0));
+    }
+
+    #[test]
+    fn test_see() {
+        // position, move and the material it wins after the exchange
+        let cases = [
+            (
+                "1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1",
+                "e1e5",
+                100,
+            ),
+            (
+                "1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1",
+                "d3e5",
+                -220,
+            ),
+            // the rook on d1 recaptures through the one on d2
+            ("4k3/8/2p5/3p4/8/8/3R4/3RK3 w - - 0 1", "d2d5", -300),
+            // the queen recaptures through the bishop
+            ("4k3/8/5p2/4p3/3B4/2Q5/8/4K3 w - - 0 1", "d4e5", -130),
+            ("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6", 100),
+            // the king cannot take a defended rook
+            ("8/8/3k4/3p4/8/8/3R4/3RK3 w - - 0 1", "d2d5", 100),
+            ("8/8/3k4/3p4/8/8/3R4/4K3 w - - 0 1", "d2d5", -400),
+            ("4k3/8/8/3p4/8/8/3N4/4K3 w - - 0 1", "d2c4", -320),
+            ("4k3/8/8/3p4/8/8/3N4/4K3 w - - 0 1", "d2b3", 0),
+            ("r3k3/1P6/1n6/8/8/8/8/4K3 w - - 0 1", "b7a8q", 400),
+            ("4k3/8/8/8/8/8/8/4K2R w K - 0 1", "e1g1", 0),
+            // black to move
+            ("4k3/8/8/3q4/4P3/8/8/4K3 b - - 0 1", "d5e4", 100),
+            ("4k3/8/8/3q4/4P3/3P4/8/4K3 b - - 0 1", "d5e4", -800),
+        ];
+        for (fen, uci, value) in cases {
+            let game = Game::from_fen(fen).unwrap();
+            let mv = game.parse_uci(uci).unwrap().raw();
+            assert!(game.board.see(mv, value), "{} {}", fen, uci);
+            assert!(!game.board.see(mv, value + 1), "{} {}", fen, uci);
+        }
+    }
+
+    #[test]
+    fn test_has_non_pawn_material() {
+        let board = Board::from_fen("4k3/pp6/8/8/8/8/5PPP/4KN2 w - - 0 1").unwrap();
+        assert!(board.has_non_pawn_material(true));
+        assert!(!board.has_non_pawn_material(false));
+    }
+
+    #[test]
+    fn test_hanging_pieces() {
+        // the knight is attacked by a pawn, the pawn is defended
+        let board = Board::from_fen("4k3/8/4p3/3p4/2N5/8/3R4/3K4 w - - 0 1").unwrap();
+        assert_eq!(board.hanging_pieces(true), 1 << 26);
+        assert_eq!(board.hanging_pieces(false), 0);
+        // the king can only take the rook while it is not defended
+        let board = Board::from_fen("8/8/8/8/8/8/2kR4/7K w - - 0 1").unwrap();
+        assert_eq!(board.hanging_pieces(true), 1 << 11);
+        let board = Board::from_fen("8/8/8/8/8/8/2kR4/4K3 w - - 0 1").unwrap();
+        assert_eq!(board.hanging_pieces(true), 0);
+    }
+
+    #[test]
+    fn test_is_insufficient_material() {
+        let dead = [
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4KN2 w - - 0 1",
+            "4kb2/8/8/8/8/8/8/4K3 w - - 0 1",
+            // bishops on light squares only
+            "4k3/8/8/8/8/8/8/3BKB2 w - - 0 1",
+            "2b1k3/8/8/8/8/8/8/4KB2 w - - 0 1",
+        ];
+        for fen in dead {
+            assert!(
+                Board::from_fen(fen).unwrap().is_insufficient_material(),
+                "{}",
+                fen
+            );
+        }
+        let alive = [
+            "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4KNN1 w - - 0 1",
+            "4kn2/8/8/8/8/8/8/4KB2 w - - 0 1",
+            // bishops on opposite colors
+            "3bk3/8/8/8/8/8/8/4KB2 w - - 0 1",
+            "4k3/8/8/8/8/8/8/R3K3 w - - 0 1",
+        ];
+        for fen in alive {
+            assert!(
+                !Board::from_fen(fen).unwrap().is_insufficient_material(),
+                "{}",
+                fen
+            );
+        }
+    }
+
     #[test]
     fn test_print_bitboard() {
         let bitboard: u64 = 0xFFEF00101000FFEF;
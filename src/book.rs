@@ -0,0 +1,215 @@
+use crate::game::Game;
+use crate::polyglot::{decode_move, polyglot_key};
+use crate::types::Move;
+use rand::Rng;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// size of an entry in a Polyglot .bin file
+const ENTRY_SIZE: usize = 16;
+
+// An entry of a Polyglot book, stored big-endian in this field order. The
+// move uses the Polyglot encoding, see `polyglot::decode_move`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct BookEntry {
+    pub key: u64,
+    pub mv: u16,
+    pub weight: u16,
+    pub learn: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BookSelection {
+    // the move with the highest weight
+    Best,
+    // a random move, chosen with probability proportional to its weight
+    Weighted,
+}
+
+// An opening book in the Polyglot format.
+pub struct Book {
+    // sorted by key
+    entries: Vec<BookEntry>,
+}
+
+impl Book {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Book> {
+        let bytes = fs::read(path)?;
+        if bytes.len() % ENTRY_SIZE != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "file size is not a multiple of the entry size",
+            ));
+        }
+        Ok(Book::from_bytes(&bytes))
+    }
+
+    // a trailing incomplete entry is ignored
+    pub fn from_bytes(bytes: &[u8]) -> Book {
+        let mut entries: Vec<BookEntry> = bytes
+            .chunks_exact(ENTRY_SIZE)
+            .map(|entry| BookEntry {
+                key: u64::from_be_bytes(entry[0..8].try_into().unwrap()),
+                mv: u16::from_be_bytes(entry[8..10].try_into().unwrap()),
+                weight: u16::from_be_bytes(entry[10..12].try_into().unwrap()),
+                learn: u32::from_be_bytes(entry[12..16].try_into().unwrap()),
+            })
+            .collect();
+        // books are sorted by the tools that write them, but lookups rely on it
+        entries.sort_by_key(|entry| entry.key);
+        Book { entries }
+    }
+
+    // Entries for the same position are ordered by weight, best first, as
+    // Polyglot tools expect.
+    pub(crate) fn from_entries(mut entries: Vec<BookEntry>) -> Book {
+        entries.sort_by(|a, b| a.key.cmp(&b.key).then(b.weight.cmp(&a.weight)));
+        Book { entries }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.entries.len() * ENTRY_SIZE);
+        for entry in &self.entries {
+            bytes.extend_from_slice(&entry.key.to_be_bytes());
+            bytes.extend_from_slice(&entry.mv.to_be_bytes());
+            bytes.extend_from_slice(&entry.weight.to_be_bytes());
+            bytes.extend_from_slice(&entry.learn.to_be_bytes());
+        }
+        bytes
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub(crate) fn entries(&self, key: u64) -> &[BookEntry] {
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        let end = self.entries.partition_point(|entry| entry.key <= key);
+        &self.entries[start..end]
+    }
+
+    // The legal book moves in the position with their weights. Moves with
+    // weight 0 are in the book but should not be played.
+    pub fn moves(&self, game: &Game) -> Vec<(Move, u16)> {
+        self.entries(polyglot_key(game))
+            .iter()
+            .filter_map(|entry| {
+                let mv = decode_move(game, entry.mv)?;
+                Some((Move::from_raw(mv), entry.weight))
+            })
+            .collect()
+    }
+
+    pub fn probe(&self, game: &Game, selection: BookSelection) -> Option<Move> {
+        let moves: Vec<(Move, u16)> = self
+            .moves(game)
+            .into_iter()
+            .filter(|&(_, weight)| weight > 0)
+            .collect();
+        match selection {
+            BookSelection::Best => moves
+                .iter()
+                .max_by_key(|&&(_, weight)| weight)
+                .map(|&(mv, _)| mv),
+            BookSelection::Weighted => {
+                let total: u32 = moves.iter().map(|&(_, weight)| weight as u32).sum();
+                if total == 0 {
+                    return None;
+                }
+                let mut choice = rand::rng().random_range(0..total);
+                moves.into_iter().find_map(|(mv, weight)| {
+                    if choice < weight as u32 {
+                        Some(mv)
+                    } else {
+                        choice -= weight as u32;
+                        None
+                    }
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::polyglot::encode_move;
+
+    fn find_move(game: &Game, uci: &str) -> Move {
+        game.parse_uci(uci).unwrap()
+    }
+
+    fn start_book() -> Book {
+        let game = Game::new();
+        let key = polyglot_key(&game);
+        let entry = |key, mv, weight| BookEntry {
+            key,
+            mv,
+            weight,
+            learn: 0,
+        };
+        let mut entries = vec![entry(key + 1, 0, 10), entry(key - 1, 0, 10)];
+        for (uci, weight) in [("d2d4", 10), ("e2e4", 30), ("g1f3", 0)] {
+            entries.push(entry(
+                key,
+                encode_move(&game, find_move(&game, uci).raw()),
+                weight,
+            ));
+        }
+        // through the file format
+        Book::from_bytes(&Book::from_entries(entries).to_bytes())
+    }
+
+    #[test]
+    fn test_book_lookup() {
+        let book = start_book();
+        assert_eq!(book.len(), 5);
+        let game = Game::new();
+        let moves: Vec<(String, u16)> = book
+            .moves(&game)
+            .into_iter()
+            .map(|(mv, weight)| (mv.to_string(), weight))
+            .collect();
+        assert_eq!(
+            moves,
+            vec![
+                ("e2e4".to_string(), 30),
+                ("d2d4".to_string(), 10),
+                ("g1f3".to_string(), 0)
+            ]
+        );
+        assert_eq!(
+            book.probe(&game, BookSelection::Best),
+            Some(find_move(&game, "e2e4"))
+        );
+        // out of book
+        let game = Game::from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+        assert_eq!(book.probe(&game, BookSelection::Best), None);
+    }
+
+    #[test]
+    fn test_weighted_selection() {
+        let book = start_book();
+        let game = Game::new();
+        let (e4, d4) = (find_move(&game, "e2e4"), find_move(&game, "d2d4"));
+        let mut e4_count = 0;
+        for _ in 0..400 {
+            let mv = book.probe(&game, BookSelection::Weighted).unwrap();
+            assert!(mv == e4 || mv == d4, "moves with weight 0 are not played");
+            if mv == e4 {
+                e4_count += 1;
+            }
+        }
+        // expected 300
+        assert!((240..=360).contains(&e4_count), "{}", e4_count);
+    }
+}
@@ -0,0 +1,315 @@
+use crate::book::{Book, BookEntry};
+use crate::game::Game;
+use crate::game_result::GameResult;
+use crate::pgn::{PgnGame, read_pgn};
+use crate::polyglot::{encode_move, polyglot_key};
+use crate::types::Move;
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BookOptions {
+    // only the first plies of each game are added
+    pub max_ply: usize,
+    // moves of players without a rating tag of at least this are skipped
+    pub min_rating: Option<u32>,
+    // skip the moves of the side that lost the game
+    pub exclude_losses: bool,
+    // moves played in fewer games are left out of the book
+    pub min_games: u32,
+}
+
+impl Default for BookOptions {
+    fn default() -> Self {
+        BookOptions {
+            max_ply: 30,
+            min_rating: None,
+            exclude_losses: false,
+            min_games: 1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MoveStats {
+    pub games: u32,
+    // 2 points per win and 1 per draw for the side that made the move
+    pub score: u32,
+}
+
+// Collects move statistics from games and turns them into a Polyglot book.
+pub struct BookBuilder {
+    options: BookOptions,
+    // by position key and Polyglot move
+    stats: HashMap<(u64, u16), MoveStats>,
+    games: usize,
+}
+
+impl BookBuilder {
+    pub fn new(options: BookOptions) -> BookBuilder {
+        BookBuilder {
+            options,
+            stats: HashMap::new(),
+            games: 0,
+        }
+    }
+
+    // number of games that contributed moves
+    pub fn games(&self) -> usize {
+        self.games
+    }
+
+    pub fn stats(&self, game: &Game, mv: Move) -> Option<MoveStats> {
+        self.stats
+            .get(&(polyglot_key(game), encode_move(game, mv.raw())))
+            .copied()
+    }
+
+    // Adds the main lines of all games in the PGN text. Returns how many of
+    // them were used and how many were skipped because they are malformed.
+    pub fn add_pgn(&mut self, text: &str) -> (usize, usize) {
+        let (mut used, mut skipped) = (0, 0);
+        for game in read_pgn(text) {
+            match game {
+                Ok(game) => used += self.add_game(&game) as usize,
+                Err(_) => skipped += 1,
+            }
+        }
+        (used, skipped)
+    }
+
+    // Unfinished games are skipped, they cannot be scored.
+    pub fn add_game(&mut self, pgn: &PgnGame) -> bool {
+        let Some(result) = pgn.result else {
+            return false;
+        };
+        let rated = |tag| {
+            self.options.min_rating.is_none_or(|min_rating| {
+                pgn.tag(tag)
+                    .and_then(|rating| rating.parse::<u32>().ok())
+                    .is_some_and(|rating| rating >= min_rating)
+            })
+        };
+        let white_rated = rated("WhiteElo");
+        let black_rated = rated("BlackElo");
+
+        let mut game = pgn.start.clone();
+        let mut used = false;
+        for node in pgn.mainline.moves.iter().take(self.options.max_ply) {
+            let white = game.history.current_state().white_to_move;
+            let score = match (result, white) {
+                (GameResult::Draw, _) => 1,
+                (GameResult::WhiteWins, true) | (GameResult::BlackWins, false) => 2,
+                _ => 0,
+            };
+            let rated = if white { white_rated } else { black_rated };
+            let lost = score == 0 && self.options.exclude_losses;
+            if rated && !lost {
+                let stats = self
+                    .stats
+                    .entry((polyglot_key(&game), encode_move(&game, node.mv.raw())))
+                    .or_default();
+                stats.games += 1;
+                stats.score += score;
+                used = true;
+            }
+            game.make_move(node.mv.raw());
+        }
+        if used {
+            self.games += 1;
+        }
+        used
+    }
+
+    // The weight of a move is its score plus the number of games it was
+    // played in, so a move that only lost still gets a small weight. Those
+    // moves are not recorded at all with `exclude_losses`. Weights are scaled
+    // down together if the largest does not fit.
+    pub fn build(&self) -> Book {
+        let weight = |stats: &MoveStats| stats.score as u64 + stats.games as u64;
+        let max_weight = self.stats.values().map(weight).max().unwrap_or(0);
+        let scale = (max_weight as f64 / u16::MAX as f64).max(1.0);
+        let entries = self
+            .stats
+            .iter()
+            .filter(|(_, stats)| stats.games >= self.options.min_games)
+            .map(|(&(key, mv), stats)| BookEntry {
+                key,
+                mv,
+                weight: ((weight(stats) as f64 / scale) as u16).max(1),
+                learn: 0,
+            })
+            .collect();
+        Book::from_entries(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GAMES: &str = r#"[White "A"]
+[Black "B"]
+[WhiteElo "2500"]
+[BlackElo "2100"]
+[Result "1-0"]
+
+1. e4 e5 2. Nf3 Nc6 1-0
+
+[White "C"]
+[Black "D"]
+[WhiteElo "2400"]
+[BlackElo "2450"]
+[Result "1/2-1/2"]
+
+1. e4 c5 2. Nf3 1/2-1/2
+
+[White "E"]
+[Black "F"]
+[Result "0-1"]
+
+1. d4 d5 0-1
+
+[Result "*"]
+
+1. c4 *
+"#;
+
+    fn book_moves(book: &Book, game: &Game) -> Vec<(String, u16)> {
+        book.moves(game)
+            .into_iter()
+            .map(|(mv, weight)| (mv.to_string(), weight))
+            .collect()
+    }
+
+    fn after(moves: &str) -> Game {
+        let mut game = Game::new();
+        for san in moves.split_whitespace() {
+            let mv = game.parse_san(san).unwrap();
+            game.make_move(mv.raw());
+        }
+        game
+    }
+
+    #[test]
+    fn test_build_book() {
+        let mut builder = BookBuilder::new(BookOptions::default());
+        assert_eq!(builder.add_pgn(GAMES), (3, 0));
+        let book = builder.build();
+        let start = Game::new();
+        // e4 won once and drew once, d4 lost and c4 is from an unfinished game
+        assert_eq!(
+            book_moves(&book, &start),
+            vec![("e2e4".to_string(), 5), ("d2d4".to_string(), 1)]
+        );
+        let e4 = start.parse_san("e4").unwrap();
+        assert_eq!(
+            builder.stats(&start, e4),
+            Some(MoveStats { games: 2, score: 3 })
+        );
+        assert_eq!(
+            book_moves(&book, &after("e4")),
+            vec![("c7c5".to_string(), 2), ("e7e5".to_string(), 1)]
+        );
+        assert_eq!(
+            book_moves(&book, &after("d4")),
+            vec![("d7d5".to_string(), 3)]
+        );
+    }
+
+    #[test]
+    fn test_exclude_losses() {
+        let mut builder = BookBuilder::new(BookOptions {
+            exclude_losses: true,
+            ..BookOptions::default()
+        });
+        assert_eq!(builder.add_pgn(GAMES), (3, 0));
+        let book = builder.build();
+        // only the moves of the losing sides are gone
+        let start = Game::new();
+        assert_eq!(book_moves(&book, &start), vec![("e2e4".to_string(), 5)]);
+        assert_eq!(
+            book_moves(&book, &after("e4")),
+            vec![("c7c5".to_string(), 2)]
+        );
+        assert_eq!(
+            book_moves(&book, &after("d4")),
+            vec![("d7d5".to_string(), 3)]
+        );
+    }
+
+    #[test]
+    fn test_weight_scaling() {
+        let mut builder = BookBuilder::new(BookOptions::default());
+        let start = Game::new();
+        let e4 = start.parse_san("e4").unwrap();
+        let d4 = start.parse_san("d4").unwrap();
+        let key = polyglot_key(&start);
+        builder.stats.insert(
+            (key, encode_move(&start, e4.raw())),
+            MoveStats {
+                games: 100_000,
+                score: 200_000,
+            },
+        );
+        builder.stats.insert(
+            (key, encode_move(&start, d4.raw())),
+            MoveStats { games: 1, score: 0 },
+        );
+        // the weights keep their ratio and the lost move stays in the book
+        assert_eq!(
+            book_moves(&builder.build(), &start),
+            vec![("e2e4".to_string(), 65535), ("d2d4".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn test_skip_malformed_games() {
+        let text = format!(
+            "{}\n[Result \"1-0\"]\n\n1. e4 e4 1-0\n\n[Result \"1-0\"]\n\n1. e4 e5 1-0\n",
+            GAMES
+        );
+        let mut builder = BookBuilder::new(BookOptions::default());
+        assert_eq!(builder.add_pgn(&text), (4, 1));
+        assert_eq!(builder.games(), 4);
+        // the malformed game adds none of its moves, not even the legal ones
+        let start = Game::new();
+        assert_eq!(
+            builder.stats(&start, start.parse_san("e4").unwrap()),
+            Some(MoveStats { games: 3, score: 5 })
+        );
+    }
+
+    #[test]
+    fn test_book_filters() {
+        let options = BookOptions {
+            max_ply: 2,
+            min_rating: Some(2200),
+            exclude_losses: true,
+            min_games: 1,
+        };
+        let mut builder = BookBuilder::new(options);
+        assert_eq!(builder.add_pgn(GAMES), (2, 0));
+        let book = builder.build();
+        let start = Game::new();
+        let e4 = start.parse_san("e4").unwrap();
+        assert_eq!(
+            builder.stats(&start, e4),
+            Some(MoveStats { games: 2, score: 3 })
+        );
+        // the first game's black player is rated too low, the third game has
+        // no ratings
+        let e5 = after("e4").parse_san("e5").unwrap();
+        assert_eq!(builder.stats(&after("e4"), e5), None);
+        assert_eq!(book_moves(&book, &after("d4")), vec![]);
+        // beyond the maximum ply
+        assert_eq!(book_moves(&book, &after("e4 c5")), vec![]);
+
+        let mut builder = BookBuilder::new(BookOptions {
+            min_games: 2,
+            ..BookOptions::default()
+        });
+        builder.add_pgn(GAMES);
+        // only 1. e4 was played in two games
+        assert_eq!(builder.build().len(), 1);
+    }
+}
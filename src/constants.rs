@@ -15,4 +15,4 @@ pub enum Piece {
     BlackPawn = 12,
 }
 
-pub const MAX_GAME_HISTORY_LENGTH: usize = 1024;
+pub const MAX_PLY: usize = 128;
@@ -1,42 +1,238 @@
-use dfdx::losses::{binary_cross_entropy_with_logits_loss, cross_entropy_with_logits_loss};
-use dfdx::nn::modules::{Conv2D, Cpu, Flatten2D, Linear, ReLU, Sigmoid};
-use dfdx::optim::Adam;
-
-// random model architecture
-type Model = (
-    (Conv2D<12, 64, 3>, ReLU),
-    (Conv2D<64, 64, 3>, ReLU),
-    (Conv2D<64, 64, 3>, ReLU),
-    (Conv2D<64, 64, 3>, ReLU),
-    Flatten2D,
-    Linear<4096, 3>, // 8*8*64
-);
-
-struct PositionEvaluator {
-    model: Model,
-    opt: Adam<Model>,
+use crate::board::Board;
+use crate::constants::Piece;
+
+// material values in centipawns, indexed by `Piece as usize`
+pub const PIECE_VALUES: [i32; 13] = [0, 0, 900, 500, 330, 320, 100, 0, 900, 500, 330, 320, 100];
+// the part of the value of a hanging piece that counts against its side,
+// the search finds out whether it is actually lost
+const HANGING_PENALTY_DIVISOR: i32 = 8;
+
+// piece-square tables from white's point of view, written with rank 8 on top
+// (simplified evaluation function by Tomasz Michniewski)
+#[rustfmt::skip]
+const PAWN_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    10, 10, 20, 30, 30, 20, 10, 10,
+     5,  5, 10, 25, 25, 10,  5,  5,
+     0,  0,  0, 20, 20,  0,  0,  0,
+     5, -5,-10,  0,  0,-10, -5,  5,
+     5, 10, 10,-20,-20, 10, 10,  5,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const KNIGHT_TABLE: [i32; 64] = [
+    -50,-40,-30,-30,-30,-30,-40,-50,
+    -40,-20,  0,  0,  0,  0,-20,-40,
+    -30,  0, 10, 15, 15, 10,  0,-30,
+    -30,  5, 15, 20, 20, 15,  5,-30,
+    -30,  0, 15, 20, 20, 15,  0,-30,
+    -30,  5, 10, 15, 15, 10,  5,-30,
+    -40,-20,  0,  5,  5,  0,-20,-40,
+    -50,-40,-30,-30,-30,-30,-40,-50,
+];
+
+#[rustfmt::skip]
+const BISHOP_TABLE: [i32; 64] = [
+    -20,-10,-10,-10,-10,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5, 10, 10,  5,  0,-10,
+    -10,  5,  5, 10, 10,  5,  5,-10,
+    -10,  0, 10, 10, 10, 10,  0,-10,
+    -10, 10, 10, 10, 10, 10, 10,-10,
+    -10,  5,  0,  0,  0,  0,  5,-10,
+    -20,-10,-10,-10,-10,-10,-10,-20,
+];
+
+#[rustfmt::skip]
+const ROOK_TABLE: [i32; 64] = [
+      0,  0,  0,  0,  0,  0,  0,  0,
+      5, 10, 10, 10, 10, 10, 10,  5,
+     -5,  0,  0,  0,  0,  0,  0, -5,
+     -5,  0,  0,  0,  0,  0,  0, -5,
+     -5,  0,  0,  0,  0,  0,  0, -5,
+     -5,  0,  0,  0,  0,  0,  0, -5,
+     -5,  0,  0,  0,  0,  0,  0, -5,
+      0,  0,  0,  5,  5,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const QUEEN_TABLE: [i32; 64] = [
+    -20,-10,-10, -5, -5,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5,  5,  5,  5,  0,-10,
+     -5,  0,  5,  5,  5,  5,  0, -5,
+      0,  0,  5,  5,  5,  5,  0, -5,
+    -10,  5,  5,  5,  5,  5,  0,-10,
+    -10,  0,  5,  0,  0,  0,  0,-10,
+    -20,-10,-10, -5, -5,-10,-10,-20,
+];
+
+#[rustfmt::skip]
+const KING_MIDDLEGAME_TABLE: [i32; 64] = [
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -20,-30,-30,-40,-40,-30,-30,-20,
+    -10,-20,-20,-20,-20,-20,-20,-10,
+     20, 20,  0,  0,  0,  0, 20, 20,
+     20, 30, 10,  0,  0, 10, 30, 20,
+];
+
+#[rustfmt::skip]
+const KING_ENDGAME_TABLE: [i32; 64] = [
+    -50,-40,-30,-20,-20,-30,-40,-50,
+    -30,-20,-10,  0,  0,-10,-20,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-30,  0,  0,  0,  0,-30,-30,
+    -50,-30,-30,-30,-30,-30,-30,-50,
+];
+
+// without queens, or with little material besides them, the king should
+// walk to the center instead of hiding
+fn is_endgame(board: &Board) -> bool {
+    let mut queens = 0;
+    let mut minors_and_rooks = 0;
+    for piece in board.piece_list {
+        match piece {
+            Piece::WhiteQueen | Piece::BlackQueen => queens += 1,
+            Piece::WhiteRook | Piece::BlackRook => minors_and_rooks += 2,
+            Piece::WhiteBishop | Piece::BlackBishop | Piece::WhiteKnight | Piece::BlackKnight => {
+                minors_and_rooks += 1
+            }
+            _ => {}
+        }
+    }
+    queens == 0 || (queens <= 2 && minors_and_rooks <= 2)
+}
+
+fn piece_square_value(piece: Piece, square: usize, endgame: bool) -> i32 {
+    // the tables are written from white's point of view with rank 8 first
+    let index = if piece as u8 <= Piece::WhitePawn as u8 {
+        square ^ 56
+    } else {
+        square
+    };
+    match piece {
+        Piece::WhitePawn | Piece::BlackPawn => PAWN_TABLE[index],
+        Piece::WhiteKnight | Piece::BlackKnight => KNIGHT_TABLE[index],
+        Piece::WhiteBishop | Piece::BlackBishop => BISHOP_TABLE[index],
+        Piece::WhiteRook | Piece::BlackRook => ROOK_TABLE[index],
+        Piece::WhiteQueen | Piece::BlackQueen => QUEEN_TABLE[index],
+        Piece::WhiteKing | Piece::BlackKing if endgame => KING_ENDGAME_TABLE[index],
+        Piece::WhiteKing | Piece::BlackKing => KING_MIDDLEGAME_TABLE[index],
+        Piece::Empty => 0,
+    }
 }
 
-impl PositionEvaluator {
-    pub fn new() -> Self {
-        let dev: Cpu = Default::default();
-        let model = dev.build_model(Model);
-        let opt = Adam::default();
-        PositionEvaluator { model, opt }
+// The terms of the evaluation for each color, index 0 is white and 1 black.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EvaluationBreakdown {
+    pub endgame: bool,
+    pub material: [i32; 2],
+    pub piece_square: [i32; 2],
+    // penalties for pieces the opponent wins material from by taking them
+    pub hanging: [i32; 2],
+}
+
+impl EvaluationBreakdown {
+    // in centipawns from white's point of view
+    pub fn total(&self) -> i32 {
+        self.material[0] + self.piece_square[0] + self.hanging[0]
+            - self.material[1]
+            - self.piece_square[1]
+            - self.hanging[1]
     }
+}
+
+pub fn evaluation_breakdown(board: &Board) -> EvaluationBreakdown {
+    let mut breakdown = EvaluationBreakdown {
+        endgame: is_endgame(board),
+        ..EvaluationBreakdown::default()
+    };
+    for (square, &piece) in board.piece_list.iter().enumerate() {
+        if piece == Piece::Empty {
+            continue;
+        }
+        let color = if piece as u8 <= Piece::WhitePawn as u8 {
+            0
+        } else {
+            1
+        };
+        breakdown.material[color] += PIECE_VALUES[piece as usize];
+        breakdown.piece_square[color] += piece_square_value(piece, square, breakdown.endgame);
+    }
+    for (color, white) in [(0, true), (1, false)] {
+        let mut hanging = board.hanging_pieces(white);
+        while hanging != 0 {
+            let square = hanging.trailing_zeros() as usize;
+            hanging &= hanging - 1;
+            breakdown.hanging[color] -=
+                PIECE_VALUES[board.piece_list[square] as usize] / HANGING_PENALTY_DIVISOR;
+        }
+    }
+    breakdown
+}
 
-    pub fn train(&mut self) {
-        let (x, target) = self.generate_batch();
+// Static evaluation in centipawns from the point of view of the side to move.
+pub fn evaluate(board: &Board, white_to_move: bool) -> i32 {
+    let score = evaluation_breakdown(board).total();
+    if white_to_move { score } else { -score }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluate_starting_position() {
+        let board = Board::starting_position();
+        assert_eq!(evaluate(&board, true), 0);
+        assert_eq!(evaluate(&board, false), 0);
+    }
+
+    #[test]
+    fn test_evaluate_is_symmetric() {
+        // the same position with colors reversed has the same score for the side to move
+        let white = Board::from_fen("4k3/8/8/8/3P4/2N5/8/4K3 w - - 0 1").unwrap();
+        let black = Board::from_fen("4k3/8/2n5/3p4/8/8/8/4K3 b - - 0 1").unwrap();
+        assert_eq!(evaluate(&white, true), evaluate(&black, false));
+        assert!(evaluate(&white, true) > 0);
+    }
 
-        let mut grads = model.alloc_grads();
-        let y = self.model.forward_mut(x.traced(grads));
-        let loss = cross_entropy_with_logits_loss(y, target);
-        loss.backward();
-        self.opt.update(&mut self.model, &grads);
+    #[test]
+    fn test_evaluation_breakdown() {
+        let breakdown = evaluation_breakdown(&Board::starting_position());
+        assert!(!breakdown.endgame);
+        // 8 pawns, 2 knights, 2 bishops, 2 rooks and a queen
+        assert_eq!(breakdown.material, [4000, 4000]);
+        assert_eq!(breakdown.piece_square[0], breakdown.piece_square[1]);
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+        let breakdown = evaluation_breakdown(&board);
+        assert!(breakdown.endgame);
+        assert_eq!(breakdown.material, [500, 0]);
+        assert_eq!(breakdown.total(), evaluate(&board, true));
+        assert_eq!(breakdown.total(), -evaluate(&board, false));
     }
 
-    pub fn load(file: &str) -> Self {
-        // TODO: implement
-        PositionEvaluator::new()
+    #[test]
+    fn test_hanging_pieces() {
+        // the knight on c3 is attacked by a pawn and the bishop on g5 by the
+        // queen, which is not defended against the knight
+        let board = Board::from_fen("4k3/8/8/3q2B1/3p4/2N5/8/R1K5 w - - 0 1").unwrap();
+        let breakdown = evaluation_breakdown(&board);
+        assert_eq!(breakdown.hanging, [-320 / 8 - 330 / 8, -900 / 8]);
+        let [material, piece_square, hanging] = [
+            breakdown.material,
+            breakdown.piece_square,
+            breakdown.hanging,
+        ]
+        .map(|[w, b]| w - b);
+        assert_eq!(breakdown.total(), material + piece_square + hanging);
     }
 }
@@ -0,0 +1,80 @@
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FenError {
+    // the FEN does not consist of six space separated fields
+    WrongFieldCount(usize),
+    // the piece placement does not consist of eight ranks
+    WrongRankCount(usize),
+    // rank (1-8) does not describe exactly eight squares
+    BadRankLength(usize),
+    InvalidPiece(char),
+    // for each color there has to be exactly one king
+    MissingKing { white: bool },
+    TooManyKings { white: bool },
+    PawnOnBackRank,
+    InvalidSideToMove(String),
+    InvalidCastlingRights(String),
+    // castling right without a king on the back rank and a rook to castle with
+    CastlingRightsInconsistent(char),
+    InvalidEnPassantSquare(String),
+    // the en passant square does not follow a double pawn push
+    ImpossibleEnPassant(String),
+    // the side that just moved left its king in check
+    SideNotToMoveInCheck,
+    InvalidHalfmoveClock(String),
+    InvalidFullmoveNumber(String),
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let color = |white: &bool| if *white { "white" } else { "black" };
+        match self {
+            FenError::WrongFieldCount(count) => write!(f, "expected 6 fields, found {}", count),
+            FenError::WrongRankCount(count) => write!(f, "expected 8 ranks, found {}", count),
+            FenError::BadRankLength(rank) => {
+                write!(f, "rank {} does not describe 8 squares", rank)
+            }
+            FenError::InvalidPiece(piece) => write!(f, "invalid piece '{}'", piece),
+            FenError::MissingKing { white } => write!(f, "{} king is missing", color(white)),
+            FenError::TooManyKings { white } => {
+                write!(f, "{} has more than one king", color(white))
+            }
+            FenError::PawnOnBackRank => write!(f, "pawn on the first or eighth rank"),
+            FenError::InvalidSideToMove(side) => write!(f, "invalid side to move '{}'", side),
+            FenError::InvalidCastlingRights(rights) => {
+                write!(f, "invalid castling rights '{}'", rights)
+            }
+            FenError::CastlingRightsInconsistent(right) => write!(
+                f,
+                "castling right '{}' without a king and rook to castle with",
+                right
+            ),
+            FenError::InvalidEnPassantSquare(square) => {
+                write!(f, "invalid en passant square '{}'", square)
+            }
+            FenError::ImpossibleEnPassant(square) => {
+                write!(
+                    f,
+                    "en passant square '{}' does not follow a double pawn push",
+                    square
+                )
+            }
+            FenError::SideNotToMoveInCheck => write!(f, "the side not to move is in check"),
+            FenError::InvalidHalfmoveClock(clock) => {
+                write!(f, "invalid halfmove clock '{}'", clock)
+            }
+            FenError::InvalidFullmoveNumber(number) => {
+                write!(f, "invalid fullmove number '{}'", number)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
+pub fn square_to_string(square: usize) -> String {
+    let file = (square % 8) as u8 + b'a';
+    let rank = (square / 8) as u8 + b'1';
+    format!("{}{}", file as char, rank as char)
+}
@@ -1,83 +1,498 @@
 use crate::board::Board;
+use crate::constants::Piece;
+use crate::fen::{FenError, square_to_string};
 use crate::game_history::GameHistory;
+use crate::game_result::{GameResult, Outcome, Termination};
+use crate::move_generator;
+use crate::types::{Color, Move, PieceType, Square};
+use crate::zobrist::Zobrist;
+use std::fmt;
 
-#[derive(Clone, Copy)]
+// returned by `Game::play` for a move that is not legal in the position
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IllegalMove(pub Move);
+
+impl fmt::Display for IllegalMove {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "illegal move {}", self.0)
+    }
+}
+
+impl std::error::Error for IllegalMove {}
+
+// A position together with the states that led to it. There is no separate
+// position type: repetitions and the fifty-move rule depend on the history,
+// so the search, the tablebases and the book all work on a `Game`.
+#[derive(Clone)]
 pub struct Game {
-    pub board: Board,
-    pub history: GameHistory,
+    pub(crate) board: Board,
+    pub(crate) history: GameHistory,
+    // castling moves are written as the king taking its own rook in UCI
+    // notation, as GUIs expect it for Chess960
+    chess960: bool,
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Game::new()
+    }
 }
 
 impl Game {
     pub fn new() -> Game {
-        Game {
+        let mut game = Game {
             board: Board::starting_position(),
             history: GameHistory::new(),
+            chess960: false,
+        };
+        game.history.current_state_mut().zobrist_hash = Zobrist::keys().zobrist_hash(&game);
+        game
+    }
+
+    // Sets up a game from a FEN string. Besides the syntax the position is
+    // checked for consistency, so that move generation can rely on it.
+    pub fn from_fen(fen: &str) -> Result<Game, FenError> {
+        let mut game = Game {
+            board: Board::from_fen(fen)?,
+            history: GameHistory::from_fen(fen)?,
+            chess960: false,
+        };
+        game.validate()?;
+        // the field count was checked above
+        game.set_castling_rights(fen.split_whitespace().nth(2).unwrap())?;
+        // an en passant square no pawn can use is dropped like in `make_move`
+        let state = game.history.current_state_mut();
+        if let Some(ep_square) = state.en_passant_square
+            && !game
+                .board
+                .can_capture_en_passant(ep_square as usize, state.white_to_move)
+        {
+            state.en_passant_square = None;
         }
+        game.history.current_state_mut().zobrist_hash = Zobrist::keys().zobrist_hash(&game);
+        Ok(game)
     }
 
-    pub fn from_fen(fen: &str) -> Game {
-        Game {
-            board: Board::from_fen(fen).unwrap(),
-            history: GameHistory::from_fen(fen),
+    fn validate(&self) -> Result<(), FenError> {
+        let pieces = &self.board.piece_list;
+        for (white, king) in [(true, Piece::WhiteKing), (false, Piece::BlackKing)] {
+            match pieces.iter().filter(|&&piece| piece == king).count() {
+                0 => return Err(FenError::MissingKing { white }),
+                1 => {}
+                _ => return Err(FenError::TooManyKings { white }),
+            }
         }
+
+        let back_ranks = (0..8).chain(56..64);
+        if back_ranks
+            .into_iter()
+            .any(|square| matches!(pieces[square], Piece::WhitePawn | Piece::BlackPawn))
+        {
+            return Err(FenError::PawnOnBackRank);
+        }
+
+        let state = self.history.current_state();
+        if let Some(ep_square) = state.en_passant_square {
+            let ep_square = ep_square as usize;
+            // the pawn that just made the double push and the square it came from
+            let (rank, pawn_square, origin_square, pawn) = if state.white_to_move {
+                (
+                    5,
+                    ep_square.wrapping_sub(8),
+                    ep_square + 8,
+                    Piece::BlackPawn,
+                )
+            } else {
+                (
+                    2,
+                    ep_square + 8,
+                    ep_square.wrapping_sub(8),
+                    Piece::WhitePawn,
+                )
+            };
+            if ep_square / 8 != rank
+                || pieces[pawn_square] != pawn
+                || pieces[ep_square] != Piece::Empty
+                || pieces[origin_square] != Piece::Empty
+            {
+                return Err(FenError::ImpossibleEnPassant(square_to_string(ep_square)));
+            }
+        }
+
+        if self.board.in_check(!state.white_to_move) {
+            return Err(FenError::SideNotToMoveInCheck);
+        }
+        Ok(())
     }
 
-    pub fn make_move(&mut self, mv: u16) {
-        let captured_piece = self.board.make_move(mv);
-        self.history.record_move(mv, captured_piece);
+    // Sets the castling rights from the castling field of a FEN string, whose
+    // syntax `GameState::from_fen` has checked. Like in X-FEN, KQkq stand for
+    // the outermost rook on either side of the king and the file of the rook
+    // (as in Shredder-FEN) picks another one in Chess960.
+    fn set_castling_rights(&mut self, field: &str) -> Result<(), FenError> {
+        let mut rights = 0;
+        for symbol in field.chars().filter(|&symbol| symbol != '-') {
+            let white = symbol.is_ascii_uppercase();
+            let (back_rank, first_right, rook) = if white {
+                (0, 0, Piece::WhiteRook)
+            } else {
+                (56, 2, Piece::BlackRook)
+            };
+            let Some(king_square) = self
+                .board
+                .king_square(white)
+                .filter(|square| square / 8 == back_rank / 8)
+            else {
+                return Err(FenError::CastlingRightsInconsistent(symbol));
+            };
+            let rook_square = match symbol.to_ascii_lowercase() {
+                'k' => self.outermost_rook(first_right),
+                'q' => self.outermost_rook(first_right + 1),
+                file => Some(back_rank + (file as u8 - b'a') as usize)
+                    .filter(|&square| self.board.piece_list[square] == rook),
+            }
+            .ok_or(FenError::CastlingRightsInconsistent(symbol))?;
+            let right = if rook_square > king_square {
+                first_right
+            } else {
+                first_right + 1
+            };
+            if rights & (1 << right) != 0 {
+                return Err(FenError::InvalidCastlingRights(field.to_string()));
+            }
+            rights |= 1 << right;
+            self.board.castling_rooks[right] = rook_square as u8;
+        }
+        self.history.current_state_mut().castling_rights = rights;
+        Ok(())
     }
 
-    pub fn undo_move(&mut self) {
-        if let Some(state) = self.history.undo_move() {
-            self.board
-                .undo_move(state.current_move.unwrap(), state.captured_piece);
+    // the rook furthest away from the king on the side of the castling right
+    fn outermost_rook(&self, right: usize) -> Option<usize> {
+        let white = right < 2;
+        let (back_rank, rook) = if white {
+            (0, Piece::WhiteRook)
+        } else {
+            (56, Piece::BlackRook)
+        };
+        let king_square = self
+            .board
+            .king_square(white)
+            .filter(|square| square / 8 == back_rank / 8)?;
+        let is_rook = |square: &usize| self.board.piece_list[*square] == rook;
+        if right & 1 == 0 {
+            (king_square + 1..back_rank + 8).rev().find(is_rook)
+        } else {
+            (back_rank..king_square).find(is_rook)
         }
     }
 
-    pub fn generate_legal_moves(&self) -> Vec<u16> {
-        let white_to_move = self.history.current_state().white_to_move;
-        let moves = self.board.generate_legal_moves(white_to_move);
-        let mut legal_moves: Vec<u16> = Vec::new();
+    // The symbols of the castling rights in a FEN string: KQkq for the
+    // outermost rooks, otherwise the file of the rook.
+    fn castling_symbols(&self) -> [char; 4] {
+        let mut symbols = ['K', 'Q', 'k', 'q'];
+        for (right, symbol) in symbols.iter_mut().enumerate() {
+            let rook_square = self.board.castling_rooks[right] as usize;
+            if self.outermost_rook(right) != Some(rook_square) {
+                let file = (b'a' + (rook_square % 8) as u8) as char;
+                *symbol = if right < 2 {
+                    file.to_ascii_uppercase()
+                } else {
+                    file
+                };
+            }
+        }
+        symbols
+    }
 
-        // filter out castling and en passant that are not legal due to game state
-        let state = self.history.current_state();
-        for mv in moves.into_iter() {
-            if mv & 0xF000 == 0x5000 {
-                let to = ((mv >> 6) & 0x3F) as u8;
-                // en passant
-                if state.en_passant_square != Some(to) {
-                    continue;
-                }
-            } else if mv & 0xE000 == 0x2000 {
-                // castling
-                if mv & 0xF000 == 0x2000 {
-                    // kingside
-                    if state.white_to_move {
-                        if state.castling_rights & 0x01 == 0 {
-                            continue;
-                        }
-                    } else {
-                        if state.castling_rights & 0x04 == 0 {
-                            continue;
-                        }
-                    }
-                } else if mv & 0xF000 == 0x3000 {
-                    // queenside
-                    if state.white_to_move {
-                        if state.castling_rights & 0x02 == 0 {
-                            continue;
-                        }
+    // The same position with the colors swapped and the board mirrored, the
+    // game history is not kept.
+    pub fn flipped(&self) -> Game {
+        let fen = self.to_fen();
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        let swap_case = |text: &str| -> String {
+            text.chars()
+                .map(|c| {
+                    if c.is_ascii_uppercase() {
+                        c.to_ascii_lowercase()
                     } else {
-                        if state.castling_rights & 0x08 == 0 {
-                            continue;
-                        }
+                        c.to_ascii_uppercase()
                     }
-                }
+                })
+                .collect()
+        };
+        let placement: Vec<String> = fields[0].split('/').rev().map(swap_case).collect();
+        let side = if fields[1] == "w" { "b" } else { "w" };
+        let mut castling: Vec<char> = swap_case(fields[2]).chars().collect();
+        // white's rights come first
+        castling.sort_by_key(|right| right.is_ascii_lowercase());
+        let en_passant = match fields[3].as_bytes() {
+            [file, rank] => format!("{}{}", *file as char, (b'1' + b'8' - rank) as char),
+            _ => "-".to_string(),
+        };
+        let flipped = format!(
+            "{} {} {} {} {} {}",
+            placement.join("/"),
+            side,
+            castling.iter().collect::<String>(),
+            en_passant,
+            fields[4],
+            fields[5]
+        );
+        let mut game =
+            Game::from_fen(&flipped).expect("the mirror image of a valid position is valid");
+        game.chess960 = self.chess960;
+        game
+    }
+
+    pub fn to_fen(&self) -> String {
+        format!(
+            "{} {}",
+            self.board.to_fen(),
+            self.history.current_state().to_fen(self.castling_symbols())
+        )
+    }
+
+    pub(crate) fn make_move(&mut self, mv: u16) {
+        let piece_hash = self.board.zobrist_hash();
+        let moving_piece = self.board.piece_list[(mv & 0x3F) as usize];
+        let captured_piece = self.board.make_move(mv);
+        self.history.record_move(
+            mv,
+            moving_piece,
+            captured_piece,
+            self.board.castling_rooks,
+            piece_hash ^ self.board.zobrist_hash(),
+        );
+
+        // only keep the en passant square if the capture is possible, otherwise
+        // the same position would hash differently right after a double push
+        let state = self.history.current_state_mut();
+        if let Some(ep_square) = state.en_passant_square
+            && !self
+                .board
+                .can_capture_en_passant(ep_square as usize, state.white_to_move)
+        {
+            state.zobrist_hash ^= Zobrist::keys().en_passant_key(state.en_passant_square);
+            state.en_passant_square = None;
+        }
+        debug_assert_eq!(
+            self.history.current_state().zobrist_hash,
+            Zobrist::keys().zobrist_hash(self),
+            "incremental zobrist hash out of sync"
+        );
+    }
+
+    pub fn zobrist_hash(&self) -> u64 {
+        self.history.current_state().zobrist_hash
+    }
+
+    // Passes the move to the other side, taken back with `undo_move`. Only
+    // meant for the search, the side to move must not be in check.
+    pub(crate) fn make_null_move(&mut self) {
+        self.history.record_null_move();
+        debug_assert_eq!(
+            self.history.current_state().zobrist_hash,
+            Zobrist::keys().zobrist_hash(self),
+            "incremental zobrist hash out of sync"
+        );
+    }
+
+    pub fn undo_move(&mut self) {
+        // a null move left the board untouched
+        if let Some(state) = self.history.undo_move()
+            && let Some(mv) = state.current_move
+        {
+            self.board.undo_move(mv, state.captured_piece);
+        }
+    }
+
+    pub(crate) fn generate_legal_moves(&self) -> Vec<u16> {
+        let state = self.history.current_state();
+        self.board.generate_legal_moves(
+            state.white_to_move,
+            state.castling_rights,
+            state.en_passant_square,
+        )
+    }
+
+    pub fn side_to_move(&self) -> Color {
+        if self.history.current_state().white_to_move {
+            Color::White
+        } else {
+            Color::Black
+        }
+    }
+
+    pub fn piece_at(&self, square: Square) -> Option<(Color, PieceType)> {
+        let piece = self.board.piece_list[square.index()];
+        Some((piece.color()?, piece.piece_type()?))
+    }
+
+    pub fn en_passant_square(&self) -> Option<Square> {
+        Square::new(self.history.current_state().en_passant_square?)
+    }
+
+    pub fn legal_moves(&self) -> Vec<Move> {
+        move_generator::legal_moves(self)
+    }
+
+    // With Chess960 castling moves are read and written as the king taking
+    // its own rook, see `move_to_uci`.
+    pub fn set_chess960(&mut self, chess960: bool) {
+        self.chess960 = chess960;
+    }
+
+    pub fn is_chess960(&self) -> bool {
+        self.chess960
+    }
+
+    // UCI notation of a legal move, like its `Display` except for castling
+    // in Chess960: the king may not move at all there, so e1g1 would be
+    // ambiguous and the rook's square is given instead.
+    pub fn move_to_uci(&self, mv: Move) -> String {
+        if self.chess960 && mv.is_castling() {
+            let (rook_square, _) = self.board.castling_rook_squares(mv.raw());
+            format!("{}{}", mv.from(), square_to_string(rook_square))
+        } else {
+            mv.to_string()
+        }
+    }
+
+    // Finds the legal move in UCI notation like e2e4 or e7e8q, upper case
+    // letters are accepted.
+    pub fn parse_uci(&self, uci: &str) -> Option<Move> {
+        let uci = uci.to_ascii_lowercase();
+        self.legal_moves()
+            .into_iter()
+            .find(|&mv| self.move_to_uci(mv) == uci)
+    }
+
+    // the legal captures (including en passant) and promotions
+    pub fn captures(&self) -> Vec<Move> {
+        move_generator::captures(self)
+    }
+
+    // Plays a move, the game is unchanged if the move is not legal.
+    pub fn play(&mut self, mv: Move) -> Result<(), IllegalMove> {
+        if !self.generate_legal_moves().contains(&mv.raw()) {
+            return Err(IllegalMove(mv));
+        }
+        self.make_move(mv.raw());
+        Ok(())
+    }
+
+    // how often the current position occurred in the game so far
+    pub fn repetition_count(&self) -> usize {
+        self.history.repetition_count()
+    }
+
+    // the position occurred before, which the search scores as a draw
+    pub fn is_repetition(&self) -> bool {
+        self.repetition_count() >= 2
+    }
+
+    // a draw can be claimed
+    pub fn is_threefold_repetition(&self) -> bool {
+        self.repetition_count() >= 3
+    }
+
+    // the game is drawn automatically
+    pub fn is_fivefold_repetition(&self) -> bool {
+        self.repetition_count() >= 5
+    }
+
+    // 50 moves by each side without a capture or pawn move, a draw can be claimed
+    pub fn is_fifty_move_rule(&self) -> bool {
+        self.history.current_state().reversible_move_counter >= 100
+    }
+
+    // 75 moves by each side without a capture or pawn move, the game is drawn
+    pub fn is_seventy_five_move_rule(&self) -> bool {
+        self.history.current_state().reversible_move_counter >= 150
+    }
+
+    pub fn is_insufficient_material(&self) -> bool {
+        self.board.is_insufficient_material()
+    }
+
+    // Returns how the game ended, or None if it is still going on. Claimable
+    // draws (threefold repetition, 50-move rule) are reported as well, a
+    // checkmate takes precedence over them.
+    pub fn outcome(&self) -> Option<Outcome> {
+        let draw = |termination| {
+            Some(Outcome {
+                result: GameResult::Draw,
+                termination,
+            })
+        };
+
+        if self.generate_legal_moves().is_empty() {
+            if !self.in_check() {
+                return draw(Termination::Stalemate);
             }
-            legal_moves.push(mv);
+            let result = if self.history.current_state().white_to_move {
+                GameResult::BlackWins
+            } else {
+                GameResult::WhiteWins
+            };
+            return Some(Outcome {
+                result,
+                termination: Termination::Checkmate,
+            });
+        }
+
+        if self.is_insufficient_material() {
+            draw(Termination::InsufficientMaterial)
+        } else if self.is_fivefold_repetition() {
+            draw(Termination::FivefoldRepetition)
+        } else if self.is_seventy_five_move_rule() {
+            draw(Termination::SeventyFiveMoveRule)
+        } else if self.is_threefold_repetition() {
+            draw(Termination::ThreefoldRepetition)
+        } else if self.is_fifty_move_rule() {
+            draw(Termination::FiftyMoveRule)
+        } else {
+            None
+        }
+    }
+
+    pub fn in_check(&self) -> bool {
+        self.board
+            .in_check(self.history.current_state().white_to_move)
+    }
+
+    // Counts the leaf nodes of the legal move tree up to `depth`, used to
+    // validate move generation against known node counts.
+    pub fn perft(&mut self, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let moves = self.generate_legal_moves();
+        if depth == 1 {
+            return moves.len() as u64;
         }
+        let mut nodes = 0;
+        for mv in moves {
+            self.make_move(mv);
+            nodes += self.perft(depth - 1);
+            self.undo_move();
+        }
+        nodes
+    }
 
-        legal_moves
+    // Like `perft`, but returns the node count below each root move.
+    pub fn perft_divide(&mut self, depth: u32) -> Vec<(Move, u64)> {
+        if depth == 0 {
+            return Vec::new();
+        }
+        let mut divide = Vec::new();
+        for mv in self.legal_moves() {
+            self.make_move(mv.raw());
+            divide.push((mv, self.perft(depth - 1)));
+            self.undo_move();
+        }
+        divide
     }
 }
 
@@ -86,6 +501,90 @@ mod tests {
     use super::*;
     use crate::constants::Piece;
 
+    #[test]
+    fn test_typed_api() {
+        let mut game = Game::new();
+        assert_eq!(game.side_to_move(), Color::White);
+        let e2: Square = "e2".parse().unwrap();
+        assert_eq!(game.piece_at(e2), Some((Color::White, PieceType::Pawn)));
+        assert_eq!(game.legal_moves().len(), 20);
+
+        let e4 = game.parse_uci("e2e4").unwrap();
+        assert_eq!(game.play(e4), Ok(()));
+        assert_eq!(game.side_to_move(), Color::Black);
+        assert_eq!(game.piece_at(e2), None);
+        // the same move is not legal for black
+        assert_eq!(game.play(e4), Err(IllegalMove(e4)));
+        assert_eq!(game.side_to_move(), Color::Black);
+    }
+
+    #[test]
+    fn test_parse_uci() {
+        let game = Game::new();
+        let e4 = game.parse_uci("e2e4").unwrap();
+        assert_eq!(e4.to_string(), "e2e4");
+        assert_eq!(game.parse_uci("E2E4"), Some(e4));
+        assert_eq!(game.parse_uci("e2e5"), None);
+        assert_eq!(game.parse_uci("e2"), None);
+        assert_eq!(game.parse_uci(""), None);
+
+        let game = Game::from_fen("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let knight = game.parse_uci("b7b8n").unwrap();
+        assert_eq!(knight.promotion(), Some(PieceType::Knight));
+        assert_eq!(
+            game.parse_uci("b7b8Q").unwrap().promotion(),
+            Some(PieceType::Queen)
+        );
+        // the promotion piece is required
+        assert_eq!(game.parse_uci("b7b8"), None);
+        assert_eq!(game.parse_uci("b7b8k"), None);
+    }
+
+    #[test]
+    fn test_chess960_castling() {
+        // the king stays on c1 and the rook goes from b1 to d1
+        let mut game = Game::from_fen("4k3/8/8/8/8/8/8/1RK5 w Q - 0 1").unwrap();
+        let castling = game
+            .legal_moves()
+            .into_iter()
+            .find(|mv| mv.is_castling())
+            .unwrap();
+        assert_eq!(game.move_to_uci(castling), "c1c1");
+        game.set_chess960(true);
+        assert_eq!(game.move_to_uci(castling), "c1b1");
+        assert_eq!(game.parse_uci("c1b1"), Some(castling));
+        assert_eq!(game.parse_uci("c1c1"), None);
+        game.make_move(castling.raw());
+        assert_eq!(game.to_fen(), "4k3/8/8/8/8/8/8/2KR4 b - - 1 1");
+        game.undo_move();
+        assert_eq!(game.to_fen(), "4k3/8/8/8/8/8/8/1RK5 w Q - 0 1");
+
+        // without the rook on b1 the king would be in check from a1
+        let game = Game::from_fen("4k3/8/8/8/8/8/8/rRK5 w Q - 0 1").unwrap();
+        assert!(!game.legal_moves().iter().any(|mv| mv.is_castling()));
+    }
+
+    #[test]
+    fn test_chess960_fen() {
+        // KQkq stand for the outermost rooks, so Shredder-FEN is read but
+        // written as X-FEN
+        let game =
+            Game::from_fen("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9")
+                .unwrap();
+        assert_eq!(
+            game.to_fen(),
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9"
+        );
+        // the inner of two rooks on the same side is given by its file
+        let fen = "1r1kr2r/8/8/8/8/8/8/RR2K3 w Beq - 0 1";
+        let game = Game::from_fen(fen).unwrap();
+        assert_eq!(game.to_fen(), fen);
+        assert_eq!(
+            game.flipped().to_fen(),
+            "rr2k3/8/8/8/8/8/8/1R1KR2R b EQb - 0 1"
+        );
+    }
+
     #[test]
     fn test_starting_position() {
         let game = Game::new();
@@ -98,17 +597,18 @@ mod tests {
     fn test_from_fen() {
         // Test parsing starting position FEN
         let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
-        let game = Game::from_fen(fen);
+        let game = Game::from_fen(fen).unwrap();
         let board = game.board;
         assert_eq!(board.piece_list[0], Piece::WhiteRook);
         assert_eq!(board.piece_list[60], Piece::BlackKing);
+        assert_eq!(game.history.current_state().castling_rights, 0x0F);
     }
 
     #[test]
     fn test_from_fen_e4e5_opening() {
         // Test parsing a FEN string after 1. e4 e5
         let fen = "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2";
-        let game = Game::from_fen(fen);
+        let game = Game::from_fen(fen).unwrap();
         let board = game.board;
         assert_eq!(board.piece_list[28], Piece::WhitePawn); // e4
         assert_eq!(board.piece_list[36], Piece::BlackPawn); // e5
@@ -118,10 +618,486 @@ mod tests {
     fn test_from_fen_bongcloud_opening() {
         // Test parsing a FEN string after 1. e4 e5 2. Ke2
         let fen = "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPPKPPP/RNBQ1BNR b kq - 1 2";
-        let game = Game::from_fen(fen);
+        let game = Game::from_fen(fen).unwrap();
         let board = game.board;
         assert_eq!(board.piece_list[28], Piece::WhitePawn); // e4
         assert_eq!(board.piece_list[36], Piece::BlackPawn); // e5
         assert_eq!(board.piece_list[12], Piece::WhiteKing); // e2
     }
+
+    #[test]
+    fn test_to_fen_round_trip() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2",
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPPKPPP/RNBQ1BNR b kq - 1 2",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+            "4k3/8/8/8/3pP3/8/8/4K3 b - e3 0 1",
+        ];
+        for fen in fens {
+            assert_eq!(Game::from_fen(fen).unwrap().to_fen(), fen);
+        }
+    }
+
+    #[test]
+    fn test_flipped() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let game = Game::from_fen(fen).unwrap();
+        let mut flipped = game.flipped();
+        assert_eq!(
+            flipped.to_fen(),
+            "r3k2r/pppbbppp/2n2q1P/1P2p3/3pn3/BN2PNP1/P1PPQPB1/R3K2R b KQkq - 0 1"
+        );
+        assert_eq!(flipped.flipped().to_fen(), fen);
+        assert_eq!(flipped.perft(3), 97862);
+        let game = Game::from_fen("4k3/8/8/8/3pP3/8/8/4K3 b - e3 0 1").unwrap();
+        assert_eq!(game.flipped().to_fen(), "4k3/8/8/3Pp3/8/8/8/4K3 w - e6 0 1");
+    }
+
+    #[test]
+    fn test_to_fen_after_moves() {
+        let mut game = Game::new();
+        // 1. e4 c5 2. Ke2
+        game.make_move(0x1000 | (28 << 6) | 12);
+        game.make_move(0x1000 | (34 << 6) | 50);
+        game.make_move((12 << 6) | 4);
+        assert_eq!(
+            game.to_fen(),
+            "rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPPKPPP/RNBQ1BNR b kq - 1 2"
+        );
+    }
+
+    #[test]
+    fn test_from_fen_errors() {
+        let cases = [
+            ("8/8/8/8/8/8/8/8 w - -", FenError::WrongFieldCount(4)),
+            ("4k3/8/8/8/8/8/4K3 w - - 0 1", FenError::WrongRankCount(7)),
+            ("4k3/8/8/8/8/8/8/4K4 w - - 0 1", FenError::BadRankLength(1)),
+            ("4k3/8/8/8/8/8/8/4K2 w - - 0 1", FenError::BadRankLength(1)),
+            ("4k3/8/8/8/44/8/8/4K3 w - - 0 1", FenError::BadRankLength(4)),
+            ("4k3/8/8/8/8/8/8/4X3 w - - 0 1", FenError::InvalidPiece('X')),
+            (
+                "8/8/8/8/8/8/8/4K3 w - - 0 1",
+                FenError::MissingKing { white: false },
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K2K w - - 0 1",
+                FenError::TooManyKings { white: true },
+            ),
+            ("4k2P/8/8/8/8/8/8/4K3 w - - 0 1", FenError::PawnOnBackRank),
+            (
+                "4k3/8/8/8/8/8/8/4K3 x - - 0 1",
+                FenError::InvalidSideToMove("x".to_string()),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w KK - 0 1",
+                FenError::InvalidCastlingRights("KK".to_string()),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w K - 0 1",
+                FenError::CastlingRightsInconsistent('K'),
+            ),
+            (
+                "r3k3/8/8/8/8/8/8/4K3 w k - 0 1",
+                FenError::CastlingRightsInconsistent('k'),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/R3K3 w C - 0 1",
+                FenError::CastlingRightsInconsistent('C'),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K2R w KH - 0 1",
+                FenError::InvalidCastlingRights("KH".to_string()),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K2R w KX - 0 1",
+                FenError::InvalidCastlingRights("KX".to_string()),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - e9 0 1",
+                FenError::InvalidEnPassantSquare("e9".to_string()),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - e6 0 1",
+                FenError::ImpossibleEnPassant("e6".to_string()),
+            ),
+            (
+                "4k3/8/8/4p3/8/8/8/4K3 b - e6 0 1",
+                FenError::ImpossibleEnPassant("e6".to_string()),
+            ),
+            (
+                "4k3/4p3/8/4p3/8/8/8/4K3 w - e6 0 1",
+                FenError::ImpossibleEnPassant("e6".to_string()),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4R1K1 w - - 0 1",
+                FenError::SideNotToMoveInCheck,
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - - x 1",
+                FenError::InvalidHalfmoveClock("x".to_string()),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - - 0 0",
+                FenError::InvalidFullmoveNumber("0".to_string()),
+            ),
+        ];
+        for (fen, error) in cases {
+            assert_eq!(Game::from_fen(fen).err(), Some(error), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_zobrist_hash_make_undo() {
+        // Kiwipete exercises castling, en passant and promotions below the root
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let mut game = Game::from_fen(fen).unwrap();
+        let initial_hash = game.zobrist_hash();
+        assert_eq!(initial_hash, Zobrist::keys().zobrist_hash(&game));
+        for mv in game.generate_legal_moves() {
+            game.make_move(mv);
+            assert_eq!(game.zobrist_hash(), Zobrist::keys().zobrist_hash(&game));
+            for reply in game.generate_legal_moves() {
+                game.make_move(reply);
+                assert_eq!(game.zobrist_hash(), Zobrist::keys().zobrist_hash(&game));
+                game.undo_move();
+            }
+            game.undo_move();
+            assert_eq!(game.zobrist_hash(), initial_hash);
+        }
+    }
+
+    #[test]
+    fn test_null_move() {
+        // the en passant square after d5 is cleared by the null move
+        let fen = "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1";
+        let mut game = Game::from_fen(fen).unwrap();
+        let initial_hash = game.zobrist_hash();
+        game.make_null_move();
+        let state = game.history.current_state();
+        assert!(!state.white_to_move);
+        assert_eq!(state.en_passant_square, None);
+        assert_eq!(game.zobrist_hash(), Zobrist::keys().zobrist_hash(&game));
+        assert_eq!(game.generate_legal_moves().len(), 6);
+        game.make_move(60 | (59 << 6));
+        game.undo_move();
+        game.undo_move();
+        assert_eq!(game.zobrist_hash(), initial_hash);
+        assert_eq!(game.to_fen(), fen);
+    }
+
+    #[test]
+    fn test_long_game() {
+        // far more moves than any real game, the history keeps all of them
+        let mut game = Game::new();
+        let moves = [6 | (21 << 6), 62 | (45 << 6), 21 | (6 << 6), 45 | (62 << 6)];
+        for &mv in moves.iter().cycle().take(4000) {
+            game.play(Move::from_raw(mv)).unwrap();
+        }
+        assert_eq!(game.history.len(), 4001);
+        assert!(game.is_fivefold_repetition());
+        assert_eq!(game.zobrist_hash(), Game::new().zobrist_hash());
+        for _ in 0..4000 {
+            game.undo_move();
+        }
+        assert_eq!(game.to_fen(), Game::new().to_fen());
+    }
+
+    #[test]
+    fn test_threefold_repetition() {
+        let mut game = Game::new();
+        // Nf3 Nf6 Ng1 Ng8, twice
+        let moves = [6 | (21 << 6), 62 | (45 << 6), 21 | (6 << 6), 45 | (62 << 6)];
+        assert_eq!(game.repetition_count(), 1);
+        for mv in moves {
+            game.make_move(mv);
+        }
+        assert!(game.is_repetition());
+        assert!(!game.is_threefold_repetition());
+        for mv in moves {
+            game.make_move(mv);
+        }
+        assert!(game.is_threefold_repetition());
+        assert!(!game.is_fivefold_repetition());
+        for mv in moves.iter().chain(moves.iter()) {
+            game.make_move(*mv);
+        }
+        assert!(game.is_fivefold_repetition());
+    }
+
+    #[test]
+    fn test_repetition_after_double_push() {
+        // after 1. e4 the en passant square is not set since no black pawn can
+        // capture, so the position repeats after Nf3 Nc6 Ng1 Nb8
+        let mut game = Game::new();
+        game.make_move(0x1000 | 12 | (28 << 6));
+        assert_eq!(game.history.current_state().en_passant_square, None);
+        for mv in [57 | (42 << 6), 6 | (21 << 6), 42 | (57 << 6), 21 | (6 << 6)] {
+            game.make_move(mv);
+        }
+        assert!(game.is_repetition());
+    }
+
+    #[test]
+    fn test_fen_en_passant_square_like_moves() {
+        // a GUI sends the en passant square after 1. e4 although no black
+        // pawn can capture
+        let mut game =
+            Game::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1").unwrap();
+        let mut played = Game::new();
+        played.make_move(0x1000 | 12 | (28 << 6));
+        assert_eq!(game.en_passant_square(), None);
+        assert_eq!(game.zobrist_hash(), played.zobrist_hash());
+        assert_eq!(
+            game.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1"
+        );
+        for mv in [57 | (42 << 6), 6 | (21 << 6), 42 | (57 << 6), 21 | (6 << 6)] {
+            game.make_move(mv);
+        }
+        assert!(game.is_repetition());
+        // a square that can be used stays
+        let game =
+            Game::from_fen("rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 3").unwrap();
+        assert_eq!(game.en_passant_square(), Some("e3".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_pawn_move_breaks_repetition() {
+        let mut game = Game::new();
+        for mv in [6 | (21 << 6), 62 | (45 << 6), 21 | (6 << 6), 45 | (62 << 6)] {
+            game.make_move(mv);
+        }
+        game.make_move(12 | (20 << 6));
+        assert_eq!(game.history.current_state().reversible_move_counter, 0);
+        assert_eq!(game.repetition_count(), 1);
+    }
+
+    #[test]
+    fn test_fifty_move_rule() {
+        let game = Game::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 99 80").unwrap();
+        assert!(!game.is_fifty_move_rule());
+        let mut game = game;
+        game.make_move(4 | (12 << 6));
+        assert!(game.is_fifty_move_rule());
+        assert!(!game.is_seventy_five_move_rule());
+        let game = Game::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 150 100").unwrap();
+        assert!(game.is_seventy_five_move_rule());
+    }
+
+    #[test]
+    fn test_outcome_ongoing() {
+        assert_eq!(Game::new().outcome(), None);
+    }
+
+    #[test]
+    fn test_outcome_checkmate() {
+        // fool's mate
+        let game = Game::from_fen("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3")
+            .unwrap();
+        let outcome = game.outcome().unwrap();
+        assert_eq!(outcome.termination, Termination::Checkmate);
+        assert_eq!(outcome.result, GameResult::BlackWins);
+        assert_eq!(outcome.result.to_string(), "0-1");
+    }
+
+    #[test]
+    fn test_outcome_checkmate_beats_fifty_move_rule() {
+        let game = Game::from_fen("R5k1/5ppp/8/8/8/8/8/6K1 b - - 100 80").unwrap();
+        let outcome = game.outcome().unwrap();
+        assert_eq!(outcome.termination, Termination::Checkmate);
+        assert_eq!(outcome.result, GameResult::WhiteWins);
+    }
+
+    #[test]
+    fn test_outcome_draws() {
+        let draws = [
+            ("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", Termination::Stalemate),
+            (
+                "4k3/8/8/8/8/8/8/4KN2 w - - 0 1",
+                Termination::InsufficientMaterial,
+            ),
+            (
+                "4k3/8/8/8/8/8/8/R3K3 w - - 100 80",
+                Termination::FiftyMoveRule,
+            ),
+            (
+                "4k3/8/8/8/8/8/8/R3K3 w - - 150 100",
+                Termination::SeventyFiveMoveRule,
+            ),
+        ];
+        for (fen, termination) in draws {
+            let outcome = Game::from_fen(fen).unwrap().outcome().unwrap();
+            assert_eq!(outcome.result, GameResult::Draw, "{}", fen);
+            assert_eq!(outcome.termination, termination, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_outcome_repetition() {
+        let mut game = Game::new();
+        let moves = [6 | (21 << 6), 62 | (45 << 6), 21 | (6 << 6), 45 | (62 << 6)];
+        for mv in moves.iter().chain(moves.iter()) {
+            game.make_move(*mv);
+        }
+        let outcome = game.outcome().unwrap();
+        assert_eq!(outcome.termination, Termination::ThreefoldRepetition);
+        assert!(outcome.termination.is_claimable());
+        for mv in moves.iter().chain(moves.iter()) {
+            game.make_move(*mv);
+        }
+        let outcome = game.outcome().unwrap();
+        assert_eq!(outcome.termination, Termination::FivefoldRepetition);
+        assert!(!outcome.termination.is_claimable());
+    }
+
+    fn assert_perft(fen: &str, expected: &[u64]) {
+        let mut game = Game::from_fen(fen).unwrap();
+        for (depth, &nodes) in expected.iter().enumerate() {
+            assert_eq!(
+                game.perft(depth as u32 + 1),
+                nodes,
+                "depth {} of {}",
+                depth + 1,
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn test_perft_starting_position() {
+        assert_perft(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            &[20, 400, 8902, 197281, 4865609],
+        );
+    }
+
+    #[test]
+    fn test_perft_kiwipete() {
+        assert_perft(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            &[48, 2039, 97862, 4085603],
+        );
+    }
+
+    #[test]
+    fn test_perft_en_passant_endgame() {
+        // position 3: en passant captures exposing the king along the rank
+        assert_perft(
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            &[14, 191, 2812, 43238, 674624],
+        );
+    }
+
+    #[test]
+    fn test_perft_promotions() {
+        // position 4: promotions, castling out of check and pinned pieces
+        assert_perft(
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            &[6, 264, 9467, 422333],
+        );
+        // the same position with colors reversed
+        assert_perft(
+            "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+            &[6, 264, 9467, 422333],
+        );
+    }
+
+    #[test]
+    fn test_perft_position_5() {
+        assert_perft(
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            &[44, 1486, 62379, 2103487],
+        );
+    }
+
+    #[test]
+    fn test_perft_position_6() {
+        assert_perft(
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+            &[46, 2079, 89890, 3894594],
+        );
+    }
+
+    #[test]
+    fn test_perft_edge_cases() {
+        let positions = [
+            // avoid illegal en passant capture
+            ("3k4/3p4/8/K1P4r/8/8/8/8 b - - 0 1", 6, 1134888),
+            ("8/8/4k3/8/2p5/8/B2P2K1/8 w - - 0 1", 6, 1015133),
+            // en passant capture checks opponent
+            ("8/8/1k6/2b5/2pP4/8/5K2/8 b - d3 0 1", 6, 1440467),
+            // short and long castling give check
+            ("5k2/8/8/8/8/8/8/4K2R w K - 0 1", 6, 661072),
+            ("3k4/8/8/8/8/8/8/R3K3 w Q - 0 1", 6, 803711),
+            // castling rights lost by captures
+            ("r3k2r/1b4bq/8/8/8/8/7B/R3K2R w KQkq - 0 1", 4, 1274206),
+            // castling prevented by attacked squares
+            ("r3k2r/8/3Q4/8/8/5q2/8/R3K2R b KQkq - 0 1", 4, 1720476),
+            // promote out of check
+            ("2K2r2/4P3/8/8/8/8/8/3k4 w - - 0 1", 6, 3821001),
+            // discovered check
+            ("8/8/1P2K3/8/2n5/1q6/8/5k2 b - - 0 1", 5, 1004658),
+            // promote and underpromote to give check
+            ("4k3/1P6/8/8/8/8/K7/8 w - - 0 1", 6, 217342),
+            ("8/P1k5/K7/8/8/8/8/8 w - - 0 1", 6, 92683),
+            // self stalemate
+            ("K1k5/8/P7/8/8/8/8/8 w - - 0 1", 6, 2217),
+            // stalemate and checkmate
+            ("8/k1P5/8/1K6/8/8/8/8 w - - 0 1", 7, 567584),
+            ("8/8/2k5/5q2/5n2/8/5K2/8 b - - 0 1", 4, 23527),
+        ];
+        for (fen, depth, nodes) in positions {
+            let mut game = Game::from_fen(fen).unwrap();
+            assert_eq!(game.perft(depth), nodes, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_perft_chess960() {
+        // from the Chess960 perft suite, the rights are given in X-FEN and
+        // Shredder-FEN
+        assert_perft(
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+            &[21, 528, 12189, 326672],
+        );
+        assert_perft(
+            "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+            &[21, 807, 18002, 667366],
+        );
+        assert_perft(
+            "b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9",
+            &[20, 479, 10471, 273318],
+        );
+        assert_perft(
+            "qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9",
+            &[22, 593, 13440, 382958],
+        );
+        assert_perft(
+            "1nbbnrkr/p1p1ppp1/3p4/1p3P1p/3Pq2P/8/PPP1P1P1/QNBBNRKR w HFhf - 0 9",
+            &[28, 1120, 31058, 1171749],
+        );
+        assert_perft(
+            "qnbnr1kr/ppp1b1pp/4p3/3p1p2/8/2NPP3/PPP1BPPP/QNB1R1KR w HEhe - 1 9",
+            &[29, 899, 26578, 824055],
+        );
+    }
+
+    #[test]
+    fn test_perft_divide() {
+        let mut game = Game::new();
+        let divide = game.perft_divide(3);
+        assert_eq!(divide.len(), 20);
+        assert_eq!(divide.iter().map(|(_, nodes)| nodes).sum::<u64>(), 8902);
+        // e2e4
+        let e2e4 = divide
+            .iter()
+            .find(|(mv, _)| mv.to_string() == "e2e4")
+            .unwrap();
+        assert_eq!(e2e4.1, 600);
+    }
 }
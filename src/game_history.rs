@@ -1,53 +1,128 @@
-use crate::constants::MAX_GAME_HISTORY_LENGTH;
 use crate::constants::Piece;
+use crate::fen::FenError;
 use crate::game_state::GameState;
 
-#[derive(Clone, Copy)]
+// room for the states of a long game and a search below it, more is
+// allocated when needed
+const INITIAL_CAPACITY: usize = 1024;
+
+// The states of a game from its start position on, the last one is the
+// current state.
+#[derive(Clone, Debug)]
 pub struct GameHistory {
-    pub list: [GameState; MAX_GAME_HISTORY_LENGTH],
-    pub length: usize,
+    states: Vec<GameState>,
+}
+
+impl Default for GameHistory {
+    fn default() -> Self {
+        GameHistory::new()
+    }
 }
 
 impl GameHistory {
     pub fn new() -> GameHistory {
-        GameHistory {
-            list: [GameState::new(); MAX_GAME_HISTORY_LENGTH],
-            length: 1,
-        }
+        GameHistory::with_start(GameState::new())
     }
 
-    pub fn from_fen(fen: &str) -> GameHistory {
-        let mut list = [GameState::new(); MAX_GAME_HISTORY_LENGTH];
-        list[0] = GameState::from_fen(fen).unwrap();
-        GameHistory {
-            list: list,
-            length: 1,
-        }
+    pub fn from_fen(fen: &str) -> Result<GameHistory, FenError> {
+        Ok(GameHistory::with_start(GameState::from_fen(fen)?))
+    }
+
+    fn with_start(state: GameState) -> GameHistory {
+        let mut states = Vec::with_capacity(INITIAL_CAPACITY);
+        states.push(state);
+        GameHistory { states }
     }
 
     pub fn current_state(&self) -> GameState {
-        self.list[self.length - 1]
+        self.states[self.states.len() - 1]
     }
 
-    pub fn record_move(&mut self, mv: u16, captured_piece: Piece) -> Option<GameState> {
-        if self.length < MAX_GAME_HISTORY_LENGTH {
-            let prev_state = self.list[self.length - 1];
-            let new_state = prev_state.after_move(mv, captured_piece);
-            self.list[self.length] = new_state;
-            self.length += 1;
-            Some(new_state)
-        } else {
-            None
+    pub(crate) fn current_state_mut(&mut self) -> &mut GameState {
+        let last = self.states.len() - 1;
+        &mut self.states[last]
+    }
+
+    // number of states, the start position included
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    // `piece_hash_delta` is the change of the board's piece placement hash
+    // caused by the move
+    pub fn record_move(
+        &mut self,
+        mv: u16,
+        moving_piece: Piece,
+        captured_piece: Piece,
+        castling_rooks: [u8; 4],
+        piece_hash_delta: u64,
+    ) {
+        let mut new_state =
+            self.current_state()
+                .after_move(mv, moving_piece, captured_piece, castling_rooks);
+        new_state.zobrist_hash ^= piece_hash_delta;
+        self.states.push(new_state);
+    }
+
+    pub fn record_null_move(&mut self) {
+        let new_state = self.current_state().after_null_move();
+        self.states.push(new_state);
+    }
+
+    // Counts how often the current position occurred, including itself. Only
+    // positions since the last capture or pawn move can repeat, and only with
+    // the same side to move.
+    pub fn repetition_count(&self) -> usize {
+        let current = self.current_state();
+        let last = self.states.len() - 1;
+        let window = (current.reversible_move_counter as usize).min(last);
+        let mut count = 1;
+        let mut distance = 2;
+        while distance <= window {
+            if self.states[last - distance].zobrist_hash == current.zobrist_hash {
+                count += 1;
+            }
+            distance += 2;
         }
+        count
     }
 
+    // takes back the last move, the start position stays
     pub fn undo_move(&mut self) -> Option<GameState> {
-        if self.length > 1 {
-            let state = self.list[self.length - 1];
-            self.length -= 1;
-            Some(state)
+        if self.states.len() > 1 {
+            self.states.pop()
         } else {
             None
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::STANDARD_CASTLING_ROOKS;
+
+    #[test]
+    fn test_history_grows_beyond_initial_capacity() {
+        let mut history = GameHistory::new();
+        // Ng1f3 and back, over and over
+        let moves = [6 | (21 << 6), 62 | (45 << 6), 21 | (6 << 6), 45 | (62 << 6)];
+        for mv in moves.iter().cycle().take(2 * INITIAL_CAPACITY) {
+            history.record_move(
+                *mv,
+                Piece::WhiteKnight,
+                Piece::Empty,
+                STANDARD_CASTLING_ROOKS,
+                0,
+            );
+        }
+        assert_eq!(history.len(), 2 * INITIAL_CAPACITY + 1);
+        for _ in 0..2 * INITIAL_CAPACITY {
+            assert!(history.undo_move().is_some());
+        }
+        assert_eq!(history.undo_move(), None);
+        assert_eq!(history.current_state(), GameState::new());
+    }
+}
@@ -0,0 +1,76 @@
+// The UCI binary never ends games itself, these types are for match tooling
+// and GUIs that link against the engine.
+
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Termination {
+    Checkmate,
+    Stalemate,
+    InsufficientMaterial,
+    // drawn automatically
+    FivefoldRepetition,
+    SeventyFiveMoveRule,
+    // a draw that one of the players can claim
+    ThreefoldRepetition,
+    FiftyMoveRule,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Outcome {
+    pub result: GameResult,
+    pub termination: Termination,
+}
+
+impl GameResult {
+    pub fn winner_is_white(&self) -> Option<bool> {
+        match self {
+            GameResult::WhiteWins => Some(true),
+            GameResult::BlackWins => Some(false),
+            GameResult::Draw => None,
+        }
+    }
+}
+
+impl Termination {
+    // whether the game only ends if a player claims the draw
+    pub fn is_claimable(&self) -> bool {
+        matches!(
+            self,
+            Termination::ThreefoldRepetition | Termination::FiftyMoveRule
+        )
+    }
+}
+
+// result notation as used in PGN files
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameResult::WhiteWins => write!(f, "1-0"),
+            GameResult::BlackWins => write!(f, "0-1"),
+            GameResult::Draw => write!(f, "1/2-1/2"),
+        }
+    }
+}
+
+impl fmt::Display for Termination {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let description = match self {
+            Termination::Checkmate => "checkmate",
+            Termination::Stalemate => "stalemate",
+            Termination::InsufficientMaterial => "insufficient material",
+            Termination::FivefoldRepetition => "fivefold repetition",
+            Termination::SeventyFiveMoveRule => "75-move rule",
+            Termination::ThreefoldRepetition => "threefold repetition",
+            Termination::FiftyMoveRule => "50-move rule",
+        };
+        write!(f, "{}", description)
+    }
+}
@@ -1,4 +1,6 @@
 use crate::constants::Piece;
+use crate::fen::{FenError, square_to_string};
+use crate::zobrist::Zobrist;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct GameState {
@@ -16,7 +18,14 @@ pub struct GameState {
     pub reversible_move_counter: u8,
     pub full_move_counter: u16,
 
-    pub zobrist_hash: u64, // TODO: add zobrist hash to game state
+    // hash of the full position, the piece placement part is maintained by the board
+    pub zobrist_hash: u64,
+}
+
+impl Default for GameState {
+    fn default() -> Self {
+        GameState::new()
+    }
 }
 
 impl GameState {
@@ -33,10 +42,13 @@ impl GameState {
         }
     }
 
-    pub fn from_fen(fen: &str) -> Result<Self, String> {
+    // Parses the side to move, castling rights, en passant square and clocks
+    // of a FEN string. Consistency with the piece placement is checked by
+    // `Game::from_fen`.
+    pub fn from_fen(fen: &str) -> Result<Self, FenError> {
         let parts: Vec<&str> = fen.split_whitespace().collect();
         if parts.len() != 6 {
-            return Err("Invalid FEN string".to_string());
+            return Err(FenError::WrongFieldCount(parts.len()));
         }
 
         let mut state = GameState::new();
@@ -45,56 +57,95 @@ impl GameState {
         state.white_to_move = match parts[1] {
             "w" => true,
             "b" => false,
-            _ => return Err("Invalid FEN string".to_string()),
+            side => return Err(FenError::InvalidSideToMove(side.to_string())),
         };
 
-        // Part 3: Castling availability
+        // Part 3: Castling availability. Which rook a right belongs to
+        // depends on the piece placement in Chess960, so only the syntax is
+        // checked here and `Game::from_fen` sets the rights.
         state.castling_rights = 0;
-        for c in parts[2].chars() {
-            match c {
-                'K' => state.castling_rights |= 0x01,
-                'Q' => state.castling_rights |= 0x02,
-                'k' => state.castling_rights |= 0x04,
-                'q' => state.castling_rights |= 0x08,
-                '-' => (),
-                _ => return Err("Invalid FEN string".to_string()),
+        if parts[2] != "-" {
+            for (index, c) in parts[2].char_indices() {
+                if !matches!(c, 'K' | 'Q' | 'k' | 'q' | 'A'..='H' | 'a'..='h')
+                    || parts[2][..index].contains(c)
+                {
+                    return Err(FenError::InvalidCastlingRights(parts[2].to_string()));
+                }
             }
         }
 
         // Part 4: En passant target square
         if parts[3] != "-" {
-            let file = parts[3].chars().next().unwrap() as u8 - 'a' as u8;
-            let rank = parts[3].chars().nth(1).unwrap() as u8 - '1' as u8;
-            state.en_passant_square = Some(file + rank * 8);
+            let square = parts[3].as_bytes();
+            if square.len() != 2
+                || !(b'a'..=b'h').contains(&square[0])
+                || !(b'1'..=b'8').contains(&square[1])
+            {
+                return Err(FenError::InvalidEnPassantSquare(parts[3].to_string()));
+            }
+            state.en_passant_square = Some(square[0] - b'a' + (square[1] - b'1') * 8);
         }
 
         // Part 5: Halfmove clock
-        state.reversible_move_counter = parts[4].parse().unwrap();
+        state.reversible_move_counter = parts[4]
+            .parse()
+            .map_err(|_| FenError::InvalidHalfmoveClock(parts[4].to_string()))?;
 
         // Part 6: Fullmove number
-        state.full_move_counter = parts[5].parse().unwrap();
+        state.full_move_counter = match parts[5].parse() {
+            Ok(number) if number >= 1 => number,
+            _ => return Err(FenError::InvalidFullmoveNumber(parts[5].to_string())),
+        };
         Ok(state)
     }
 
-    pub fn after_move(&self, mv: u16, captured_piece: Piece) -> GameState {
+    // The side to move, castling, en passant and clock fields of a FEN
+    // string. `castling` holds the symbol of each castling right, which
+    // depends on the rooks on the board.
+    pub fn to_fen(self, castling: [char; 4]) -> String {
+        let side = if self.white_to_move { "w" } else { "b" };
+        let mut rights: String = (0..4)
+            .filter(|right| self.castling_rights & (1 << right) != 0)
+            .map(|right| castling[right])
+            .collect();
+        if rights.is_empty() {
+            rights.push('-');
+        }
+        let en_passant = match self.en_passant_square {
+            Some(square) => square_to_string(square as usize),
+            None => "-".to_string(),
+        };
+        format!(
+            "{} {} {} {} {}",
+            side, rights, en_passant, self.reversible_move_counter, self.full_move_counter
+        )
+    }
+
+    // `castling_rooks` are the squares of the castling rooks, see `Board`
+    pub fn after_move(
+        &self,
+        mv: u16,
+        moving_piece: Piece,
+        captured_piece: Piece,
+        castling_rooks: [u8; 4],
+    ) -> GameState {
         let mut new_state = *self;
         new_state.current_move = Some(mv);
         new_state.captured_piece = captured_piece;
         new_state.white_to_move = !self.white_to_move;
-        new_state.reversible_move_counter += 1;
+        new_state.reversible_move_counter = self.reversible_move_counter.saturating_add(1);
         new_state.full_move_counter += if !self.white_to_move { 1 } else { 0 };
 
-        // Reset reversible move counter on pawn move or capture
-        if mv & 0xC000 != 0 || mv & 0xF000 == 0x1000 {
-            // TODO: This only checks captures and promotions and double pawn push,
-            // need to check for pawn moves as well
+        // Reset reversible move counter on pawn move or capture (including en passant)
+        let is_pawn_move = matches!(moving_piece, Piece::WhitePawn | Piece::BlackPawn);
+        if mv & 0x4000 != 0 || is_pawn_move {
             new_state.reversible_move_counter = 0;
         }
 
         // Check double pawn push
         if mv & 0xF000 == 0x1000 {
             let to = ((mv >> 6) & 0x003F) as u8;
-            if to <= 32 {
+            if to < 32 {
                 // White pawn double push
                 new_state.en_passant_square = Some(to - 8);
             } else {
@@ -105,17 +156,45 @@ impl GameState {
             new_state.en_passant_square = None;
         }
 
-        // Update castling rights if a rook or king moves
-        let from = (mv & 0x003F) as usize;
-        match from {
-            0 => new_state.castling_rights &= !0x02, // White queenside rook
-            4 => new_state.castling_rights &= !0x03, // White king
-            7 => new_state.castling_rights &= !0x01, // White kingside rook
-            56 => new_state.castling_rights &= !0x08, // Black queenside rook
-            60 => new_state.castling_rights &= !0x0C, // Black king
-            63 => new_state.castling_rights &= !0x04, // Black kingside rook
+        // Update castling rights if a rook or king moves, or a rook is captured
+        match moving_piece {
+            Piece::WhiteKing => new_state.castling_rights &= !0x03,
+            Piece::BlackKing => new_state.castling_rights &= !0x0C,
             _ => {}
         }
+        let from = (mv & 0x003F) as u8;
+        let to = ((mv >> 6) & 0x003F) as u8;
+        for (right, rook_square) in castling_rooks.into_iter().enumerate() {
+            if from == rook_square || to == rook_square {
+                new_state.castling_rights &= !(1 << right);
+            }
+        }
+
+        // update the hash for everything but the pieces, which the board tracks
+        let keys = Zobrist::keys();
+        new_state.zobrist_hash ^= keys.side_to_move_key()
+            ^ keys.castling_key(self.castling_rights)
+            ^ keys.castling_key(new_state.castling_rights)
+            ^ keys.en_passant_key(self.en_passant_square)
+            ^ keys.en_passant_key(new_state.en_passant_square);
+        new_state
+    }
+
+    // Passes the move to the other side. The search uses this to find out
+    // whether a position is good enough without moving at all.
+    pub fn after_null_move(&self) -> GameState {
+        let mut new_state = *self;
+        new_state.current_move = None;
+        new_state.captured_piece = Piece::Empty;
+        new_state.white_to_move = !self.white_to_move;
+        // positions before the null move cannot count as repetitions
+        new_state.reversible_move_counter = 0;
+        new_state.full_move_counter += if !self.white_to_move { 1 } else { 0 };
+        new_state.en_passant_square = None;
+
+        let keys = Zobrist::keys();
+        new_state.zobrist_hash ^=
+            keys.side_to_move_key() ^ keys.en_passant_key(self.en_passant_square);
         new_state
     }
 }
@@ -123,13 +202,15 @@ impl GameState {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::board::STANDARD_CASTLING_ROOKS;
 
     #[test]
     fn test_from_fen() {
         let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
         let state = GameState::from_fen(fen).unwrap();
-        assert_eq!(state.white_to_move, true);
-        assert_eq!(state.castling_rights, 0x0F);
+        assert!(state.white_to_move);
+        // the castling rights are set by `Game::from_fen`
+        assert_eq!(state.castling_rights, 0);
         assert_eq!(state.en_passant_square, None);
         assert_eq!(state.reversible_move_counter, 0);
         assert_eq!(state.full_move_counter, 1);
@@ -140,8 +221,9 @@ mod tests {
         let state = GameState::new();
         // Move: e2e4 (double pawn push)
         let mv: u16 = 0x1000 | (28 << 6) | 12;
-        let new_state = state.after_move(mv, Piece::Empty);
-        assert_eq!(new_state.white_to_move, false);
+        let new_state =
+            state.after_move(mv, Piece::WhitePawn, Piece::Empty, STANDARD_CASTLING_ROOKS);
+        assert!(!new_state.white_to_move);
         assert_eq!(new_state.en_passant_square, Some(20));
         assert_eq!(new_state.reversible_move_counter, 0);
         assert_eq!(new_state.full_move_counter, 1);
@@ -152,7 +234,51 @@ mod tests {
         let state = GameState::new();
         // Move: e1g1 (white kingside castle)
         let mv: u16 = 0x2000 | (6 << 6) | 4;
-        let new_state = state.after_move(mv, Piece::Empty);
+        let new_state =
+            state.after_move(mv, Piece::WhiteKing, Piece::Empty, STANDARD_CASTLING_ROOKS);
         assert_eq!(new_state.castling_rights & 0x03, 0); // White castling rights removed
     }
+
+    #[test]
+    fn test_after_move_chess960_castling_rights() {
+        let state = GameState::new();
+        // rooks on b1, g1, b8 and g8, Rb1b2 only loses white queenside castling
+        let castling_rooks = [6, 1, 62, 57];
+        let mv: u16 = 1 | (9 << 6);
+        let new_state = state.after_move(mv, Piece::WhiteRook, Piece::Empty, castling_rooks);
+        assert_eq!(new_state.castling_rights, 0x0D);
+        // the rook on a1 is not a castling rook
+        let new_state = state.after_move(8 << 6, Piece::WhiteRook, Piece::Empty, castling_rooks);
+        assert_eq!(new_state.castling_rights, 0x0F);
+    }
+
+    #[test]
+    fn test_after_move_reversible_move_counter() {
+        let mut state = GameState::new();
+        state.reversible_move_counter = 10;
+        // Ng1f3 is reversible
+        let new_state = state.after_move(
+            6 | (21 << 6),
+            Piece::WhiteKnight,
+            Piece::Empty,
+            STANDARD_CASTLING_ROOKS,
+        );
+        assert_eq!(new_state.reversible_move_counter, 11);
+        // e2e3 is a quiet pawn move and resets the counter
+        let new_state = state.after_move(
+            12 | (20 << 6),
+            Piece::WhitePawn,
+            Piece::Empty,
+            STANDARD_CASTLING_ROOKS,
+        );
+        assert_eq!(new_state.reversible_move_counter, 0);
+        // a capture resets the counter
+        let new_state = state.after_move(
+            0x4000 | 6 | (21 << 6),
+            Piece::WhiteKnight,
+            Piece::BlackPawn,
+            STANDARD_CASTLING_ROOKS,
+        );
+        assert_eq!(new_state.reversible_move_counter, 0);
+    }
 }
@@ -1,19 +1,89 @@
-use crate::constants::Piece;
+use crate::book::{Book, BookSelection};
+use crate::evaluation::evaluation_breakdown;
 use crate::game::Game;
-use crate::zobrist::Zobrist;
-use rand::seq::IndexedRandom;
+use crate::search::{
+    DEFAULT_TB_PROBE_DEPTH, MATE_SCORE, MATE_THRESHOLD, Search, SearchLimits, SearchReport,
+    SearchSignals,
+};
+use crate::search_parameters::ParameterError;
+use crate::syzygy::Tablebases;
+use crate::time_manager::DEFAULT_MOVE_OVERHEAD_MS;
+use crate::transposition_table::{Bound, DEFAULT_HASH_SIZE_MB};
+use crate::types::Move;
 use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// search depth of a go command without any limits
+const DEFAULT_SEARCH_DEPTH: u32 = 5;
+const MAX_HASH_SIZE_MB: usize = 16384;
+const MAX_MULTI_PV: usize = 256;
+const MAX_MOVE_OVERHEAD_MS: u64 = 5000;
+// last move number for which the opening book is consulted
+const DEFAULT_BOOK_DEPTH: u16 = 20;
+const MAX_BOOK_DEPTH: u16 = 255;
+const MAX_TB_PROBE_DEPTH: u64 = 100;
+// nodes searched per position by the bench command
+const DEFAULT_BENCH_NODES: u64 = 200_000;
+const BENCH_POSITIONS: [&str; 8] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    "r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP3PPP/R2QKB1R w KQ - 2 8",
+    "8/8/4k3/3p4/3P1K2/8/8/8 w - - 0 1",
+];
 
 pub struct UciInterface {
-    game: Option<Game>,
-    zobrist: Zobrist,
+    game: Game,
+    // the search is moved to the worker thread while it runs and handed
+    // back when the thread is joined
+    search: Option<Search>,
+    worker: Option<JoinHandle<Search>>,
+    signals: Arc<SearchSignals>,
+    // the running search only ends with stop
+    infinite_search: bool,
+    // print additional information as info strings
+    debug: bool,
+    // the book is only used with OwnBook, up to move number `book_depth`
+    own_book: bool,
+    book_file: Option<String>,
+    book: Option<Book>,
+    book_depth: u16,
+    book_best_move: bool,
+    // directories of the endgame tablebases, the search gets the tables
+    syzygy_path: Option<String>,
+    // UCI_Chess960, castling moves are sent as the king taking its own rook
+    chess960: bool,
+}
+
+impl Default for UciInterface {
+    fn default() -> Self {
+        UciInterface::new()
+    }
 }
 
 impl UciInterface {
     pub fn new() -> Self {
+        let search = Search::new();
         UciInterface {
-            game: None,
-            zobrist: Zobrist::new(),
+            game: Game::new(),
+            signals: search.signals(),
+            search: Some(search),
+            worker: None,
+            infinite_search: false,
+            debug: false,
+            own_book: false,
+            book_file: None,
+            book: None,
+            book_depth: DEFAULT_BOOK_DEPTH,
+            book_best_move: false,
+            syzygy_path: None,
+            chess960: false,
         }
     }
 
@@ -21,230 +91,853 @@ impl UciInterface {
         let mut input = String::new();
         loop {
             input.clear();
-            io::stdin().read_line(&mut input).unwrap();
+            // at the end of the input, e.g. of a script, the last search may
+            // still finish
+            if !matches!(io::stdin().read_line(&mut input), Ok(bytes) if bytes > 0) {
+                self.wait_for_search();
+                break;
+            }
             let command = input.trim();
-            match command {
+            let Some(keyword) = command.split_whitespace().next() else {
+                continue;
+            };
+            match keyword {
                 "quit" => {
                     break;
                 }
+                "stop" => {
+                    self.stop_search();
+                }
+                "ponderhit" => {
+                    self.signals.ponderhit();
+                }
                 "uci" => {
                     println!("id name rusty_chess");
                     println!("id author Daniel Kirch");
+                    UciInterface::print_options();
                     println!("uciok");
                 }
                 "isready" => {
                     println!("readyok");
                 }
-                pos_str if pos_str.starts_with("position") => {
-                    // Handle position command
-                    self.parse_position_command(pos_str);
+                "ucinewgame" => {
+                    self.wait_for_search();
+                    self.search.as_mut().unwrap().new_game();
+                    self.game = Game::new();
+                    self.game.set_chess960(self.chess960);
+                }
+                "debug" => match command.split_whitespace().nth(1) {
+                    Some("on") => self.debug = true,
+                    Some("off") => self.debug = false,
+                    _ => println!("info string expected debug on or off"),
+                },
+                "setoption" => {
+                    self.parse_setoption_command(command);
+                }
+                "position" => {
+                    self.parse_position_command(command);
+                }
+                "go" => {
+                    let limits = self.parse_go_command(command);
+                    match self.book_move(&limits) {
+                        Some(mv) => {
+                            self.wait_for_search();
+                            println!("bestmove {}", self.game.move_to_uci(mv));
+                        }
+                        None => self.start_search(limits),
+                    }
+                }
+                // commands for developers that are not part of the protocol
+                "d" => {
+                    self.print_position();
                 }
-                go_str if go_str.starts_with("go") => {
-                    // Handle go command
-                    self.make_move();
+                "eval" => {
+                    self.print_evaluation();
+                }
+                "perft" => match command.split_whitespace().nth(1).map(str::parse) {
+                    Some(Ok(depth)) => self.perft(depth),
+                    _ => println!("info string expected perft <depth>"),
+                },
+                "bench" => match command.split_whitespace().nth(1).map(str::parse) {
+                    None => self.bench(DEFAULT_BENCH_NODES),
+                    Some(Ok(nodes)) => self.bench(nodes),
+                    Some(Err(_)) => println!("info string expected bench [nodes]"),
+                },
+                "flip" => {
+                    self.game = self.game.flipped();
                 }
                 _ => {
-                    println!("Unknown command: {}", command);
+                    println!("info string unknown command {}", command);
                 }
             }
         }
+        self.stop_search();
     }
 
-    fn parse_position_command(&mut self, command: &str) {
-        // This parses the position command. If successful, it sets up the game state.
-        // Example: position startpos moves e2e4 e7e5
-        if !command.starts_with("position") {
+    fn print_options() {
+        println!(
+            "option name Hash type spin default {} min 1 max {}",
+            DEFAULT_HASH_SIZE_MB, MAX_HASH_SIZE_MB
+        );
+        // the search is single threaded
+        println!("option name Threads type spin default 1 min 1 max 1");
+        println!(
+            "option name MultiPV type spin default 1 min 1 max {}",
+            MAX_MULTI_PV
+        );
+        println!(
+            "option name Move Overhead type spin default {} min 0 max {}",
+            DEFAULT_MOVE_OVERHEAD_MS, MAX_MOVE_OVERHEAD_MS
+        );
+        println!("option name Clear Hash type button");
+        println!("option name Ponder type check default false");
+        println!("option name OwnBook type check default false");
+        println!("option name BookFile type string default <empty>");
+        println!(
+            "option name BookDepth type spin default {} min 1 max {}",
+            DEFAULT_BOOK_DEPTH, MAX_BOOK_DEPTH
+        );
+        println!("option name BookBestMove type check default false");
+        println!("option name UCI_Chess960 type check default false");
+        println!("option name SyzygyPath type string default <empty>");
+        println!(
+            "option name SyzygyProbeDepth type spin default {} min 1 max {}",
+            DEFAULT_TB_PROBE_DEPTH, MAX_TB_PROBE_DEPTH
+        );
+    }
+
+    fn parse_setoption_command(&mut self, command: &str) {
+        // Example: setoption name Move Overhead value 100
+        let Some(arguments) = command["setoption".len()..].trim().strip_prefix("name") else {
+            println!("info string invalid setoption command {}", command);
             return;
+        };
+        let (name, value) = match arguments.split_once(" value") {
+            Some((name, value)) => (name.trim(), value.trim()),
+            None => (arguments.trim(), ""),
+        };
+        // options can only change while no search is running
+        self.wait_for_search();
+        let search = self.search.as_mut().unwrap();
+
+        let spin = |min: u64, max: u64| match value.parse::<u64>() {
+            Ok(number) if (min..=max).contains(&number) => Some(number),
+            _ => {
+                println!("info string invalid value '{}' for option {}", value, name);
+                None
+            }
+        };
+        let check = || match value {
+            "true" => Some(true),
+            "false" => Some(false),
+            _ => {
+                println!("info string invalid value '{}' for option {}", value, name);
+                None
+            }
+        };
+        let string = || match value {
+            "" | "<empty>" => None,
+            path => Some(path.to_string()),
+        };
+
+        match name.to_lowercase().as_str() {
+            "hash" => {
+                if let Some(size_mb) = spin(1, MAX_HASH_SIZE_MB as u64) {
+                    search.set_hash_size(size_mb as usize);
+                }
+            }
+            "threads" => {
+                spin(1, 1);
+            }
+            "multipv" => {
+                if let Some(multi_pv) = spin(1, MAX_MULTI_PV as u64) {
+                    search.set_multi_pv(multi_pv as usize);
+                }
+            }
+            "move overhead" => {
+                if let Some(overhead) = spin(0, MAX_MOVE_OVERHEAD_MS) {
+                    search.set_move_overhead(Duration::from_millis(overhead));
+                }
+            }
+            "clear hash" => search.clear_hash(),
+            // go ponder works regardless, the option only tells us the GUI may use it
+            "ponder" => {
+                check();
+            }
+            "ownbook" => {
+                if let Some(own_book) = check() {
+                    self.own_book = own_book;
+                }
+            }
+            "bookfile" => {
+                self.book_file = string();
+                self.book = self
+                    .book_file
+                    .as_ref()
+                    .and_then(|path| match Book::open(path) {
+                        Ok(book) => {
+                            if self.debug {
+                                println!("info string book {} has {} entries", path, book.len());
+                            }
+                            Some(book)
+                        }
+                        Err(error) => {
+                            println!("info string could not open book {}: {}", path, error);
+                            None
+                        }
+                    });
+            }
+            "bookdepth" => {
+                if let Some(depth) = spin(1, MAX_BOOK_DEPTH as u64) {
+                    self.book_depth = depth as u16;
+                }
+            }
+            "bookbestmove" => {
+                if let Some(best_move) = check() {
+                    self.book_best_move = best_move;
+                }
+            }
+            "uci_chess960" => {
+                if let Some(chess960) = check() {
+                    self.chess960 = chess960;
+                    self.game.set_chess960(chess960);
+                }
+            }
+            "syzygypath" => {
+                self.syzygy_path = string();
+                let tablebases = self.syzygy_path.as_deref().map(Tablebases::open);
+                if let Some(tablebases) = &tablebases {
+                    println!(
+                        "info string found {} tablebases with up to {} pieces",
+                        tablebases.len(),
+                        tablebases.max_pieces()
+                    );
+                }
+                search.set_tablebases(tablebases.filter(|tablebases| !tablebases.is_empty()));
+            }
+            "syzygyprobedepth" => {
+                if let Some(depth) = spin(1, MAX_TB_PROBE_DEPTH) {
+                    search.set_tb_probe_depth(depth as u32);
+                }
+            }
+            // the search parameters are not listed by uci, they are only
+            // meant for tuning and testing changes to the search
+            _ => match search.set_parameter(name, value) {
+                Ok(()) => {}
+                Err(ParameterError::InvalidValue(_)) => {
+                    println!("info string invalid value '{}' for option {}", value, name)
+                }
+                Err(ParameterError::UnknownParameter(_)) => {
+                    println!("info string unknown option {}", name)
+                }
+            },
         }
+    }
 
-        let parts: Vec<&str> = command[8..].split("moves").collect();
-        // trim all parts
-        let parts: Vec<&str> = parts.iter().map(|s| s.trim()).collect();
+    fn parse_position_command(&mut self, command: &str) {
+        // This parses the position command. If successful, it sets up the game state.
+        // Example: position startpos moves e2e4 e7e5
+        let arguments = command["position".len()..].trim();
+        let (setup, moves) = match arguments.split_once("moves") {
+            Some((setup, moves)) => (setup.trim(), moves),
+            None => (arguments, ""),
+        };
 
         // Set up initial position
-        if parts[0] == "startpos" {
-            // Set up starting position
-            self.game = Some(Game::new());
-        } else if parts[0].starts_with("fen") {
-            // Set up position from FEN
-            let fen = parts[0][4..].trim();
-            self.game = Some(Game::from_fen(fen));
+        let mut game = if setup == "startpos" {
+            Game::new()
+        } else if let Some(fen) = setup.strip_prefix("fen") {
+            match Game::from_fen(fen.trim()) {
+                Ok(game) => game,
+                Err(error) => {
+                    println!("info string invalid fen: {}", error);
+                    return;
+                }
+            }
         } else {
-            println!("info invalid position command {}", command);
+            println!("info string invalid position command {}", command);
             return;
+        };
+        game.set_chess960(self.chess960);
+
+        // Apply moves up to the first illegal one
+        for mv_str in moves.split_whitespace() {
+            match game.parse_uci(mv_str) {
+                Some(mv) => game.make_move(mv.raw()),
+                None => {
+                    println!("info string illegal move {}", mv_str);
+                    break;
+                }
+            }
+        }
+        self.game = game;
+        if self.debug {
+            println!("info string position {}", self.game.to_fen());
         }
+    }
 
-        // Apply moves if any
-        if parts.len() > 1 {
-            let moves: Vec<&str> = parts[1].split_whitespace().collect();
-            for mv_str in moves {
-                let mv = self.parse_move_string(mv_str);
-                if let Some(mv) = mv {
-                    let mut game: Game = self.game.unwrap();
-                    game.make_move(mv);
-                    self.game = Some(game);
-                } else {
-                    println!("info invalid move: {}", mv_str);
+    fn parse_go_command(&self, command: &str) -> SearchLimits {
+        // Example: go wtime 300000 btime 300000 winc 2000 binc 2000
+        let mut limits = SearchLimits::default();
+        let mut tokens = command.split_whitespace().skip(1).peekable();
+        while let Some(token) = tokens.next() {
+            match token {
+                "infinite" => limits.infinite = true,
+                "ponder" => limits.ponder = true,
+                "searchmoves" => {
+                    // all following tokens that are legal moves
+                    while let Some(mv) =
+                        tokens.peek().and_then(|mv_str| self.game.parse_uci(mv_str))
+                    {
+                        limits.search_moves.push(mv);
+                        tokens.next();
+                    }
                 }
+                "wtime" | "btime" | "winc" | "binc" | "movetime" => {
+                    // some GUIs send negative times when the clock ran out
+                    let Some(value) = tokens.next().and_then(|value| value.parse::<i64>().ok())
+                    else {
+                        println!("info string invalid value for {}", token);
+                        continue;
+                    };
+                    let time = Some(Duration::from_millis(value.max(0) as u64));
+                    match token {
+                        "wtime" => limits.white_time = time,
+                        "btime" => limits.black_time = time,
+                        "winc" => limits.white_increment = time,
+                        "binc" => limits.black_increment = time,
+                        _ => limits.move_time = time,
+                    }
+                }
+                "movestogo" | "depth" | "nodes" | "mate" => {
+                    let Some(value) = tokens.next().and_then(|value| value.parse::<u64>().ok())
+                    else {
+                        println!("info string invalid value for {}", token);
+                        continue;
+                    };
+                    match token {
+                        "movestogo" => limits.moves_to_go = Some(value),
+                        "depth" => limits.depth = Some(value.min(u32::MAX as u64) as u32),
+                        "nodes" => limits.nodes = Some(value),
+                        _ => limits.mate = Some(value.min(u32::MAX as u64) as u32),
+                    }
+                }
+                _ => println!("info string unknown go parameter {}", token),
             }
         }
 
-        println!("info position set up");
-        println!(
-            "info current board position: {:?}",
-            self.game.unwrap().board.piece_list
-        );
+        let unlimited = !limits.infinite
+            && !limits.ponder
+            && limits.white_time.is_none()
+            && limits.black_time.is_none()
+            && limits.move_time.is_none()
+            && limits.depth.is_none()
+            && limits.nodes.is_none()
+            && limits.mate.is_none();
+        if unlimited {
+            limits.depth = Some(DEFAULT_SEARCH_DEPTH);
+        }
+        if self.debug {
+            println!("info string search limits {:?}", limits);
+        }
+        limits
     }
 
-    fn parse_move_string(&self, mv_str: &str) -> Option<u16> {
-        let board = self.game.unwrap().board;
-
-        // Convert move string like "e2e4" to move bitmap u16
-        if mv_str.len() < 4 {
+    // A move from the opening book, if it is used for this search. Analysis
+    // and pondering always search.
+    fn book_move(&self, limits: &SearchLimits) -> Option<Move> {
+        let book = self.book.as_ref()?;
+        if !self.own_book
+            || limits.infinite
+            || limits.ponder
+            || self.game.history.current_state().full_move_counter > self.book_depth
+        {
+            return None;
+        }
+        let selection = if self.book_best_move {
+            BookSelection::Best
+        } else {
+            BookSelection::Weighted
+        };
+        let mv = book.probe(&self.game, selection)?;
+        if !limits.search_moves.is_empty() && !limits.search_moves.contains(&mv) {
             return None;
         }
-        let from_file = mv_str.chars().nth(0).unwrap() as u8 - 'a' as u8;
-        let from_rank = mv_str.chars().nth(1).unwrap() as u8 - '1' as u8;
-        let to_file = mv_str.chars().nth(2).unwrap() as u8 - 'a' as u8;
-        let to_rank = mv_str.chars().nth(3).unwrap() as u8 - '1' as u8;
+        if self.debug {
+            println!("info string book move {}", self.game.move_to_uci(mv));
+        }
+        Some(mv)
+    }
 
-        let from = (from_rank * 8 + from_file) as usize;
-        let to = (to_rank * 8 + to_file) as usize;
+    fn print_position(&self) {
+        print!("{}", self.game.board.to_diagram());
+        println!("Fen: {}", self.game.to_fen());
+        println!("Key: {:016X}", self.game.zobrist_hash());
+    }
 
-        // Check for promotion
-        let promotion = if mv_str.len() == 5 {
-            match mv_str.chars().nth(4).unwrap() {
-                'q' => 0x8000,
-                'r' => 0x9000,
-                'b' => 0xA000,
-                'n' => 0xB000,
-                _ => 0x0000,
+    fn print_evaluation(&self) {
+        let breakdown = evaluation_breakdown(&self.game.board);
+        let [white_material, black_material] = breakdown.material;
+        let [white_piece_square, black_piece_square] = breakdown.piece_square;
+        let [white_hanging, black_hanging] = breakdown.hanging;
+        println!("         Term |  White |  Black |  Total");
+        println!(
+            "     Material | {:6} | {:6} | {:6}",
+            white_material,
+            black_material,
+            white_material - black_material
+        );
+        println!(
+            " Piece-square | {:6} | {:6} | {:6}",
+            white_piece_square,
+            black_piece_square,
+            white_piece_square - black_piece_square
+        );
+        println!(
+            "      Hanging | {:6} | {:6} | {:6}",
+            white_hanging,
+            black_hanging,
+            white_hanging - black_hanging
+        );
+        println!(
+            "Phase: {}",
+            if breakdown.endgame {
+                "endgame"
+            } else {
+                "middlegame"
             }
-        } else {
-            0x0000
-        };
+        );
+        println!("Evaluation: {} (white side)", breakdown.total());
+    }
 
-        // Check for capture
-        let capture = if board.piece_list[to] != Piece::Empty {
-            0x4000
-        } else {
-            0x0000
-        };
+    fn perft(&mut self, depth: u32) {
+        self.wait_for_search();
+        let start = Instant::now();
+        let mut game = self.game.clone();
+        let mut divide: Vec<(String, u64)> = game
+            .perft_divide(depth)
+            .into_iter()
+            .map(|(mv, nodes)| (game.move_to_uci(mv), nodes))
+            .collect();
+        divide.sort();
+        for (mv, nodes) in &divide {
+            println!("{}: {}", mv, nodes);
+        }
+        let nodes: u64 = divide.iter().map(|(_, nodes)| nodes).sum();
+        let milliseconds = start.elapsed().as_millis() as u64;
+        println!();
+        println!("Nodes searched: {}", nodes);
+        println!("Time (ms): {}", milliseconds);
+        println!("Nodes/second: {}", nodes * 1000 / milliseconds.max(1));
+    }
 
-        // Check for en passant
-        let game_state = self.game.unwrap().history.current_state();
-        let is_pawn = match board.piece_list[from] {
-            Piece::WhitePawn | Piece::BlackPawn => true,
-            _ => false,
-        };
-        let en_passant = if to as u8 == game_state.en_passant_square.unwrap_or(64) && is_pawn {
-            0x5000
-        } else {
-            0x0000
+    // Searches a fixed number of nodes in each of the bench positions with an
+    // empty hash table, the total node count identifies the search version.
+    fn bench(&mut self, nodes: u64) {
+        self.wait_for_search();
+        let mut search = Search::new();
+        let limits = SearchLimits {
+            nodes: Some(nodes),
+            ..SearchLimits::default()
         };
+        let start = Instant::now();
+        let mut total_nodes = 0;
+        for (index, fen) in BENCH_POSITIONS.iter().enumerate() {
+            let mut game = Game::from_fen(fen).unwrap();
+            search.new_game();
+            let result = search.iterative_deepening(&mut game, &limits);
+            println!(
+                "Position {}/{}: {} nodes, depth {}",
+                index + 1,
+                BENCH_POSITIONS.len(),
+                result.nodes,
+                result.depth
+            );
+            total_nodes += search.nodes();
+        }
+        let milliseconds = start.elapsed().as_millis() as u64;
+        println!();
+        println!("Total time (ms): {}", milliseconds);
+        println!("Nodes searched: {}", total_nodes);
+        println!("Nodes/second: {}", total_nodes * 1000 / milliseconds.max(1));
+    }
 
-        // Check for double pawn push
-        let double_pawn_push = if is_pawn && ((from as i8 - to as i8).abs() == 16) {
-            0x1000
-        } else {
-            0x0000
-        };
+    // Starts searching the current position on the worker thread, which
+    // prints the best move when it is done.
+    fn start_search(&mut self, limits: SearchLimits) {
+        self.wait_for_search();
+        self.infinite_search = limits.infinite;
+        let game = self.game.clone();
+        let mut search = self.search.take().unwrap();
+        let root = game.clone();
+        search.set_reporter(move |report| UciInterface::print_report(&root, report));
+        let signals = Arc::clone(&self.signals);
+        signals.reset(limits.ponder);
+        self.worker = Some(thread::spawn(move || {
+            let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+                let mut game = game.clone();
+                let result = search.iterative_deepening(&mut game, &limits);
+                let ponder_move = search.ponder_move(&mut game, &result);
+                (result.best_move, ponder_move)
+            }));
+            // a bug in the search must not lose the game, any legal move is
+            // better than none
+            let (best_move, ponder_move) = outcome.unwrap_or_else(|_| {
+                println!("info string search failed");
+                (game.legal_moves().first().copied(), None)
+            });
+            // while pondering or in infinite mode the best move may only be
+            // sent after stop or ponderhit, even if the search ended early
+            while (limits.infinite || signals.is_pondering()) && !signals.is_stopped() {
+                thread::sleep(Duration::from_millis(1));
+            }
+            match (best_move, ponder_move) {
+                (Some(mv), Some(ponder)) => println!(
+                    "bestmove {} ponder {}",
+                    game.move_to_uci(mv),
+                    game.move_to_uci(ponder)
+                ),
+                (Some(mv), None) => println!("bestmove {}", game.move_to_uci(mv)),
+                (None, _) => println!("bestmove (none)"),
+            }
+            search
+        }));
+    }
 
-        // Check for castling
-        let is_king = match board.piece_list[from] {
-            Piece::WhiteKing | Piece::BlackKing => true,
-            _ => false,
-        };
-        let castle = if is_king {
-            match from as i8 - to as i8 {
-                -2 => 0x2000, // kingside
-                2 => 0x3000,  // queenside
-                _ => 0x0000,
+    // `game` is the position the search started from, which tells how to
+    // write castling moves
+    fn print_report(game: &Game, report: &SearchReport) {
+        match report {
+            SearchReport::Line {
+                depth,
+                seldepth,
+                multipv,
+                score,
+                bound,
+                nodes,
+                time,
+                hashfull,
+                tb_hits,
+                pv,
+            } => {
+                let bound = match bound {
+                    Bound::Exact => "",
+                    Bound::Lower => " lowerbound",
+                    Bound::Upper => " upperbound",
+                };
+                let milliseconds = time.as_millis() as u64;
+                let nps = nodes * 1000 / milliseconds.max(1);
+                let pv: Vec<String> = pv.iter().map(|&mv| game.move_to_uci(mv)).collect();
+                println!(
+                    "info depth {} seldepth {} multipv {} score {}{} nodes {} nps {} time {} hashfull {} tbhits {} pv {}",
+                    depth,
+                    seldepth,
+                    multipv,
+                    UciInterface::score_to_string(*score),
+                    bound,
+                    nodes,
+                    nps,
+                    milliseconds,
+                    hashfull,
+                    tb_hits,
+                    pv.join(" ")
+                );
             }
-        } else {
-            0x0000
-        };
+            SearchReport::CurrentMove { depth, mv, number } => println!(
+                "info depth {} currmove {} currmovenumber {}",
+                depth,
+                game.move_to_uci(*mv),
+                number
+            ),
+        }
+    }
 
-        // Construct move bitmap
-        let mut mv: u16 = 0;
-        mv |= from as u16;
-        mv |= (to as u16) << 6;
-        mv |= promotion;
-        mv |= capture;
-        mv |= en_passant;
-        mv |= double_pawn_push;
-        mv |= castle;
+    // mate scores are given in moves, negative if we are getting mated
+    fn score_to_string(score: i32) -> String {
+        if score >= MATE_THRESHOLD {
+            format!("mate {}", (MATE_SCORE - score + 1) / 2)
+        } else if score <= -MATE_THRESHOLD {
+            format!("mate -{}", (MATE_SCORE + score) / 2)
+        } else {
+            format!("cp {}", score)
+        }
+    }
 
-        Some(mv)
+    // Stops a running search and waits until it has sent its best move.
+    fn stop_search(&mut self) {
+        self.signals.stop();
+        self.wait_for_search();
     }
 
-    fn make_move(&mut self) {
-        let game: Game = self.game.unwrap();
-        // For demonstration, we will make a random valid move.
-        let moves = game.generate_legal_moves();
-        match moves.choose(&mut rand::rng()) {
-            Some(mv) => println!("bestmove {}", UciInterface::move_to_string(*mv)),
-            None => println!("bestmove (none)"),
-        }
-    }
-
-    fn move_to_string(mv: u16) -> String {
-        let from = (mv & 0x3F) as u8;
-        let to = ((mv >> 6) & 0x3F) as u8;
-
-        let from_file = (from % 8) + b'a';
-        let from_rank = (from / 8) + b'1';
-        let to_file = (to % 8) + b'a';
-        let to_rank = (to / 8) + b'1';
-
-        let mut move_str = String::new();
-        move_str.push(from_file as char);
-        move_str.push(from_rank as char);
-        move_str.push(to_file as char);
-        move_str.push(to_rank as char);
-
-        // Check for promotion
-        let promotion = mv & 0x8000;
-        if promotion != 0 {
-            let promo_char = match promotion {
-                0x8000 => 'q',
-                0x9000 => 'r',
-                0xA000 => 'b',
-                0xB000 => 'n',
-                _ => ' ',
-            };
-            move_str.push(promo_char);
+    // Waits until the running search has sent its best move. Searches that
+    // would never end on their own are stopped.
+    fn wait_for_search(&mut self) {
+        if let Some(worker) = self.worker.take() {
+            if self.infinite_search || self.signals.is_pondering() {
+                self.signals.stop();
+            }
+            self.search = Some(worker.join().unwrap());
         }
-
-        move_str
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::book::BookEntry;
+    use crate::constants::Piece;
+    use crate::polyglot::{encode_move, polyglot_key};
 
     #[test]
     fn test_parse_move_string() {
         let mut interface = UciInterface::new();
         let command = "position startpos moves e2e4 e7e5";
         interface.parse_position_command(command);
-        let board = interface.game.unwrap().board;
+        let board = interface.game.board;
         assert_eq!(board.piece_list[28], Piece::WhitePawn);
         assert_eq!(board.piece_list[36], Piece::BlackPawn);
     }
 
     #[test]
-    fn test_move_to_string() {
-        let mv = 0x000C | (0x001C << 6);
-        let move_str = UciInterface::move_to_string(mv);
-        assert_eq!(move_str, "e2e4");
+    fn test_parse_go_command() {
+        let mut interface = UciInterface::new();
+        interface.parse_position_command("position startpos");
+        let limits = interface.parse_go_command(
+            "go wtime 300000 btime -150 winc 2000 binc 2000 movestogo 40 nodes 100000",
+        );
+        assert_eq!(limits.white_time, Some(Duration::from_millis(300000)));
+        assert_eq!(limits.black_time, Some(Duration::ZERO));
+        assert_eq!(limits.white_increment, Some(Duration::from_millis(2000)));
+        assert_eq!(limits.black_increment, Some(Duration::from_millis(2000)));
+        assert_eq!(limits.moves_to_go, Some(40));
+        assert_eq!(limits.nodes, Some(100000));
+        assert_eq!(limits.depth, None);
+
+        let limits = interface.parse_go_command("go depth 7 mate 3 movetime 500 infinite");
+        assert_eq!(limits.depth, Some(7));
+        assert_eq!(limits.mate, Some(3));
+        assert_eq!(limits.move_time, Some(Duration::from_millis(500)));
+        assert!(limits.infinite);
+
+        // a plain go searches to the default depth
+        assert_eq!(
+            interface.parse_go_command("go"),
+            SearchLimits::depth(DEFAULT_SEARCH_DEPTH)
+        );
+    }
+
+    #[test]
+    fn test_parse_go_searchmoves() {
+        let mut interface = UciInterface::new();
+        interface.parse_position_command("position startpos");
+        let limits = interface.parse_go_command("go searchmoves e2e4 g1f3 e2e5 depth 3");
+        let e2e4 = Move::from_raw(0x1000 | 12 | (28 << 6));
+        let g1f3 = Move::from_raw(6 | (21 << 6));
+        // the illegal e2e5 ends the list, the following parameters still count
+        assert_eq!(limits.search_moves, vec![e2e4, g1f3]);
+        assert_eq!(limits.depth, Some(3));
+    }
+
+    #[test]
+    fn test_stop_infinite_search() {
+        let mut interface = UciInterface::new();
+        interface.parse_position_command("position startpos");
+        interface.start_search(interface.parse_go_command("go infinite"));
+        thread::sleep(Duration::from_millis(50));
+        assert!(!interface.worker.as_ref().unwrap().is_finished());
+        interface.stop_search();
+        assert!(interface.worker.is_none());
+        assert!(interface.search.is_some());
+    }
+
+    #[test]
+    fn test_ponderhit_starts_clock() {
+        let mut interface = UciInterface::new();
+        interface.parse_position_command("position startpos moves e2e4");
+        interface.start_search(interface.parse_go_command("go ponder wtime 100 btime 100"));
+        // the clock is ignored while pondering
+        thread::sleep(Duration::from_millis(200));
+        assert!(!interface.worker.as_ref().unwrap().is_finished());
+        interface.signals.ponderhit();
+        for _ in 0..200 {
+            if interface.worker.as_ref().unwrap().is_finished() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(interface.worker.as_ref().unwrap().is_finished());
+        interface.stop_search();
     }
 
     #[test]
-    fn test_move_to_string_with_promotion() {
-        let mv = 0x0036 | (0x003E << 6) | 0x8000;
-        let move_str = UciInterface::move_to_string(mv);
-        assert_eq!(move_str, "g7g8q");
+    fn test_parse_setoption_command() {
+        let mut interface = UciInterface::new();
+        interface.parse_setoption_command("setoption name Hash value 1");
+        interface.parse_setoption_command("setoption name multipv value 3");
+        interface.parse_setoption_command("setoption name Clear Hash");
+        interface.parse_setoption_command("setoption name OwnBook value true");
+        interface.parse_setoption_command("setoption name BookFile value books/my book.bin");
+        interface.parse_setoption_command("setoption name SyzygyPath value <empty>");
+        assert!(interface.own_book);
+        assert_eq!(interface.book_file.as_deref(), Some("books/my book.bin"));
+        assert_eq!(interface.syzygy_path, None);
+        // a directory without tables leaves the search without tablebases
+        interface.parse_setoption_command("setoption name SyzygyPath value /nonexistent/syzygy");
+        assert_eq!(
+            interface.syzygy_path.as_deref(),
+            Some("/nonexistent/syzygy")
+        );
+        interface.parse_setoption_command("setoption name SyzygyProbeDepth value 4");
+        // out of range values are ignored
+        interface.parse_setoption_command("setoption name MultiPV value 0");
+        // the hidden search parameters are set the same way
+        interface.parse_setoption_command("setoption name NullMove value false");
+        interface.parse_setoption_command("setoption name LmrBase value 100");
+        interface.parse_setoption_command("setoption name LmrDivisor value 0");
+        let parameters = interface.search.as_ref().unwrap().parameters();
+        assert!(!parameters.null_move);
+        assert_eq!(parameters.lmr_base, 100);
+        assert_eq!(parameters.lmr_divisor, 225);
+
+        interface.parse_position_command("position startpos");
+        let mut game = interface.game;
+        let result = interface
+            .search
+            .as_mut()
+            .unwrap()
+            .iterative_deepening(&mut game, &SearchLimits::depth(2));
+        assert_eq!(result.lines.len(), 3);
+    }
+
+    #[test]
+    fn test_chess960_option() {
+        let mut interface = UciInterface::new();
+        interface.parse_setoption_command("setoption name UCI_Chess960 value true");
+        // the king stays on c1 and takes the rook on b1 to castle queenside
+        interface.parse_position_command("position fen 4k3/8/8/8/8/8/8/1RK5 w Q - 0 1 moves c1b1");
+        assert!(interface.game.is_chess960());
+        assert_eq!(interface.game.to_fen(), "4k3/8/8/8/8/8/8/2KR4 b - - 1 1");
+        interface.parse_setoption_command("setoption name UCI_Chess960 value false");
+        interface.parse_position_command("position startpos");
+        assert!(!interface.game.is_chess960());
+    }
+
+    #[test]
+    fn test_book_move() {
+        // a book with 1. e4 for the starting position
+        let game = Game::new();
+        let e4 = game.parse_uci("e2e4").unwrap();
+        let book = Book::from_entries(vec![BookEntry {
+            key: polyglot_key(&game),
+            mv: encode_move(&game, e4.raw()),
+            weight: 1,
+            learn: 0,
+        }]);
+        let path =
+            std::env::temp_dir().join(format!("rusty_chess_book_{}.bin", std::process::id()));
+        book.save(&path).unwrap();
+
+        let mut interface = UciInterface::new();
+        interface
+            .parse_setoption_command(&format!("setoption name BookFile value {}", path.display()));
+        std::fs::remove_file(&path).unwrap();
+        assert!(interface.book.is_some());
+        let limits = SearchLimits::depth(1);
+        // only with OwnBook
+        assert_eq!(interface.book_move(&limits), None);
+        interface.parse_setoption_command("setoption name OwnBook value true");
+        assert_eq!(interface.book_move(&limits), Some(e4));
+        // analysis always searches
+        let infinite = SearchLimits {
+            infinite: true,
+            ..SearchLimits::default()
+        };
+        assert_eq!(interface.book_move(&infinite), None);
+        // the book move is not among the moves to search
+        let d4 = game.parse_uci("d2d4").unwrap();
+        let restricted = SearchLimits {
+            search_moves: vec![d4],
+            ..limits.clone()
+        };
+        assert_eq!(interface.book_move(&restricted), None);
+        // beyond the book depth
+        interface.parse_setoption_command("setoption name BookDepth value 1");
+        interface.parse_position_command(
+            "position fen rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 2",
+        );
+        assert_eq!(interface.book_move(&limits), None);
+    }
+
+    #[test]
+    fn test_score_to_string() {
+        assert_eq!(UciInterface::score_to_string(35), "cp 35");
+        assert_eq!(UciInterface::score_to_string(-120), "cp -120");
+        assert_eq!(UciInterface::score_to_string(MATE_SCORE - 1), "mate 1");
+        assert_eq!(UciInterface::score_to_string(MATE_SCORE - 3), "mate 2");
+        assert_eq!(UciInterface::score_to_string(-MATE_SCORE + 2), "mate -1");
+        assert_eq!(UciInterface::score_to_string(-MATE_SCORE + 4), "mate -2");
+    }
+
+    #[test]
+    fn test_parse_position_command_errors() {
+        let mut interface = UciInterface::new();
+        interface.parse_position_command("position startpos moves e2e4");
+        let fen = interface.game.to_fen();
+        // malformed commands keep the previous position
+        for command in [
+            "position",
+            "position fen",
+            "position fen 8/8/8/8/8/8/8/8 w - - 0 1",
+            "position somewhere",
+        ] {
+            interface.parse_position_command(command);
+            assert_eq!(interface.game.to_fen(), fen);
+        }
+        // moves are applied up to the first illegal one
+        for command in [
+            "position startpos moves e2e4 e7e5 e1e3 g1f3",
+            "position startpos moves e2e4 e7e5 xx",
+            "position startpos moves e2e4 e7e5 e2e4q",
+            "position startpos moves e2e4 e7e5 é9ü1",
+        ] {
+            interface.parse_position_command(command);
+            assert_eq!(
+                interface.game.to_fen(),
+                "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2"
+            );
+        }
+    }
+
+    #[test]
+    fn test_long_game_keeps_its_history() {
+        let mut interface = UciInterface::new();
+        // knights going back and forth for a very long game
+        let shuffle = "g1f3 g8f6 f3g1 f6g8 ".repeat(300);
+        interface.parse_position_command(&format!("position startpos moves {}", shuffle));
+        assert_eq!(interface.game.history.len(), 1201);
+        // the repetitions are still known to the search
+        assert!(interface.game.is_fivefold_repetition());
+        let mut game = interface.game.clone();
+        let result = interface
+            .search
+            .as_mut()
+            .unwrap()
+            .iterative_deepening(&mut game, &SearchLimits::depth(3));
+        assert!(result.best_move.is_some());
+    }
+
+    #[test]
+    fn test_go_without_position_uses_starting_position() {
+        let mut interface = UciInterface::new();
+        interface.start_search(SearchLimits::depth(1));
+        interface.wait_for_search();
+        assert!(interface.search.is_some());
+    }
+
+    #[test]
+    fn test_bench_positions_are_valid() {
+        for fen in BENCH_POSITIONS {
+            assert!(Game::from_fen(fen).is_ok(), "{}", fen);
+        }
     }
 }
@@ -0,0 +1,45 @@
+// Chess engine library. The UCI binary in main.rs is built on top of it, other
+// tools can use the typed API below to set up positions, generate and play
+// moves, and run searches. The modules are internal, their public types are
+// re-exported here and work with `Move` rather than the raw move encoding.
+mod board;
+mod book;
+mod book_builder;
+mod constants;
+mod evaluation;
+mod fen;
+mod game;
+mod game_history;
+mod game_result;
+mod game_state;
+mod interface;
+mod magic;
+mod move_generator;
+mod move_picker;
+mod pgn;
+mod polyglot;
+mod san;
+mod search;
+mod search_parameters;
+mod syzygy;
+mod time_manager;
+mod transposition_table;
+mod types;
+mod zobrist;
+
+pub use book::{Book, BookSelection};
+pub use book_builder::{BookBuilder, BookOptions, MoveStats};
+pub use fen::FenError;
+pub use game::{Game, IllegalMove};
+pub use game_result::{GameResult, Outcome, Termination};
+pub use interface::UciInterface;
+pub use pgn::{Line, MoveNode, PgnError, PgnGame, parse_pgn, read_pgn};
+pub use san::SanError;
+pub use search::{
+    MATE_SCORE, MATE_THRESHOLD, PvLine, Search, SearchLimits, SearchReport, SearchResult,
+    SearchSignals,
+};
+pub use search_parameters::{ParameterError, SearchParameters};
+pub use syzygy::{Tablebases, Wdl};
+pub use transposition_table::Bound;
+pub use types::{Color, File, Move, ParseSquareError, PieceType, Rank, Square};
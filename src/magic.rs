@@ -0,0 +1,214 @@
+use std::sync::OnceLock;
+
+const BISHOP_DIRECTIONS: [[i16; 2]; 4] = [[1, 1], [1, -1], [-1, 1], [-1, -1]];
+const ROOK_DIRECTIONS: [[i16; 2]; 4] = [[0, 1], [0, -1], [1, 0], [-1, 0]];
+
+// one entry per square, the attack sets for all relevant blocker
+// configurations of that square live at `offset` in the shared table
+#[derive(Clone, Copy, Default)]
+struct Magic {
+    mask: u64,
+    magic: u64,
+    shift: u32,
+    offset: usize,
+}
+
+impl Magic {
+    fn index(&self, occupied: u64) -> usize {
+        self.offset + (((occupied & self.mask).wrapping_mul(self.magic)) >> self.shift) as usize
+    }
+}
+
+struct MagicTables {
+    bishop_magics: [Magic; 64],
+    rook_magics: [Magic; 64],
+    bishop_attacks: Vec<u64>,
+    rook_attacks: Vec<u64>,
+}
+
+static TABLES: OnceLock<MagicTables> = OnceLock::new();
+
+impl MagicTables {
+    fn get() -> &'static MagicTables {
+        TABLES.get_or_init(MagicTables::new)
+    }
+
+    fn new() -> Self {
+        let (bishop_magics, bishop_attacks) = find_magics(BISHOP_DIRECTIONS);
+        let (rook_magics, rook_attacks) = find_magics(ROOK_DIRECTIONS);
+        MagicTables {
+            bishop_magics,
+            rook_magics,
+            bishop_attacks,
+            rook_attacks,
+        }
+    }
+}
+
+pub fn bishop_attacks(square: usize, occupied: u64) -> u64 {
+    let tables = MagicTables::get();
+    tables.bishop_attacks[tables.bishop_magics[square].index(occupied)]
+}
+
+pub fn rook_attacks(square: usize, occupied: u64) -> u64 {
+    let tables = MagicTables::get();
+    tables.rook_attacks[tables.rook_magics[square].index(occupied)]
+}
+
+// walks the rays square by square, only used to fill the tables
+fn ray_attacks(directions: [[i16; 2]; 4], square: usize, occupied: u64) -> u64 {
+    let mut attacks = 0;
+
+    for direction in directions {
+        let mut rank = (square / 8) as i16 + direction[0];
+        let mut file = (square % 8) as i16 + direction[1];
+
+        while (0..8).contains(&rank) && (0..8).contains(&file) {
+            let to = (rank * 8 + file) as usize;
+            attacks |= 1 << to;
+            // stop at the first blocker, which is included as a possible capture
+            if occupied & (1 << to) != 0 {
+                break;
+            }
+            rank += direction[0];
+            file += direction[1];
+        }
+    }
+
+    attacks
+}
+
+// squares whose occupancy influences the attacks, i.e. the empty-board rays
+// without the last square of each ray
+fn relevant_mask(directions: [[i16; 2]; 4], square: usize) -> u64 {
+    let mut mask = 0;
+
+    for direction in directions {
+        let mut rank = (square / 8) as i16 + direction[0];
+        let mut file = (square % 8) as i16 + direction[1];
+
+        while (0..8).contains(&(rank + direction[0])) && (0..8).contains(&(file + direction[1])) {
+            mask |= 1 << (rank * 8 + file);
+            rank += direction[0];
+            file += direction[1];
+        }
+    }
+
+    mask
+}
+
+// xorshift64* generator, reseeded per rank with seeds that are known to
+// find magics after few attempts so the tables build quickly at startup
+struct MagicRng(u64);
+
+impl MagicRng {
+    const SEEDS: [u64; 8] = [728, 10316, 55013, 32803, 12281, 15100, 16645, 255];
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(2685821657736338717)
+    }
+
+    // sparse random numbers make good magic candidates
+    fn sparse(&mut self) -> u64 {
+        self.next() & self.next() & self.next()
+    }
+}
+
+fn find_magics(directions: [[i16; 2]; 4]) -> ([Magic; 64], Vec<u64>) {
+    let mut magics = [Magic::default(); 64];
+    let mut table = Vec::new();
+
+    for (square, entry) in magics.iter_mut().enumerate() {
+        let mask = relevant_mask(directions, square);
+        let bits = mask.count_ones();
+
+        // enumerate all subsets of the mask (carry-rippler trick)
+        let mut occupancies = Vec::with_capacity(1 << bits);
+        let mut subset = 0u64;
+        loop {
+            occupancies.push((subset, ray_attacks(directions, square, subset)));
+            subset = subset.wrapping_sub(mask) & mask;
+            if subset == 0 {
+                break;
+            }
+        }
+
+        let mut rng = MagicRng(MagicRng::SEEDS[square / 8]);
+        let mut attacks = vec![0u64; 1 << bits];
+        // an entry is in use if it was written during the current attempt
+        let mut epoch = vec![0u32; 1 << bits];
+        let mut attempt = 0;
+        let shift = 64 - bits;
+        let magic = loop {
+            let candidate = rng.sparse();
+            if (mask.wrapping_mul(candidate) >> 56).count_ones() < 6 {
+                continue;
+            }
+            attempt += 1;
+            let collision = occupancies.iter().any(|&(occupied, attack)| {
+                let index = ((occupied.wrapping_mul(candidate)) >> shift) as usize;
+                if epoch[index] == attempt && attacks[index] != attack {
+                    return true;
+                }
+                epoch[index] = attempt;
+                attacks[index] = attack;
+                false
+            });
+            if !collision {
+                break candidate;
+            }
+        };
+
+        *entry = Magic {
+            mask,
+            magic,
+            shift,
+            offset: table.len(),
+        };
+        table.extend_from_slice(&attacks);
+    }
+
+    (magics, table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::prelude::*;
+
+    #[test]
+    fn test_magic_attacks_match_ray_walk() {
+        let mut rng = StdRng::seed_from_u64(42);
+        for _ in 0..1000 {
+            let occupied = rng.random::<u64>() & rng.random::<u64>();
+            for square in 0..64 {
+                assert_eq!(
+                    bishop_attacks(square, occupied),
+                    ray_attacks(BISHOP_DIRECTIONS, square, occupied)
+                );
+                assert_eq!(
+                    rook_attacks(square, occupied),
+                    ray_attacks(ROOK_DIRECTIONS, square, occupied)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_rook_attacks_empty_board() {
+        // a1 rook sees the whole a-file and first rank
+        assert_eq!(rook_attacks(0, 0), 0x01010101010101FE);
+    }
+
+    #[test]
+    fn test_bishop_attacks_blocked() {
+        // bishop on c1 blocked by a piece on e3
+        assert_eq!(
+            bishop_attacks(2, 1 << 20),
+            (1 << 9) | (1 << 16) | (1 << 11) | (1 << 20)
+        );
+    }
+}
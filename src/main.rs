@@ -1,13 +1,5 @@
-mod board;
-mod constants;
-mod evaluation;
-mod game;
-mod game_history;
-mod game_state;
-mod interface;
-mod move_generator;
-mod zobrist;
+use rusty_chess::UciInterface;
 
 fn main() {
-    interface::UciInterface::new().run();
+    UciInterface::new().run();
 }
@@ -0,0 +1,39 @@
+use crate::game::Game;
+use crate::types::Move;
+
+// Typed entry points to the move generation in `Board`, which works on raw
+// moves and takes the game state as separate arguments.
+
+pub fn legal_moves(game: &Game) -> Vec<Move> {
+    to_moves(game.generate_legal_moves())
+}
+
+// the legal captures (including en passant) and promotions
+pub fn captures(game: &Game) -> Vec<Move> {
+    let state = game.history.current_state();
+    to_moves(
+        game.board
+            .generate_captures(state.white_to_move, state.en_passant_square),
+    )
+}
+
+fn to_moves(moves: Vec<u16>) -> Vec<Move> {
+    moves.into_iter().map(Move::from_raw).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_typed_moves() {
+        let game = Game::from_fen("4k3/8/8/3pP3/8/8/8/4K2R w K d6 0 1").unwrap();
+        let moves = legal_moves(&game);
+        assert_eq!(moves.len(), game.generate_legal_moves().len());
+        assert!(moves.iter().any(|mv| mv.is_castling()));
+        let captures = captures(&game);
+        assert_eq!(captures.len(), 1);
+        assert!(captures[0].is_en_passant());
+        assert_eq!(captures[0].to_string(), "e5d6");
+    }
+}
@@ -0,0 +1,323 @@
+use crate::constants::{MAX_PLY, Piece};
+use crate::evaluation::PIECE_VALUES;
+use crate::game::Game;
+
+// history scores stay within this bound, so that recent cutoffs still have
+// an effect on moves with a long history
+const MAX_HISTORY: i32 = 16384;
+// the bonus of a cutoff grows with the square of the remaining depth
+const MAX_HISTORY_BONUS: i32 = 1200;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Stage {
+    TtMove,
+    GoodCaptures,
+    Promotions,
+    Killers,
+    Countermove,
+    Quiets,
+    BadCaptures,
+    Done,
+}
+
+// What the search learned about quiet moves from the cutoffs they caused.
+pub struct History {
+    // by side to move, from and to square
+    scores: [[[i32; 64]; 64]; 2],
+    // the last two quiet moves that caused a cutoff at each ply
+    killers: [[u16; 2]; MAX_PLY],
+    // the quiet move that refuted a move, by the piece that moved and its
+    // target square
+    countermoves: [[u16; 64]; 13],
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History::new()
+    }
+}
+
+impl History {
+    pub fn new() -> History {
+        History {
+            scores: [[[0; 64]; 64]; 2],
+            killers: [[0; 2]; MAX_PLY],
+            countermoves: [[0; 64]; 13],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = History::new();
+    }
+
+    // killers are only meaningful for the search that found them
+    pub fn clear_killers(&mut self) {
+        self.killers = [[0; 2]; MAX_PLY];
+    }
+
+    pub fn score(&self, game: &Game, mv: u16) -> i32 {
+        let side = !game.history.current_state().white_to_move as usize;
+        self.scores[side][(mv & 0x3F) as usize][((mv >> 6) & 0x3F) as usize]
+    }
+
+    pub fn killers(&self, ply: usize) -> [u16; 2] {
+        self.killers[ply]
+    }
+
+    // the move that refuted the last move before, if any
+    pub fn countermove(&self, game: &Game) -> Option<u16> {
+        let (piece, to) = last_move(game)?;
+        Some(self.countermoves[piece][to]).filter(|&mv| mv != 0)
+    }
+
+    // Rewards the quiet move `best` that caused a cutoff and punishes the
+    // other quiet moves that were searched before it without one.
+    pub fn update(&mut self, game: &Game, ply: usize, depth: u32, best: u16, quiets: &[u16]) {
+        let side = !game.history.current_state().white_to_move as usize;
+        let bonus = (depth * depth).min(MAX_HISTORY_BONUS as u32) as i32;
+        for &mv in quiets {
+            let delta = if mv == best { bonus } else { -bonus };
+            let score = &mut self.scores[side][(mv & 0x3F) as usize][((mv >> 6) & 0x3F) as usize];
+            // scores close to the bound change less
+            *score += delta - *score * delta.abs() / MAX_HISTORY;
+        }
+
+        let killers = &mut self.killers[ply];
+        if killers[0] != best {
+            killers[1] = killers[0];
+            killers[0] = best;
+        }
+        if let Some((piece, to)) = last_move(game) {
+            self.countermoves[piece][to] = best;
+        }
+    }
+}
+
+// the piece that made the last move and its target square
+fn last_move(game: &Game) -> Option<(usize, usize)> {
+    let mv = game.history.current_state().current_move?;
+    let to = ((mv >> 6) & 0x3F) as usize;
+    Some((game.board.piece_list[to] as usize, to))
+}
+
+pub fn is_capture(mv: u16) -> bool {
+    mv & 0x4000 != 0
+}
+
+// neither a capture nor a promotion
+pub fn is_quiet(mv: u16) -> bool {
+    mv & 0xC000 == 0
+}
+
+// Most valuable victim, least valuable attacker, with the promotion piece
+// counted as a gain.
+pub fn mvv_lva(game: &Game, mv: u16) -> i32 {
+    let from = (mv & 0x3F) as usize;
+    let to = ((mv >> 6) & 0x3F) as usize;
+    let victim = match game.board.piece_list[to] {
+        // en passant captures a pawn on an empty square
+        Piece::Empty if is_capture(mv) => PIECE_VALUES[Piece::WhitePawn as usize],
+        piece => PIECE_VALUES[piece as usize],
+    };
+    victim * 10 + promotion_value(mv) - PIECE_VALUES[game.board.piece_list[from] as usize] / 10
+}
+
+fn promotion_value(mv: u16) -> i32 {
+    if mv & 0x8000 == 0 {
+        return 0;
+    }
+    let piece = match mv & 0x3000 {
+        0x0000 => Piece::WhiteQueen,
+        0x1000 => Piece::WhiteRook,
+        0x2000 => Piece::WhiteBishop,
+        _ => Piece::WhiteKnight,
+    };
+    PIECE_VALUES[piece as usize]
+}
+
+// Hands out the legal moves of a position in the order they are likely to
+// cause a cutoff. The moves of each stage are only scored once the stage is
+// reached, and each move is selected when it is needed, so a cutoff early on
+// saves the work of ordering the rest.
+pub struct MovePicker {
+    stage: Stage,
+    // moves not handed out by an earlier stage
+    moves: Vec<u16>,
+    // the moves of the current stage with their scores
+    scored: Vec<(u16, i32)>,
+    bad_captures: Vec<(u16, i32)>,
+    tt_move: Option<u16>,
+    ply: usize,
+}
+
+impl MovePicker {
+    // `tt_move` is searched first if it is legal.
+    pub fn new(moves: Vec<u16>, tt_move: Option<u16>, ply: usize) -> MovePicker {
+        let tt_move = tt_move.filter(|mv| moves.contains(mv));
+        MovePicker {
+            stage: Stage::TtMove,
+            moves,
+            scored: tt_move.map(|mv| (mv, 0)).into_iter().collect(),
+            bad_captures: Vec::new(),
+            tt_move,
+            ply,
+        }
+    }
+
+    pub fn next(&mut self, game: &Game, history: &History) -> Option<u16> {
+        loop {
+            if let Some(index) = (0..self.scored.len()).max_by_key(|&i| self.scored[i].1) {
+                let (mv, _) = self.scored.swap_remove(index);
+                return Some(mv);
+            }
+            if self.stage == Stage::Done {
+                return None;
+            }
+            self.advance(game, history);
+        }
+    }
+
+    // Moves on to the next stage and scores its moves.
+    fn advance(&mut self, game: &Game, history: &History) {
+        if let Some(tt_move) = self.tt_move.take() {
+            self.moves.retain(|&mv| mv != tt_move);
+        }
+        self.stage = match self.stage {
+            Stage::TtMove => {
+                for mv in self.take(is_capture) {
+                    let score = mvv_lva(game, mv);
+                    // captures that lose material in the exchange are searched last
+                    if game.board.see(mv, 0) {
+                        self.scored.push((mv, score));
+                    } else {
+                        self.bad_captures.push((mv, score));
+                    }
+                }
+                Stage::GoodCaptures
+            }
+            Stage::GoodCaptures => {
+                for mv in self.take(|mv| !is_quiet(mv)) {
+                    self.scored.push((mv, promotion_value(mv)));
+                }
+                Stage::Promotions
+            }
+            Stage::Promotions => {
+                for (index, killer) in history.killers(self.ply).into_iter().enumerate() {
+                    for mv in self.take(|mv| mv == killer) {
+                        self.scored.push((mv, -(index as i32)));
+                    }
+                }
+                Stage::Killers
+            }
+            Stage::Killers => {
+                if let Some(countermove) = history.countermove(game) {
+                    for mv in self.take(|mv| mv == countermove) {
+                        self.scored.push((mv, 0));
+                    }
+                }
+                Stage::Countermove
+            }
+            Stage::Countermove => {
+                for mv in self.take(|_| true) {
+                    self.scored.push((mv, history.score(game, mv)));
+                }
+                Stage::Quiets
+            }
+            Stage::Quiets => {
+                self.scored = std::mem::take(&mut self.bad_captures);
+                Stage::BadCaptures
+            }
+            Stage::BadCaptures | Stage::Done => Stage::Done,
+        };
+    }
+
+    // removes the remaining moves that match from the moves left
+    fn take(&mut self, matches: impl Fn(u16) -> bool) -> Vec<u16> {
+        let (taken, rest) = self.moves.iter().partition(|&&mv| matches(mv));
+        self.moves = rest;
+        taken
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Move;
+
+    fn find_move(game: &Game, uci: &str) -> u16 {
+        game.parse_uci(uci).unwrap().raw()
+    }
+
+    fn picked(game: &Game, tt_move: Option<u16>, history: &History, ply: usize) -> Vec<String> {
+        let mut picker = MovePicker::new(game.generate_legal_moves(), tt_move, ply);
+        let mut moves = Vec::new();
+        while let Some(mv) = picker.next(game, history) {
+            moves.push(Move::from_raw(mv).to_string());
+        }
+        moves
+    }
+
+    #[test]
+    fn test_move_picker_stages() {
+        // exd5 wins a pawn, Qxd5 loses the queen to cxd5
+        let game = Game::from_fen("4k3/8/2p5/3p4/4P3/8/8/3QK3 w - - 0 1").unwrap();
+        let history = History::new();
+        let moves = picked(&game, Some(find_move(&game, "e1f2")), &history, 0);
+        assert_eq!(moves.len(), game.generate_legal_moves().len());
+        let mut unique = moves.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), moves.len());
+        assert_eq!(moves[0], "e1f2");
+        assert_eq!(moves[1], "e4d5");
+        assert_eq!(moves.last().unwrap(), "d1d5");
+
+        // an illegal move from the transposition table is ignored
+        let moves = picked(&game, Some(find_move(&Game::new(), "g1f3")), &history, 0);
+        assert_eq!(moves[0], "e4d5");
+        assert_eq!(moves.len(), game.generate_legal_moves().len());
+    }
+
+    #[test]
+    fn test_move_picker_promotions() {
+        let game = Game::from_fen("1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let moves = picked(&game, None, &History::new(), 0);
+        // captures first, then promotions with the queen first
+        assert_eq!(moves[..5], ["a7b8q", "a7b8r", "a7b8b", "a7b8n", "a7a8q"]);
+    }
+
+    #[test]
+    fn test_history_orders_quiet_moves() {
+        let game = Game::from_fen("4k3/8/2p5/3p4/4P3/8/8/3QK3 w - - 0 1").unwrap();
+        let mut history = History::new();
+        let (a4, h5) = (find_move(&game, "d1a4"), find_move(&game, "d1h5"));
+        history.update(&game, 3, 4, h5, &[a4, h5]);
+        assert!(history.score(&game, h5) > 0);
+        assert!(history.score(&game, a4) < 0);
+        assert_eq!(history.killers(3), [h5, 0]);
+
+        let moves = picked(&game, None, &history, 3);
+        assert_eq!(moves[..2], ["e4d5", "d1h5"]);
+        // the punished move is the last quiet move
+        assert_eq!(moves[moves.len() - 2], "d1a4");
+        // killers are kept by ply
+        assert_eq!(history.killers(4), [0, 0]);
+
+        history.clear_killers();
+        assert_eq!(history.killers(3), [0, 0]);
+        assert!(history.score(&game, h5) > 0);
+    }
+
+    #[test]
+    fn test_countermove() {
+        let mut game = Game::new();
+        game.make_move(find_move(&game, "e2e4"));
+        let mut history = History::new();
+        assert_eq!(history.countermove(&game), None);
+        let c5 = find_move(&game, "c7c5");
+        history.update(&game, 1, 2, c5, &[c5]);
+        assert_eq!(history.countermove(&game), Some(c5));
+        // without killers at this ply the countermove comes first
+        assert_eq!(picked(&game, None, &history, 5)[0], "c7c5");
+    }
+}
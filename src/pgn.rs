@@ -0,0 +1,792 @@
+use crate::fen::FenError;
+use crate::game::Game;
+use crate::game_result::GameResult;
+use crate::san::SanError;
+use crate::types::Move;
+use std::fmt;
+
+// export format lines are kept below 80 characters
+const MAX_LINE_LENGTH: usize = 79;
+const SEVEN_TAG_ROSTER: [(&str, &str); 7] = [
+    ("Event", "?"),
+    ("Site", "?"),
+    ("Date", "????.??.??"),
+    ("Round", "?"),
+    ("White", "?"),
+    ("Black", "?"),
+    ("Result", "*"),
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PgnError {
+    InvalidTag { line: usize },
+    UnterminatedComment { line: usize },
+    UnexpectedToken { line: usize, token: String },
+    UnterminatedVariation { line: usize },
+    InvalidFen(FenError),
+    IllegalMove { line: usize, error: SanError },
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PgnError::InvalidTag { line } => write!(f, "line {}: invalid tag pair", line),
+            PgnError::UnterminatedComment { line } => {
+                write!(f, "line {}: comment is not closed", line)
+            }
+            PgnError::UnexpectedToken { line, token } => {
+                write!(f, "line {}: unexpected '{}'", line, token)
+            }
+            PgnError::UnterminatedVariation { line } => {
+                write!(f, "line {}: variation is not closed", line)
+            }
+            PgnError::InvalidFen(error) => write!(f, "invalid FEN tag: {}", error),
+            PgnError::IllegalMove { line, error } => write!(f, "line {}: {}", line, error),
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+// A move of the game tree with its annotations. The variations are
+// alternatives to this move, starting from the position before it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MoveNode {
+    pub mv: Move,
+    pub nags: Vec<u8>,
+    // the comment after the move
+    pub comment: Option<String>,
+    pub variations: Vec<Line>,
+}
+
+impl MoveNode {
+    pub fn new(mv: Move) -> MoveNode {
+        MoveNode {
+            mv,
+            nags: Vec::new(),
+            comment: None,
+            variations: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Line {
+    // the comment before the first move
+    pub comment: Option<String>,
+    pub moves: Vec<MoveNode>,
+}
+
+#[derive(Clone)]
+pub struct PgnGame {
+    // in the order they were read, the seven tag roster is always written first
+    pub tags: Vec<(String, String)>,
+    pub start: Game,
+    pub mainline: Line,
+    // None for an unfinished game (*)
+    pub result: Option<GameResult>,
+}
+
+impl Default for PgnGame {
+    fn default() -> Self {
+        PgnGame::new()
+    }
+}
+
+impl PgnGame {
+    pub fn new() -> PgnGame {
+        PgnGame {
+            tags: Vec::new(),
+            start: Game::new(),
+            mainline: Line::default(),
+            result: None,
+        }
+    }
+
+    // Exports the moves played in `game`. The result is only set if the game
+    // is over, claimable draws do not end it.
+    pub fn from_game(game: &Game) -> PgnGame {
+        let mut start = game.clone();
+        let mut moves = Vec::new();
+        while let Some(mv) = start.history.current_state().current_move {
+            moves.push(MoveNode::new(Move::from_raw(mv)));
+            start.undo_move();
+        }
+        moves.reverse();
+
+        let fen = start.to_fen();
+        let mut pgn = PgnGame {
+            start,
+            mainline: Line {
+                comment: None,
+                moves,
+            },
+            result: game
+                .outcome()
+                .filter(|outcome| !outcome.termination.is_claimable())
+                .map(|outcome| outcome.result),
+            ..PgnGame::new()
+        };
+        if fen != Game::new().to_fen() {
+            pgn.set_tag("SetUp", "1");
+            pgn.set_tag("FEN", &fen);
+        }
+        pgn
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(tag, _)| tag == name) {
+            Some((_, old)) => *old = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    // the game after all moves of the main line
+    pub fn mainline_game(&self) -> Game {
+        let mut game = self.start.clone();
+        for node in &self.mainline.moves {
+            game.make_move(node.mv.raw());
+        }
+        game
+    }
+}
+
+// Reads all games of a PGN file, failing on the first malformed one.
+pub fn parse_pgn(text: &str) -> Result<Vec<PgnGame>, PgnError> {
+    read_pgn(text).into_iter().collect()
+}
+
+// Reads the games of a PGN file one by one. A malformed game gives an error
+// and reading continues with the next game.
+pub fn read_pgn(text: &str) -> Vec<Result<PgnGame, PgnError>> {
+    let mut games = Vec::new();
+    let mut reader = GameReader::default();
+    let mut last_line = 1;
+    for (token, line) in tokenize(text) {
+        last_line = line;
+        match token {
+            // a tag after move text starts the next game
+            _ if is_tag(&token) && reader.in_move_text => {
+                games.push(std::mem::take(&mut reader).finish(None, line));
+            }
+            Ok(Token::Result(result)) => {
+                games.push(std::mem::take(&mut reader).finish(Some(result), line));
+                continue;
+            }
+            _ => {}
+        }
+        reader.read(token, line);
+    }
+    if reader.in_move_text || !reader.pgn.tags.is_empty() {
+        games.push(reader.finish(None, last_line));
+    }
+    games
+}
+
+// The line being read and the position at its end, for each open variation.
+#[derive(Default)]
+struct GameReader {
+    pgn: PgnGame,
+    variations: Vec<(Line, Game)>,
+    in_move_text: bool,
+    // the first error of the game, the rest of it is skipped
+    error: Option<PgnError>,
+}
+
+impl GameReader {
+    fn read(&mut self, token: Result<Token, PgnError>, line: usize) {
+        let start = !self.in_move_text && !is_tag(&token);
+        self.in_move_text |= start;
+        if self.error.is_some() {
+            return;
+        }
+        let result = token.and_then(|token| {
+            if start {
+                self.start_move_text()?;
+            }
+            self.read_token(token, line)
+        });
+        if let Err(error) = result {
+            self.error = Some(error);
+        }
+    }
+
+    fn read_token(&mut self, token: Token, line: usize) -> Result<(), PgnError> {
+        match token {
+            Token::Tag(name, value) => self.pgn.set_tag(&name, &value),
+            Token::Move(san) => self.play(&san, line)?,
+            Token::Nag(nag) => {
+                if let Some(node) = self.last_move() {
+                    node.nags.push(nag);
+                }
+            }
+            Token::Comment(comment) => self.add_comment(comment),
+            Token::OpenVariation => self.open_variation(line)?,
+            Token::CloseVariation => self.close_variation(line)?,
+            Token::Result(_) => unreachable!(),
+        }
+        Ok(())
+    }
+
+    fn start_move_text(&mut self) -> Result<(), PgnError> {
+        if let Some(fen) = self.pgn.tag("FEN") {
+            self.pgn.start = Game::from_fen(fen).map_err(PgnError::InvalidFen)?;
+        }
+        self.variations
+            .push((Line::default(), self.pgn.start.clone()));
+        Ok(())
+    }
+
+    fn play(&mut self, san: &str, line: usize) -> Result<(), PgnError> {
+        let (variation, game) = self.variations.last_mut().unwrap();
+        let mv = game
+            .parse_san(san)
+            .map_err(|error| PgnError::IllegalMove { line, error })?;
+        game.make_move(mv.raw());
+        variation.moves.push(MoveNode::new(mv));
+        Ok(())
+    }
+
+    fn last_move(&mut self) -> Option<&mut MoveNode> {
+        self.variations.last_mut()?.0.moves.last_mut()
+    }
+
+    fn add_comment(&mut self, comment: String) {
+        let (line, _) = self.variations.last_mut().unwrap();
+        let target = match line.moves.last_mut() {
+            Some(node) => &mut node.comment,
+            None => &mut line.comment,
+        };
+        match target {
+            Some(existing) => {
+                existing.push(' ');
+                existing.push_str(&comment);
+            }
+            None => *target = Some(comment),
+        }
+    }
+
+    fn open_variation(&mut self, line: usize) -> Result<(), PgnError> {
+        let (variation, game) = self.variations.last().unwrap();
+        if variation.moves.is_empty() {
+            return Err(PgnError::UnexpectedToken {
+                line,
+                token: "(".to_string(),
+            });
+        }
+        let mut before = game.clone();
+        before.undo_move();
+        self.variations.push((Line::default(), before));
+        Ok(())
+    }
+
+    fn close_variation(&mut self, line: usize) -> Result<(), PgnError> {
+        if self.variations.len() < 2 {
+            return Err(PgnError::UnexpectedToken {
+                line,
+                token: ")".to_string(),
+            });
+        }
+        let (variation, _) = self.variations.pop().unwrap();
+        self.last_move().unwrap().variations.push(variation);
+        Ok(())
+    }
+
+    // `result` is the termination marker, the Result tag is used without it
+    fn finish(
+        mut self,
+        result: Option<Option<GameResult>>,
+        line: usize,
+    ) -> Result<PgnGame, PgnError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        if self.variations.is_empty() {
+            self.start_move_text()?;
+        }
+        if self.variations.len() > 1 {
+            return Err(PgnError::UnterminatedVariation { line });
+        }
+        if let Some((mainline, _)) = self.variations.pop() {
+            self.pgn.mainline = mainline;
+        }
+        self.pgn.result = match result {
+            Some(result) => result,
+            None => self.pgn.tag("Result").and_then(parse_result).flatten(),
+        };
+        Ok(self.pgn)
+    }
+}
+
+// malformed tags count as tags, they do not start the move text
+fn is_tag(token: &Result<Token, PgnError>) -> bool {
+    matches!(token, Ok(Token::Tag(..)) | Err(PgnError::InvalidTag { .. }))
+}
+
+enum Token {
+    Tag(String, String),
+    Move(String),
+    Nag(u8),
+    Comment(String),
+    OpenVariation,
+    CloseVariation,
+    Result(Option<GameResult>),
+}
+
+// Splits PGN text into tokens, each with the line it starts on. Move numbers
+// are dropped and move suffixes like !? are turned into NAGs. Malformed
+// tokens become errors in place.
+fn tokenize(text: &str) -> Vec<(Result<Token, PgnError>, usize)> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+    let mut line_start = true;
+    while let Some(c) = chars.next() {
+        if c == '\n' {
+            line += 1;
+            line_start = true;
+            continue;
+        }
+        // escaped lines are meant for other software
+        if line_start && c == '%' {
+            while chars.next_if(|&c| c != '\n').is_some() {}
+            continue;
+        }
+        line_start = false;
+        match c {
+            c if c.is_whitespace() => {}
+            '[' => {
+                let tag: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                let token = parse_tag(&tag)
+                    .map(|(name, value)| Token::Tag(name, value))
+                    .ok_or(PgnError::InvalidTag { line });
+                tokens.push((token, line));
+            }
+            '{' => {
+                let start = line;
+                let mut comment = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            comment.push(c);
+                        }
+                        None => {
+                            tokens
+                                .push((Err(PgnError::UnterminatedComment { line: start }), start));
+                            return tokens;
+                        }
+                    }
+                }
+                let comment = comment.split_whitespace().collect::<Vec<_>>().join(" ");
+                tokens.push((Ok(Token::Comment(comment)), start));
+            }
+            ';' => {
+                let comment: String =
+                    std::iter::from_fn(|| chars.next_if(|&c| c != '\n')).collect();
+                tokens.push((Ok(Token::Comment(comment.trim().to_string())), line));
+            }
+            '(' => tokens.push((Ok(Token::OpenVariation), line)),
+            ')' => tokens.push((Ok(Token::CloseVariation), line)),
+            '$' => {
+                let digits: String =
+                    std::iter::from_fn(|| chars.next_if(|c| c.is_ascii_digit())).collect();
+                let token = digits
+                    .parse()
+                    .map(Token::Nag)
+                    .map_err(|_| PgnError::UnexpectedToken {
+                        line,
+                        token: format!("${}", digits),
+                    });
+                tokens.push((token, line));
+            }
+            _ => {
+                let mut symbol = c.to_string();
+                while let Some(c) =
+                    chars.next_if(|&c| !c.is_whitespace() && !"{}()[];$".contains(c))
+                {
+                    symbol.push(c);
+                }
+                push_symbol(&symbol, line, &mut tokens);
+            }
+        }
+    }
+    tokens
+}
+
+fn push_symbol(symbol: &str, line: usize, tokens: &mut Vec<(Result<Token, PgnError>, usize)>) {
+    if let Some(result) = parse_result(symbol) {
+        tokens.push((Ok(Token::Result(result)), line));
+        return;
+    }
+    // a move number may be written without a space, like 1.e4
+    let mut text = symbol;
+    let after_number = symbol.trim_start_matches(|c: char| c.is_ascii_digit());
+    if after_number.starts_with('.') {
+        text = after_number.trim_start_matches('.');
+    }
+    if text.is_empty() {
+        return;
+    }
+
+    let san = text.trim_end_matches(['!', '?']);
+    let nag = match &text[san.len()..] {
+        "" => None,
+        "!" => Some(1),
+        "?" => Some(2),
+        "!!" => Some(3),
+        "??" => Some(4),
+        "!?" => Some(5),
+        "?!" => Some(6),
+        suffix => {
+            let error = PgnError::UnexpectedToken {
+                line,
+                token: suffix.to_string(),
+            };
+            tokens.push((Err(error), line));
+            return;
+        }
+    };
+    tokens.push((Ok(Token::Move(san.to_string())), line));
+    if let Some(nag) = nag {
+        tokens.push((Ok(Token::Nag(nag)), line));
+    }
+}
+
+// the contents of a tag pair like `Event "Casual game"`
+fn parse_tag(tag: &str) -> Option<(String, String)> {
+    let tag = tag.trim();
+    let name_length = tag.find(|c: char| !c.is_ascii_alphanumeric() && c != '_')?;
+    let (name, rest) = tag.split_at(name_length);
+    let mut chars = rest.trim().strip_prefix('"')?.chars();
+    let mut value = String::new();
+    loop {
+        match chars.next()? {
+            '\\' => value.push(chars.next()?),
+            '"' => break,
+            c => value.push(c),
+        }
+    }
+    if name.is_empty() || !chars.as_str().trim().is_empty() {
+        return None;
+    }
+    Some((name.to_string(), value))
+}
+
+fn parse_result(result: &str) -> Option<Option<GameResult>> {
+    match result {
+        "1-0" => Some(Some(GameResult::WhiteWins)),
+        "0-1" => Some(Some(GameResult::BlackWins)),
+        "1/2-1/2" => Some(Some(GameResult::Draw)),
+        "*" => Some(None),
+        _ => None,
+    }
+}
+
+fn result_to_string(result: Option<GameResult>) -> String {
+    result.map_or("*".to_string(), |result| result.to_string())
+}
+
+// export format: the seven tag roster, the other tags and the move text
+// wrapped into lines
+impl fmt::Display for PgnGame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let result = result_to_string(self.result);
+        for (name, default) in SEVEN_TAG_ROSTER {
+            let value = match name {
+                "Result" => &result,
+                _ => self.tag(name).unwrap_or(default),
+            };
+            writeln!(f, "[{} \"{}\"]", name, escape_tag_value(value))?;
+        }
+        for (name, value) in &self.tags {
+            if !SEVEN_TAG_ROSTER.iter().any(|(roster, _)| roster == name) {
+                writeln!(f, "[{} \"{}\"]", name, escape_tag_value(value))?;
+            }
+        }
+        writeln!(f)?;
+
+        let mut tokens = Vec::new();
+        write_line(self.start.clone(), &self.mainline, &mut tokens);
+        tokens.push(result);
+        let mut line = String::new();
+        for token in join_parentheses(tokens) {
+            if !line.is_empty() && line.len() + 1 + token.len() > MAX_LINE_LENGTH {
+                writeln!(f, "{}", line)?;
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        writeln!(f, "{}", line)
+    }
+}
+
+fn escape_tag_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn write_line(mut game: Game, line: &Line, tokens: &mut Vec<String>) {
+    if let Some(comment) = &line.comment {
+        write_comment(comment, tokens);
+    }
+    // black moves need a number at the start and after comments or variations
+    let mut needs_number = true;
+    for node in &line.moves {
+        let state = game.history.current_state();
+        if state.white_to_move {
+            tokens.push(format!("{}.", state.full_move_counter));
+        } else if needs_number {
+            tokens.push(format!("{}...", state.full_move_counter));
+        }
+        tokens.push(game.move_to_san(node.mv));
+        tokens.extend(node.nags.iter().map(|nag| format!("${}", nag)));
+        needs_number = false;
+        if let Some(comment) = &node.comment {
+            write_comment(comment, tokens);
+            needs_number = true;
+        }
+        for variation in &node.variations {
+            tokens.push("(".to_string());
+            write_line(game.clone(), variation, tokens);
+            tokens.push(")".to_string());
+            needs_number = true;
+        }
+        game.make_move(node.mv.raw());
+    }
+}
+
+// comments may be broken across lines, so every word is a token
+fn write_comment(comment: &str, tokens: &mut Vec<String>) {
+    let comment = comment.replace('}', "");
+    let mut words: Vec<String> = comment.split_whitespace().map(str::to_string).collect();
+    if words.is_empty() {
+        words.push(String::new());
+    }
+    words[0].insert(0, '{');
+    words.last_mut().unwrap().push('}');
+    tokens.extend(words);
+}
+
+// parentheses are written without a space to the variation inside
+fn join_parentheses(tokens: Vec<String>) -> Vec<String> {
+    let mut joined: Vec<String> = Vec::new();
+    let mut open = String::new();
+    for token in tokens {
+        match token.as_str() {
+            "(" => open.push('('),
+            ")" => joined.last_mut().unwrap().push(')'),
+            _ => joined.push(std::mem::take(&mut open) + &token),
+        }
+    }
+    joined
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ANNOTATED: &str = r#"[Event "Casual \"blitz\" game"]
+[Site "?"]
+[Date "2024.01.01"]
+[Round "1"]
+[White "Alice"]
+[Black "Bob"]
+[Result "1-0"]
+[ECO "C42"]
+
+{Petrov's defence} 1. e4 e5 2. Nf3 Nf6 $1 (2... Nc6 3. Bb5 (3. Bc4 Bc5) 3... a6
+; the Ruy Lopez
+) 3.Nxe5 d6 4. Nf3 Nxe4?! {Too early.} 5. Qe2 Qe7 6. Qxe4 1-0
+"#;
+
+    fn uci_moves(line: &Line) -> Vec<String> {
+        line.moves.iter().map(|node| node.mv.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_annotated_game() {
+        let games = parse_pgn(ANNOTATED).unwrap();
+        assert_eq!(games.len(), 1);
+        let game = &games[0];
+        assert_eq!(game.tag("Event"), Some("Casual \"blitz\" game"));
+        assert_eq!(game.tag("ECO"), Some("C42"));
+        assert_eq!(game.result, Some(GameResult::WhiteWins));
+        assert_eq!(game.mainline.comment.as_deref(), Some("Petrov's defence"));
+        assert_eq!(game.mainline.moves.len(), 11);
+
+        let nf6 = &game.mainline.moves[3];
+        assert_eq!(nf6.nags, vec![1]);
+        assert_eq!(nf6.variations.len(), 1);
+        let variation = &nf6.variations[0];
+        assert_eq!(uci_moves(variation), vec!["b8c6", "f1b5", "a7a6"]);
+        assert_eq!(variation.moves[2].comment.as_deref(), Some("the Ruy Lopez"));
+        assert_eq!(
+            uci_moves(&variation.moves[1].variations[0]),
+            vec!["f1c4", "f8c5"]
+        );
+
+        let nxe4 = &game.mainline.moves[7];
+        assert_eq!(nxe4.nags, vec![6]);
+        assert_eq!(nxe4.comment.as_deref(), Some("Too early."));
+        let end = game.mainline_game();
+        assert_eq!(
+            end.to_fen(),
+            "rnb1kb1r/ppp1qppp/3p4/8/4Q3/5N2/PPPP1PPP/RNB1KB1R b KQkq - 0 6"
+        );
+    }
+
+    #[test]
+    fn test_write_round_trip() {
+        let game = &parse_pgn(ANNOTATED).unwrap()[0];
+        let written = game.to_string();
+        assert!(written.starts_with("[Event \"Casual \\\"blitz\\\" game\"]\n[Site \"?\"]"));
+        assert!(written.contains("[ECO \"C42\"]\n\n{Petrov's defence} 1. e4 e5 2. Nf3 Nf6 $1"));
+        let move_text = written.replace('\n', " ");
+        assert!(
+            move_text.contains("(2... Nc6 3. Bb5 (3. Bc4 Bc5) 3... a6 {the Ruy Lopez}) 3. Nxe5")
+        );
+        assert!(move_text.contains("Nxe4 $6 {Too early.} 5. Qe2"));
+        assert!(written.trim_end().ends_with("6. Qxe4 1-0"));
+        assert!(written.lines().all(|line| line.len() <= MAX_LINE_LENGTH));
+
+        let reread = &parse_pgn(&written).unwrap()[0];
+        assert_eq!(reread.tags, game.tags);
+        assert_eq!(reread.mainline, game.mainline);
+        assert_eq!(reread.to_string(), written);
+    }
+
+    #[test]
+    fn test_multiple_games_and_setup() {
+        let text = "[Event \"first\"]\n1. d4 d5 *\n\n\
+            [Event \"second\"]\n[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 0 40\"]\n\
+            40... Kd7 41. e4\n\n\
+            [Event \"third\"]\n[Result \"1/2-1/2\"]\n";
+        let games = parse_pgn(text).unwrap();
+        assert_eq!(games.len(), 3);
+        assert_eq!(games[0].result, None);
+        assert_eq!(games[0].mainline.moves.len(), 2);
+        assert_eq!(games[1].mainline.moves.len(), 2);
+        assert!(games[1].to_string().contains("\n40... Kd7 41. e4 *\n"));
+        assert_eq!(games[2].result, Some(GameResult::Draw));
+        assert!(games[2].mainline.moves.is_empty());
+    }
+
+    #[test]
+    fn test_from_game() {
+        let mut game = Game::new();
+        for san in ["f3", "e5", "g4", "Qh4#"] {
+            let mv = game.parse_san(san).unwrap();
+            game.make_move(mv.raw());
+        }
+        let pgn = PgnGame::from_game(&game);
+        assert_eq!(pgn.result, Some(GameResult::BlackWins));
+        assert_eq!(pgn.tag("FEN"), None);
+        assert!(pgn.to_string().ends_with("\n1. f3 e5 2. g4 Qh4# 0-1\n"));
+
+        let game = Game::from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+        let pgn = PgnGame::from_game(&game);
+        assert_eq!(pgn.tag("FEN"), Some("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1"));
+        assert_eq!(pgn.result, None);
+    }
+
+    #[test]
+    fn test_line_wrapping() {
+        let mut game = Game::new();
+        for _ in 0..20 {
+            for san in ["Nf3", "Nf6", "Ng1", "Ng8"] {
+                let mv = game.parse_san(san).unwrap();
+                game.make_move(mv.raw());
+            }
+        }
+        let mut pgn = PgnGame::from_game(&game);
+        pgn.mainline.moves[0].comment = Some("a long comment".repeat(10).trim().to_string());
+        let written = pgn.to_string();
+        assert!(written.lines().all(|line| line.len() <= MAX_LINE_LENGTH));
+        assert!(written.lines().count() > 10);
+        assert_eq!(parse_pgn(&written).unwrap()[0].mainline, pgn.mainline);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let cases = [
+            ("[Event \"x]\n1. e4 *", PgnError::InvalidTag { line: 1 }),
+            ("1. e4 {open", PgnError::UnterminatedComment { line: 1 }),
+            ("1. e4 (1. d4", PgnError::UnterminatedVariation { line: 1 }),
+            (
+                "\n(1. e4)",
+                PgnError::UnexpectedToken {
+                    line: 2,
+                    token: "(".to_string(),
+                },
+            ),
+            (
+                "1. e4 e5)",
+                PgnError::UnexpectedToken {
+                    line: 1,
+                    token: ")".to_string(),
+                },
+            ),
+            (
+                "1. e4\n1... e4",
+                PgnError::IllegalMove {
+                    line: 2,
+                    error: SanError::Illegal("e4".to_string()),
+                },
+            ),
+            (
+                "[FEN \"8/8/8/8/8/8/8/8 w - - 0 1\"]\n1. e4",
+                PgnError::InvalidFen(FenError::MissingKing { white: true }),
+            ),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(parse_pgn(text).err(), Some(expected), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_read_pgn_skips_malformed_games() {
+        let text = "[Event \"illegal\"]\n1. e4 e4 2. d4 *\n\n\
+            [Event \"good\"]\n1. e4 e5 1-0\n\n\
+            [Event \"bad tag]\n[Result \"0-1\"]\n1. d4 0-1\n\n\
+            [Event \"open variation\"]\n1. e4 (1. d4\n\n\
+            [Event \"unfinished\"]\n1. c4";
+        let games = read_pgn(text);
+        assert_eq!(games.len(), 5);
+        assert_eq!(
+            games[0].as_ref().err(),
+            Some(&PgnError::IllegalMove {
+                line: 2,
+                error: SanError::Illegal("e4".to_string()),
+            })
+        );
+        let good = games[1].as_ref().unwrap();
+        assert_eq!(good.tag("Event"), Some("good"));
+        assert_eq!(good.mainline.moves.len(), 2);
+        assert_eq!(
+            games[2].as_ref().err(),
+            Some(&PgnError::InvalidTag { line: 7 })
+        );
+        assert_eq!(
+            games[3].as_ref().err(),
+            Some(&PgnError::UnterminatedVariation { line: 14 })
+        );
+        let unfinished = games[4].as_ref().unwrap();
+        assert_eq!(unfinished.tag("Event"), Some("unfinished"));
+        assert_eq!(unfinished.result, None);
+        // parse_pgn fails on the first malformed game
+        assert_eq!(parse_pgn(text).err(), games[0].clone().err());
+    }
+}
@@ -0,0 +1,289 @@
+use crate::game::Game;
+use crate::types::{Move, PieceType, Square};
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SanError {
+    // not a move in standard algebraic notation
+    Invalid(String),
+    // no legal move matches
+    Illegal(String),
+    // more than one legal move matches
+    Ambiguous(String),
+}
+
+impl fmt::Display for SanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SanError::Invalid(san) => write!(f, "invalid move '{}'", san),
+            SanError::Illegal(san) => write!(f, "illegal move '{}'", san),
+            SanError::Ambiguous(san) => write!(f, "ambiguous move '{}'", san),
+        }
+    }
+}
+
+impl std::error::Error for SanError {}
+
+impl Game {
+    // Formats a legal move in standard algebraic notation, like Nbd7, exd6,
+    // e8=Q+ or O-O#.
+    pub fn move_to_san(&self, mv: Move) -> String {
+        let mut san = match mv.flags() {
+            0x2 => "O-O".to_string(),
+            0x3 => "O-O-O".to_string(),
+            _ => self.piece_move_to_san(mv),
+        };
+
+        let mut after = self.clone();
+        after.make_move(mv.raw());
+        if after.in_check() {
+            san.push(if after.generate_legal_moves().is_empty() {
+                '#'
+            } else {
+                '+'
+            });
+        }
+        san
+    }
+
+    fn piece_move_to_san(&self, mv: Move) -> String {
+        let piece_type = self.piece_type_at(mv.from());
+        let mut san = String::new();
+        if piece_type == Some(PieceType::Pawn) {
+            if mv.is_capture() {
+                san.push(mv.from().file().to_char());
+            }
+        } else {
+            if let Some(piece_type) = piece_type {
+                san.push(piece_type.to_char().to_ascii_uppercase());
+            }
+            // other pieces of the same type that can go to the same square
+            let others: Vec<Square> = self
+                .legal_moves()
+                .into_iter()
+                .filter(|other| {
+                    other.to() == mv.to()
+                        && other.from() != mv.from()
+                        && self.piece_type_at(other.from()) == piece_type
+                })
+                .map(|other| other.from())
+                .collect();
+            if !others.is_empty() {
+                let from = mv.from();
+                if others.iter().all(|other| other.file() != from.file()) {
+                    san.push(from.file().to_char());
+                } else if others.iter().all(|other| other.rank() != from.rank()) {
+                    san.push(from.rank().to_char());
+                } else {
+                    san.push_str(&from.to_string());
+                }
+            }
+        }
+        if mv.is_capture() {
+            san.push('x');
+        }
+        san.push_str(&mv.to().to_string());
+        if let Some(promotion) = mv.promotion() {
+            san.push('=');
+            san.push(promotion.to_char().to_ascii_uppercase());
+        }
+        san
+    }
+
+    // Finds the legal move written in standard algebraic notation. Check and
+    // annotation suffixes are ignored and common variations are accepted:
+    // 0-0 for castling, a missing or extra capture sign, e8Q for e8=Q,
+    // exd6e.p. and long forms like Ng1-f3.
+    pub fn parse_san(&self, san: &str) -> Result<Move, SanError> {
+        let invalid = || SanError::Invalid(san.to_string());
+        let mut text = san.trim().trim_end_matches(['+', '#', '!', '?']);
+        if let Some(stripped) = text.strip_suffix("e.p.") {
+            text = stripped.trim_end();
+        }
+
+        let castling = match text {
+            "O-O" | "0-0" => Some(0x2),
+            "O-O-O" | "0-0-0" => Some(0x3),
+            _ => None,
+        };
+        if let Some(flags) = castling {
+            return self.find_move(san, |mv| mv.flags() == flags);
+        }
+
+        let mut chars: Vec<char> = text
+            .chars()
+            .filter(|c| !matches!(c, 'x' | ':' | '-' | '='))
+            .collect();
+        let piece_type = match chars.first() {
+            Some('K') => PieceType::King,
+            Some('Q') => PieceType::Queen,
+            Some('R') => PieceType::Rook,
+            Some('B') => PieceType::Bishop,
+            Some('N') => PieceType::Knight,
+            _ => PieceType::Pawn,
+        };
+        if piece_type != PieceType::Pawn {
+            chars.remove(0);
+        }
+        // the promotion piece follows the destination square
+        let mut promotion = None;
+        if chars.len() >= 3
+            && chars[chars.len() - 2].is_ascii_digit()
+            && chars[chars.len() - 1].is_ascii_alphabetic()
+        {
+            promotion = Some(match chars.pop().unwrap().to_ascii_uppercase() {
+                'Q' => PieceType::Queen,
+                'R' => PieceType::Rook,
+                'B' => PieceType::Bishop,
+                'N' => PieceType::Knight,
+                _ => return Err(invalid()),
+            });
+        }
+        if chars.len() < 2 || chars.len() > 4 {
+            return Err(invalid());
+        }
+        let to: Square = chars[chars.len() - 2..]
+            .iter()
+            .collect::<String>()
+            .parse()
+            .map_err(|_| invalid())?;
+        // disambiguation by file, rank or both
+        let (mut from_file, mut from_rank) = (None, None);
+        for &c in &chars[..chars.len() - 2] {
+            match c {
+                'a'..='h' if from_file.is_none() && from_rank.is_none() => from_file = Some(c),
+                '1'..='8' if from_rank.is_none() => from_rank = Some(c),
+                _ => return Err(invalid()),
+            }
+        }
+
+        self.find_move(san, |mv| {
+            mv.to() == to
+                && !mv.is_castling()
+                && promotion.is_none_or(|piece_type| mv.promotion() == Some(piece_type))
+                && self.piece_type_at(mv.from()) == Some(piece_type)
+                && from_file.is_none_or(|file| mv.from().file().to_char() == file)
+                && from_rank.is_none_or(|rank| mv.from().rank().to_char() == rank)
+        })
+    }
+
+    fn find_move(&self, san: &str, matches: impl Fn(Move) -> bool) -> Result<Move, SanError> {
+        let mut candidates = self.legal_moves().into_iter().filter(|&mv| matches(mv));
+        match (candidates.next(), candidates.next()) {
+            (Some(mv), None) => Ok(mv),
+            (None, _) => Err(SanError::Illegal(san.to_string())),
+            _ => Err(SanError::Ambiguous(san.to_string())),
+        }
+    }
+
+    fn piece_type_at(&self, square: Square) -> Option<PieceType> {
+        self.piece_at(square).map(|(_, piece_type)| piece_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn san_of(fen: &str, uci: &str) -> String {
+        let game = Game::from_fen(fen).unwrap();
+        game.move_to_san(game.parse_uci(uci).unwrap())
+    }
+
+    fn uci_of(fen: &str, san: &str) -> Result<String, SanError> {
+        let game = Game::from_fen(fen).unwrap();
+        game.parse_san(san).map(|mv| mv.to_string())
+    }
+
+    #[test]
+    fn test_move_to_san() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(san_of(start, "e2e4"), "e4");
+        assert_eq!(san_of(start, "g1f3"), "Nf3");
+        // captures, en passant, promotion with check
+        let fen = "r3k3/1P6/8/3pP3/8/8/8/4K3 w q d6 0 1";
+        assert_eq!(san_of(fen, "e5d6"), "exd6");
+        assert_eq!(san_of(fen, "b7a8q"), "bxa8=Q+");
+        assert_eq!(san_of(fen, "b7b8n"), "b8=N");
+        // castling and mate
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        assert_eq!(san_of(fen, "e1g1"), "O-O");
+        assert_eq!(san_of(fen, "e1c1"), "O-O-O");
+        assert_eq!(san_of("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", "a1a8"), "Ra8#");
+    }
+
+    #[test]
+    fn test_move_to_san_disambiguation() {
+        // knights on b1 and f3 can both go to d2, rooks on a1 and a5 to a3
+        let fen = "4k3/8/8/R7/8/5N2/8/RN2K3 w - - 0 1";
+        assert_eq!(san_of(fen, "b1d2"), "Nbd2");
+        assert_eq!(san_of(fen, "a1a3"), "R1a3");
+        assert_eq!(san_of(fen, "a5a3"), "R5a3");
+        assert_eq!(san_of(fen, "f3e5"), "Ne5");
+        // three queens where neither file nor rank is unique
+        let fen = "7k/8/8/8/Q1Q5/8/Q7/4K3 w - - 0 1";
+        assert_eq!(san_of(fen, "a4b3"), "Qa4b3");
+        assert_eq!(san_of(fen, "c4b3"), "Qcb3");
+        assert_eq!(san_of(fen, "a2b3"), "Q2b3");
+    }
+
+    #[test]
+    fn test_parse_san() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(uci_of(start, "e4").unwrap(), "e2e4");
+        assert_eq!(uci_of(start, "Nf3").unwrap(), "g1f3");
+        assert_eq!(uci_of(start, "Ng1-f3").unwrap(), "g1f3");
+        assert_eq!(uci_of(start, "Nf3!?").unwrap(), "g1f3");
+        let fen = "r3k3/1P6/8/3pP3/8/8/8/4K3 w q d6 0 1";
+        assert_eq!(uci_of(fen, "exd6").unwrap(), "e5d6");
+        assert_eq!(uci_of(fen, "exd6e.p.").unwrap(), "e5d6");
+        assert_eq!(uci_of(fen, "exd6 e.p.").unwrap(), "e5d6");
+        assert_eq!(uci_of(fen, "bxa8=Q+").unwrap(), "b7a8q");
+        assert_eq!(uci_of(fen, "ba8N").unwrap(), "b7a8n");
+        assert_eq!(uci_of(fen, "b8=R").unwrap(), "b7b8r");
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1";
+        assert_eq!(uci_of(fen, "O-O").unwrap(), "e8g8");
+        assert_eq!(uci_of(fen, "0-0-0").unwrap(), "e8c8");
+        let fen = "4k3/8/8/R7/8/5N2/8/RN2K3 w - - 0 1";
+        assert_eq!(uci_of(fen, "Nbd2").unwrap(), "b1d2");
+        assert_eq!(uci_of(fen, "R1a3").unwrap(), "a1a3");
+        assert_eq!(uci_of(fen, "Ra5a3").unwrap(), "a5a3");
+    }
+
+    #[test]
+    fn test_parse_san_errors() {
+        let fen = "r3k3/1P6/8/3pP3/8/8/8/4K3 w q d6 0 1";
+        for invalid in ["", "Xe4", "e9", "Nbbd2", "b8=K", "hello"] {
+            assert_eq!(
+                uci_of(fen, invalid),
+                Err(SanError::Invalid(invalid.to_string())),
+                "{}",
+                invalid
+            );
+        }
+        assert_eq!(uci_of(fen, "e4"), Err(SanError::Illegal("e4".to_string())));
+        assert_eq!(
+            uci_of(fen, "O-O"),
+            Err(SanError::Illegal("O-O".to_string()))
+        );
+        // the promotion piece is missing
+        assert_eq!(
+            uci_of(fen, "b8"),
+            Err(SanError::Ambiguous("b8".to_string()))
+        );
+        let fen = "4k3/8/8/R7/8/5N2/8/RN2K3 w - - 0 1";
+        assert_eq!(
+            uci_of(fen, "Nd2"),
+            Err(SanError::Ambiguous("Nd2".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_san_round_trip() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let game = Game::from_fen(fen).unwrap();
+        for mv in game.legal_moves() {
+            assert_eq!(game.parse_san(&game.move_to_san(mv)), Ok(mv));
+        }
+    }
+}
@@ -0,0 +1,1224 @@
+use crate::constants::MAX_PLY;
+use crate::evaluation::evaluate;
+use crate::game::Game;
+use crate::move_picker::{History, MovePicker, is_quiet, mvv_lva};
+use crate::search_parameters::{ParameterError, SearchParameters};
+use crate::syzygy::{Tablebases, Wdl};
+use crate::time_manager::{DEFAULT_MOVE_OVERHEAD_MS, TimeManager};
+use crate::transposition_table::{Bound, DEFAULT_HASH_SIZE_MB, TranspositionTable};
+use crate::types::Move;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+pub const INFINITY: i32 = 32001;
+pub const MATE_SCORE: i32 = 32000;
+// scores beyond this bound encode a forced mate
+pub const MATE_THRESHOLD: i32 = MATE_SCORE - MAX_PLY as i32;
+// a win known from the tablebases, scored below any mate
+pub const TB_WIN_SCORE: i32 = MATE_THRESHOLD - 1;
+pub const TB_WIN_THRESHOLD: i32 = TB_WIN_SCORE - MAX_PLY as i32;
+// remaining depth from which positions with as many pieces as the largest
+// tablebases are probed, smaller ones are always probed
+pub const DEFAULT_TB_PROBE_DEPTH: u32 = 1;
+// how many nodes are searched between two checks of the clock
+const TIME_CHECK_INTERVAL: u64 = 2048;
+// half width of the window around the previous score, from this depth on
+const ASPIRATION_WINDOW: i32 = 50;
+const ASPIRATION_MIN_DEPTH: u32 = 4;
+// the move searched at the root is only reported for long searches
+const CURRENT_MOVE_REPORT_DELAY: Duration = Duration::from_secs(3);
+
+// Limits of a search as given by the parameters of the UCI go command. A
+// search without any limit runs until it reaches the maximum depth.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SearchLimits {
+    pub white_time: Option<Duration>,
+    pub black_time: Option<Duration>,
+    pub white_increment: Option<Duration>,
+    pub black_increment: Option<Duration>,
+    pub moves_to_go: Option<u64>,
+    pub depth: Option<u32>,
+    pub nodes: Option<u64>,
+    // search for a mate in this many moves
+    pub mate: Option<u32>,
+    pub move_time: Option<Duration>,
+    pub infinite: bool,
+    // search on the opponent's time, the clock only starts with ponderhit
+    pub ponder: bool,
+    // restricts the root to these moves if not empty
+    pub search_moves: Vec<Move>,
+}
+
+impl SearchLimits {
+    pub fn depth(depth: u32) -> Self {
+        SearchLimits {
+            depth: Some(depth),
+            ..SearchLimits::default()
+        }
+    }
+}
+
+// Flags to control a search running on another thread.
+#[derive(Debug, Default)]
+pub struct SearchSignals {
+    stop: AtomicBool,
+    pondering: AtomicBool,
+}
+
+impl SearchSignals {
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    // the opponent played the expected move, the search continues on our clock
+    pub fn ponderhit(&self) {
+        self.pondering.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_pondering(&self) -> bool {
+        self.pondering.load(Ordering::Relaxed)
+    }
+
+    // has to be called before a search is started
+    pub fn reset(&self, ponder: bool) {
+        self.stop.store(false, Ordering::Relaxed);
+        self.pondering.store(ponder, Ordering::Relaxed);
+    }
+}
+
+// Progress of a running search, reported to the function set with
+// `Search::set_reporter`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SearchReport {
+    Line {
+        depth: u32,
+        // the maximum ply reached, including quiescence
+        seldepth: usize,
+        // the index of the line starting with 1
+        multipv: usize,
+        score: i32,
+        // bounds are reported when the score fell outside the aspiration window
+        bound: Bound,
+        nodes: u64,
+        time: Duration,
+        // permille of the transposition table used by this search
+        hashfull: usize,
+        tb_hits: u64,
+        pv: Vec<Move>,
+    },
+    CurrentMove {
+        depth: u32,
+        mv: Move,
+        // the position of the move in the search order starting with 1
+        number: usize,
+    },
+}
+
+type Reporter = Box<dyn Fn(&SearchReport) + Send>;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PvLine {
+    pub score: i32,
+    pub pv: Vec<Move>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchResult {
+    pub best_move: Option<Move>,
+    pub score: i32,
+    pub depth: u32,
+    pub nodes: u64,
+    pub pv: Vec<Move>,
+    // with MultiPV the best lines for different root moves, best first
+    pub lines: Vec<PvLine>,
+}
+
+pub struct Search {
+    nodes: u64,
+    // triangular principal variation table, pv_table[ply] holds the best
+    // line found from that ply on, pv_length[ply] its length
+    pv_table: [[u16; MAX_PLY]; MAX_PLY],
+    pv_length: [usize; MAX_PLY],
+    tt: TranspositionTable,
+    history: History,
+    parameters: SearchParameters,
+    // late move reductions by depth and move number, from the parameters
+    reductions: [[u8; 64]; 64],
+    move_overhead: Duration,
+    multi_pv: usize,
+    signals: Arc<SearchSignals>,
+    reporter: Option<Reporter>,
+    // state of the running search
+    start: Instant,
+    time_manager: Option<TimeManager>,
+    seldepth: usize,
+    tb_hits: u64,
+    max_nodes: Option<u64>,
+    root_moves: Vec<u16>,
+    // root moves of the lines already found in this iteration
+    excluded_root_moves: Vec<u16>,
+    root_depth: u32,
+    pondering: bool,
+    stopped: bool,
+    // no null moves below a null move cutoff that is being verified
+    verifying_null_move: bool,
+    tablebases: Option<Tablebases>,
+    tb_probe_depth: u32,
+    // positions with at most this many pieces are probed, 0 once the root
+    // moves are ranked by the tablebases
+    tb_pieces: usize,
+}
+
+impl Default for Search {
+    fn default() -> Self {
+        Search::new()
+    }
+}
+
+impl Search {
+    pub fn new() -> Self {
+        Search {
+            nodes: 0,
+            pv_table: [[0; MAX_PLY]; MAX_PLY],
+            pv_length: [0; MAX_PLY],
+            tt: TranspositionTable::new(DEFAULT_HASH_SIZE_MB),
+            history: History::new(),
+            parameters: SearchParameters::default(),
+            reductions: SearchParameters::default().late_move_reductions(),
+            move_overhead: Duration::from_millis(DEFAULT_MOVE_OVERHEAD_MS),
+            multi_pv: 1,
+            signals: Arc::new(SearchSignals::default()),
+            reporter: None,
+            start: Instant::now(),
+            time_manager: None,
+            seldepth: 0,
+            tb_hits: 0,
+            max_nodes: None,
+            root_moves: Vec::new(),
+            excluded_root_moves: Vec::new(),
+            root_depth: 0,
+            pondering: false,
+            stopped: false,
+            verifying_null_move: false,
+            tablebases: None,
+            tb_probe_depth: DEFAULT_TB_PROBE_DEPTH,
+            tb_pieces: 0,
+        }
+    }
+
+    // nodes searched by the last search, including an interrupted iteration
+    pub fn nodes(&self) -> u64 {
+        self.nodes
+    }
+
+    // tablebase probes of the last search
+    pub fn tb_hits(&self) -> u64 {
+        self.tb_hits
+    }
+
+    pub fn signals(&self) -> Arc<SearchSignals> {
+        Arc::clone(&self.signals)
+    }
+
+    pub fn set_hash_size(&mut self, size_mb: usize) {
+        self.tt = TranspositionTable::new(size_mb);
+    }
+
+    pub fn clear_hash(&mut self) {
+        self.tt.clear();
+    }
+
+    // forgets everything learned from the previous game
+    pub fn new_game(&mut self) {
+        self.tt.clear();
+        self.history.clear();
+    }
+
+    pub fn set_move_overhead(&mut self, move_overhead: Duration) {
+        self.move_overhead = move_overhead;
+    }
+
+    pub fn set_multi_pv(&mut self, multi_pv: usize) {
+        self.multi_pv = multi_pv.max(1);
+    }
+
+    pub fn set_tablebases(&mut self, tablebases: Option<Tablebases>) {
+        self.tablebases = tablebases;
+    }
+
+    pub fn set_tb_probe_depth(&mut self, depth: u32) {
+        self.tb_probe_depth = depth.max(1);
+    }
+
+    pub fn parameters(&self) -> &SearchParameters {
+        &self.parameters
+    }
+
+    pub fn set_parameters(&mut self, parameters: SearchParameters) {
+        self.reductions = parameters.late_move_reductions();
+        self.parameters = parameters;
+    }
+
+    // sets one of the search parameters by name, see `SearchParameters::set`
+    pub fn set_parameter(&mut self, name: &str, value: &str) -> Result<(), ParameterError> {
+        let mut parameters = self.parameters.clone();
+        parameters.set(name, value)?;
+        self.set_parameters(parameters);
+        Ok(())
+    }
+
+    pub fn set_reporter(&mut self, reporter: impl Fn(&SearchReport) + Send + 'static) {
+        self.reporter = Some(Box::new(reporter));
+    }
+
+    // Searches the position with increasing depth until one of the limits is
+    // reached and returns the result of the last completed iteration.
+    pub fn iterative_deepening(&mut self, game: &mut Game, limits: &SearchLimits) -> SearchResult {
+        self.nodes = 0;
+        self.tb_hits = 0;
+        self.start = Instant::now();
+        self.stopped = false;
+        self.verifying_null_move = false;
+        self.pondering = limits.ponder;
+        self.tt.new_search();
+        self.history.clear_killers();
+        let white_to_move = game.history.current_state().white_to_move;
+        self.time_manager = Some(TimeManager::new(limits, white_to_move, self.move_overhead));
+        self.max_nodes = limits.nodes;
+        self.root_moves = limits.search_moves.iter().map(|mv| mv.raw()).collect();
+        self.rank_root_moves(game);
+        let mut max_depth = limits.depth.unwrap_or(MAX_PLY as u32);
+        // a mate in n moves is found by a search of 2n - 1 plies
+        if let Some(mate) = limits.mate {
+            max_depth = max_depth.min((2 * mate).saturating_sub(1).max(1));
+        }
+        let mut result = SearchResult {
+            best_move: None,
+            score: 0,
+            depth: 0,
+            nodes: 0,
+            pv: Vec::new(),
+            lines: Vec::new(),
+        };
+
+        'deepening: for depth in 1..=max_depth.min(MAX_PLY as u32 - 1) {
+            self.root_depth = depth;
+            self.seldepth = 0;
+            // each further line is searched without the root moves of the
+            // lines before it
+            let mut lines: Vec<PvLine> = Vec::new();
+            self.excluded_root_moves.clear();
+            for index in 0..self.multi_pv {
+                let score = self.aspiration_search(game, depth, index + 1, result.lines.get(index));
+                // the result of an interrupted iteration is incomplete
+                if self.stopped {
+                    break 'deepening;
+                }
+                let pv = self.principal_variation();
+                // no root moves left for another line
+                if index > 0 && pv.is_empty() {
+                    break;
+                }
+                self.excluded_root_moves
+                    .extend(pv.first().map(|mv| mv.raw()));
+                lines.push(PvLine { score, pv });
+            }
+            self.excluded_root_moves.clear();
+            lines.sort_by_key(|line| -line.score);
+            for (index, line) in lines.iter().enumerate() {
+                self.report_line(depth, index + 1, line.score, Bound::Exact, &line.pv);
+            }
+
+            let score = lines[0].score;
+            let pv = lines[0].pv.clone();
+            result = SearchResult {
+                best_move: pv.first().copied(),
+                score,
+                depth,
+                nodes: self.nodes,
+                pv,
+                lines,
+            };
+            // no need to search deeper once a forced mate is found
+            if score.abs() >= MATE_THRESHOLD {
+                break;
+            }
+            if self.signals.is_stopped() || self.soft_limit_reached() {
+                break;
+            }
+        }
+
+        self.time_manager = None;
+        result
+    }
+
+    // If the root is in the tablebases, only the moves that keep its result
+    // are searched. Ranked by DTZ these are the ones that zero the 50-move
+    // counter the fastest, so a won endgame is converted, and the tables are
+    // not probed any further. With the WDL tables alone the search still
+    // needs them to make progress.
+    fn rank_root_moves(&mut self, game: &mut Game) {
+        self.tb_pieces = 0;
+        let Some(tablebases) = &self.tablebases else {
+            return;
+        };
+        self.tb_pieces = tablebases.max_pieces();
+        let mut moves = game.generate_legal_moves();
+        if !self.root_moves.is_empty() {
+            moves.retain(|mv| self.root_moves.contains(mv));
+        }
+        let (ranks, by_dtz) = match tablebases.rank_root_moves(game, &moves) {
+            Some(ranks) => (ranks, true),
+            None => match tablebases.rank_root_moves_wdl(game, &moves) {
+                Some(ranks) => (ranks, false),
+                None => return,
+            },
+        };
+        let Some(&best) = ranks.iter().max() else {
+            return;
+        };
+        self.tb_hits += moves.len() as u64;
+        self.root_moves = moves
+            .into_iter()
+            .zip(ranks)
+            .filter(|&(_, rank)| rank == best)
+            .map(|(mv, _)| mv)
+            .collect();
+        if by_dtz || best <= 0 {
+            self.tb_pieces = 0;
+        }
+    }
+
+    // Searches the root with a window around the score of the previous
+    // iteration. If the score falls outside, the window is widened and the
+    // root searched again.
+    fn aspiration_search(
+        &mut self,
+        game: &mut Game,
+        depth: u32,
+        multipv: usize,
+        previous: Option<&PvLine>,
+    ) -> i32 {
+        let previous_pv: Vec<u16> = previous.map_or(Vec::new(), |line| {
+            line.pv.iter().map(|mv| mv.raw()).collect()
+        });
+        let mut delta = ASPIRATION_WINDOW;
+        let (mut alpha, mut beta) = match previous {
+            Some(line) if depth >= ASPIRATION_MIN_DEPTH && line.score.abs() < MATE_THRESHOLD => {
+                (line.score - delta, line.score + delta)
+            }
+            _ => (-INFINITY, INFINITY),
+        };
+        loop {
+            let score = self.negamax(game, depth, 0, alpha, beta, &previous_pv);
+            if self.stopped {
+                return score;
+            }
+            if score <= alpha && alpha > -INFINITY {
+                // no move reached alpha, so there is no new principal variation
+                let pv = previous.map_or(Vec::new(), |line| line.pv.clone());
+                self.report_line(depth, multipv, score, Bound::Upper, &pv);
+                alpha = (alpha - delta).max(-INFINITY);
+            } else if score >= beta && beta < INFINITY {
+                let pv = self.principal_variation();
+                self.report_line(depth, multipv, score, Bound::Lower, &pv);
+                beta = (beta + delta).min(INFINITY);
+            } else {
+                return score;
+            }
+            delta *= 2;
+        }
+    }
+
+    // the principal variation of the last search of the root
+    fn principal_variation(&self) -> Vec<Move> {
+        self.pv_table[0][..self.pv_length[0]]
+            .iter()
+            .map(|&mv| Move::from_raw(mv))
+            .collect()
+    }
+
+    fn report_line(&self, depth: u32, multipv: usize, score: i32, bound: Bound, pv: &[Move]) {
+        if let Some(reporter) = &self.reporter {
+            reporter(&SearchReport::Line {
+                depth,
+                seldepth: self.seldepth,
+                multipv,
+                score,
+                bound,
+                nodes: self.nodes,
+                time: self.start.elapsed(),
+                hashfull: self.tt.hashfull(),
+                tb_hits: self.tb_hits,
+                pv: pv.to_vec(),
+            });
+        }
+    }
+
+    fn report_current_move(&self, depth: u32, mv: u16, number: usize) {
+        if let Some(reporter) = &self.reporter
+            && self.start.elapsed() >= CURRENT_MOVE_REPORT_DELAY
+        {
+            reporter(&SearchReport::CurrentMove {
+                depth,
+                mv: Move::from_raw(mv),
+                number,
+            });
+        }
+    }
+
+    // The expected reply to the best move, taken from the principal variation
+    // or, if that ends after the best move, from the transposition table.
+    pub fn ponder_move(&self, game: &mut Game, result: &SearchResult) -> Option<Move> {
+        if let Some(&mv) = result.pv.get(1) {
+            return Some(mv);
+        }
+        let best_move = result.best_move?;
+        game.make_move(best_move.raw());
+        let ponder_move = self
+            .tt
+            .probe(game.zobrist_hash(), 0)
+            .and_then(|entry| entry.best_move)
+            .filter(|mv| game.generate_legal_moves().contains(mv));
+        game.undo_move();
+        ponder_move.map(Move::from_raw)
+    }
+
+    // While pondering the clock is not ours, the time limits only apply from
+    // the ponderhit on.
+    fn update_pondering(&mut self) {
+        if self.pondering && !self.signals.is_pondering() {
+            self.pondering = false;
+            if let Some(time_manager) = self.time_manager.as_mut() {
+                time_manager.restart();
+            }
+        }
+    }
+
+    fn soft_limit_reached(&mut self) -> bool {
+        self.update_pondering();
+        !self.pondering
+            && self
+                .time_manager
+                .is_some_and(|time_manager| time_manager.soft_limit_reached())
+    }
+
+    fn hard_limit_reached(&mut self) -> bool {
+        self.update_pondering();
+        !self.pondering
+            && self
+                .time_manager
+                .is_some_and(|time_manager| time_manager.hard_limit_reached())
+    }
+
+    // Checks the node and time limits and whether the search was stopped. The first iteration is always completed
+    // so that there is a move to play.
+    fn should_stop(&mut self) -> bool {
+        if self.stopped {
+            return true;
+        }
+        if self.root_depth <= 1 {
+            return false;
+        }
+        let out_of_nodes = self
+            .max_nodes
+            .is_some_and(|max_nodes| self.nodes >= max_nodes);
+        let interrupted = self.nodes.is_multiple_of(TIME_CHECK_INTERVAL)
+            && (self.signals.is_stopped() || self.hard_limit_reached());
+        self.stopped = out_of_nodes || interrupted;
+        self.stopped
+    }
+
+    // Negamax alpha-beta search with principal variation search: after the
+    // first move the others only need to be proven worse, which a null window
+    // does cheaper, and they are searched again if they turn out better.
+    // Nodes searched with a null window are the ones the selective search
+    // prunes and reduces. `previous_pv` is the principal variation of the
+    // last iteration, its moves are searched first while we are still on it.
+    fn negamax(
+        &mut self,
+        game: &mut Game,
+        mut depth: u32,
+        ply: usize,
+        mut alpha: i32,
+        beta: i32,
+        previous_pv: &[u16],
+    ) -> i32 {
+        self.nodes += 1;
+        self.pv_length[ply] = ply;
+        self.seldepth = self.seldepth.max(ply);
+        if self.should_stop() {
+            return 0;
+        }
+
+        // repetitions within the search are scored as draws right away
+        if ply > 0
+            && (game.is_repetition()
+                || game.is_fifty_move_rule()
+                || game.is_insufficient_material())
+        {
+            return 0;
+        }
+
+        // checks are searched deeper, up to twice the depth of the iteration
+        // so that long series of checks do not explode the tree
+        let in_check = game.in_check();
+        if in_check && self.parameters.check_extensions && ply < 2 * self.root_depth as usize {
+            depth += 1;
+        }
+
+        if depth == 0 || ply >= MAX_PLY - 1 {
+            return self.quiescence(game, ply, alpha, beta);
+        }
+
+        let key = game.zobrist_hash();
+        let mut tt_move = None;
+        if let Some(entry) = self.tt.probe(key, ply) {
+            tt_move = entry.best_move;
+            // the root always searches to get a best move and a principal variation
+            if ply > 0 && entry.depth as u32 >= depth {
+                match entry.bound {
+                    Bound::Exact => return entry.score,
+                    Bound::Lower if entry.score >= beta => return beta,
+                    Bound::Upper if entry.score <= alpha => return alpha,
+                    _ => {}
+                }
+            }
+        }
+
+        // Positions in the tablebases are scored without a search, right
+        // after a capture or pawn move, where the 50-move counter is known to
+        // not affect the result. The largest tables are only probed with
+        // enough depth left for the probe to pay off.
+        if ply > 0 && self.tb_pieces > 0 {
+            let pieces = game.board.piece_count() as usize;
+            let state = game.history.current_state();
+            if pieces <= self.tb_pieces
+                && (pieces < self.tb_pieces || depth >= self.tb_probe_depth)
+                && state.reversible_move_counter == 0
+                && state.castling_rights == 0
+                && let Some(wdl) = self.tablebases.as_ref().and_then(|tb| tb.probe_wdl(game))
+            {
+                self.tb_hits += 1;
+                let score = match wdl {
+                    Wdl::Win => TB_WIN_SCORE - ply as i32,
+                    Wdl::Loss => -TB_WIN_SCORE + ply as i32,
+                    // cursed wins and blessed losses are draws, but better
+                    // or worse than a plain one
+                    wdl => wdl as i32 * 2,
+                };
+                self.tt.store(key, None, score, depth, Bound::Exact, ply);
+                return score;
+            }
+        }
+
+        // Only nodes outside the principal variation are pruned, and not
+        // in check, where the static evaluation says little.
+        let white_to_move = game.history.current_state().white_to_move;
+        let pv_node = beta - alpha > 1;
+        let prunable = ply > 0 && !pv_node && !in_check;
+        let static_eval = if prunable {
+            evaluate(&game.board, white_to_move)
+        } else {
+            0
+        };
+
+        if prunable && beta.abs() < TB_WIN_THRESHOLD {
+            if let Some(score) = self.prune_node(game, depth, ply, beta, static_eval) {
+                return score;
+            }
+            if self.stopped {
+                return 0;
+            }
+        }
+
+        let mut moves = game.generate_legal_moves();
+        let restricted =
+            ply == 0 && !(self.root_moves.is_empty() && self.excluded_root_moves.is_empty());
+        if restricted {
+            moves.retain(|mv| {
+                (self.root_moves.is_empty() || self.root_moves.contains(mv))
+                    && !self.excluded_root_moves.contains(mv)
+            });
+        }
+        if moves.is_empty() {
+            // checkmate is scored by distance to the root to prefer faster mates
+            return if in_check {
+                -MATE_SCORE + ply as i32
+            } else {
+                0
+            };
+        }
+
+        // search the move of the previous principal variation first, or else
+        // the best move stored in the transposition table
+        let pv_move = previous_pv.first().copied();
+        let mut picker = MovePicker::new(moves, pv_move.or(tt_move), ply);
+
+        let mut best_move = None;
+        // quiet moves searched without a cutoff, they lose history score
+        let mut quiets = Vec::new();
+        let mut index = 0;
+
+        while let Some(mv) = picker.next(game, &self.history) {
+            // only the first move can continue the previous principal variation
+            let child_pv = if index == 0 && pv_move == Some(mv) {
+                &previous_pv[1..]
+            } else {
+                &[]
+            };
+
+            if ply == 0 {
+                self.report_current_move(depth, mv, index + 1);
+            }
+
+            game.make_move(mv);
+            let gives_check = game.in_check();
+            let quiet = is_quiet(mv) && !gives_check;
+
+            // late quiet moves are skipped once a move was searched, unless
+            // we are getting mated and need every move to find a way out
+            if prunable && quiet && index > 0 && alpha > -TB_WIN_THRESHOLD {
+                let parameters = &self.parameters;
+                let late = parameters.late_move_pruning
+                    && depth <= parameters.late_move_pruning_max_depth
+                    && quiets.len() >= parameters.late_move_pruning_base + (depth * depth) as usize;
+                let futile = parameters.futility
+                    && depth <= parameters.futility_max_depth
+                    && static_eval
+                        + parameters.futility_base
+                        + parameters.futility_margin * depth as i32
+                        <= alpha;
+                if late || futile {
+                    game.undo_move();
+                    continue;
+                }
+            }
+
+            let score = if index == 0 {
+                -self.negamax(game, depth - 1, ply + 1, -beta, -alpha, child_pv)
+            } else {
+                // late quiet moves are searched with less depth first
+                let reduction = if self.parameters.lmr
+                    && quiet
+                    && !in_check
+                    && depth >= self.parameters.lmr_min_depth
+                    && index >= self.parameters.lmr_min_moves
+                {
+                    let reduction = self.reductions[(depth as usize).min(63)][index.min(63)];
+                    // less so in the principal variation
+                    (reduction as u32)
+                        .saturating_sub(pv_node as u32)
+                        .min(depth.saturating_sub(2))
+                } else {
+                    0
+                };
+                let mut score = -self.negamax(
+                    game,
+                    depth - 1 - reduction,
+                    ply + 1,
+                    -alpha - 1,
+                    -alpha,
+                    &[],
+                );
+                if score > alpha && reduction > 0 {
+                    score = -self.negamax(game, depth - 1, ply + 1, -alpha - 1, -alpha, &[]);
+                }
+                if score > alpha && score < beta {
+                    score = -self.negamax(game, depth - 1, ply + 1, -beta, -alpha, &[]);
+                }
+                score
+            };
+            game.undo_move();
+            if self.stopped {
+                return 0;
+            }
+            index += 1;
+            if is_quiet(mv) {
+                quiets.push(mv);
+            }
+
+            if score >= beta {
+                // the root reports the move that failed high
+                if ply == 0 {
+                    self.update_pv(ply, mv);
+                }
+                if is_quiet(mv) {
+                    self.history.update(game, ply, depth, mv, &quiets);
+                }
+                self.tt.store(key, Some(mv), beta, depth, Bound::Lower, ply);
+                return beta;
+            }
+            if score > alpha {
+                alpha = score;
+                best_move = Some(mv);
+                self.update_pv(ply, mv);
+            }
+        }
+
+        // without a move raising alpha the score is only an upper bound
+        let bound = if best_move.is_some() {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        // the score of a root restricted by searchmoves or MultiPV is not the score of the position
+        if !restricted {
+            self.tt.store(key, best_move, alpha, depth, bound, ply);
+        }
+        alpha
+    }
+
+    // Tries to decide a null window node around `beta` before searching its
+    // moves, by the static evaluation or a search without a move of our own.
+    // Returns the score if that succeeds.
+    fn prune_node(
+        &mut self,
+        game: &mut Game,
+        depth: u32,
+        ply: usize,
+        beta: i32,
+        static_eval: i32,
+    ) -> Option<i32> {
+        let parameters = &self.parameters;
+        let alpha = beta - 1;
+
+        // so far above beta that no move of the opponent is likely to help
+        if parameters.reverse_futility
+            && depth <= parameters.reverse_futility_max_depth
+            && static_eval - parameters.reverse_futility_margin * depth as i32 >= beta
+        {
+            return Some(beta);
+        }
+
+        // so far below alpha that only captures can help
+        if parameters.razoring
+            && depth <= parameters.razoring_max_depth
+            && static_eval
+                + parameters.razoring_base
+                + parameters.razoring_margin * (depth * depth) as i32
+                <= alpha
+        {
+            let score = self.quiescence(game, ply, alpha, beta);
+            if score <= alpha {
+                return Some(alpha);
+            }
+        }
+
+        // If passing still fails high, a real move would too. This is wrong
+        // in zugzwang, so it is left out without pieces besides pawns, and
+        // deep cutoffs are verified by a search without null moves.
+        let state = game.history.current_state();
+        let parameters = &self.parameters;
+        if parameters.null_move
+            && !self.verifying_null_move
+            && depth >= parameters.null_move_min_depth
+            && static_eval >= beta
+            // two null moves in a row only lose depth
+            && state.current_move.is_some()
+            && game.board.has_non_pawn_material(state.white_to_move)
+        {
+            let reduction = (parameters.null_move_base_reduction
+                + depth / parameters.null_move_depth_divisor)
+                .min(depth);
+            let verify = depth >= parameters.null_move_verification_depth;
+            game.make_null_move();
+            let score = -self.negamax(game, depth - reduction, ply + 1, -beta, -alpha, &[]);
+            game.undo_move();
+            if self.stopped || score < beta {
+                return None;
+            }
+            if !verify {
+                return Some(beta);
+            }
+            self.verifying_null_move = true;
+            let score = self.negamax(game, depth - reduction, ply, alpha, beta, &[]);
+            self.verifying_null_move = false;
+            if !self.stopped && score >= beta {
+                return Some(beta);
+            }
+        }
+        None
+    }
+
+    // Resolves captures and promotions at the leaves so that positions with
+    // pieces en prise are not evaluated statically. In check all evasions are
+    // searched, since standing pat is not an option there.
+    fn quiescence(&mut self, game: &mut Game, ply: usize, mut alpha: i32, beta: i32) -> i32 {
+        self.nodes += 1;
+        self.pv_length[ply] = ply;
+        self.seldepth = self.seldepth.max(ply);
+        if self.should_stop() {
+            return 0;
+        }
+
+        let state = game.history.current_state();
+        let in_check = game.in_check();
+        if ply >= MAX_PLY - 1 {
+            return evaluate(&game.board, state.white_to_move);
+        }
+
+        let mut moves = if in_check {
+            let evasions = game.generate_legal_moves();
+            if evasions.is_empty() {
+                return -MATE_SCORE + ply as i32;
+            }
+            evasions
+        } else {
+            // the side to move can usually do at least as well as the static
+            // evaluation by not capturing at all
+            let stand_pat = evaluate(&game.board, state.white_to_move);
+            if stand_pat >= beta {
+                return beta;
+            }
+            alpha = alpha.max(stand_pat);
+            game.board
+                .generate_captures(state.white_to_move, state.en_passant_square)
+        };
+
+        moves.sort_by_key(|&mv| -mvv_lva(game, mv));
+        for mv in moves {
+            // captures that lose material in the exchange are rarely better
+            // than standing pat
+            if !in_check && !game.board.see(mv, 0) {
+                continue;
+            }
+            game.make_move(mv);
+            let score = -self.quiescence(game, ply + 1, -beta, -alpha);
+            game.undo_move();
+            if self.stopped {
+                return 0;
+            }
+
+            if score >= beta {
+                return beta;
+            }
+            if score > alpha {
+                alpha = score;
+                self.update_pv(ply, mv);
+            }
+        }
+
+        alpha
+    }
+
+    fn update_pv(&mut self, ply: usize, mv: u16) {
+        self.pv_table[ply][ply] = mv;
+        let child_length = self.pv_length[ply + 1];
+        for next_ply in ply + 1..child_length {
+            self.pv_table[ply][next_ply] = self.pv_table[ply + 1][next_ply];
+        }
+        self.pv_length[ply] = child_length.max(ply + 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn test_search_finds_mate_in_one() {
+        // scholar's mate pattern, Qxf7#
+        let mut game =
+            Game::from_fen("r1bqkbnr/pppp1ppp/2n5/4p3/2B1P3/5Q2/PPPP1PPP/RNB1K1NR w KQkq - 4 4")
+                .unwrap();
+        let result = Search::new().iterative_deepening(&mut game, &SearchLimits::depth(3));
+        assert_eq!(
+            result.best_move.map(|mv| mv.raw() & 0x0FFF),
+            Some(21 | (53 << 6))
+        );
+        assert_eq!(result.score, MATE_SCORE - 1);
+    }
+
+    #[test]
+    fn test_search_wins_material() {
+        // the black queen on d4 is hanging to the knight on f3
+        let mut game =
+            Game::from_fen("rnb1kbnr/pppp1ppp/8/4p3/3q4/5N2/PPPPPPPP/RNBQKB1R w KQkq - 0 3")
+                .unwrap();
+        let result = Search::new().iterative_deepening(&mut game, &SearchLimits::depth(2));
+        assert_eq!(
+            result.best_move.map(|mv| mv.raw() & 0x0FFF),
+            Some(21 | (27 << 6))
+        );
+        assert!(result.score > 300);
+    }
+
+    #[test]
+    fn test_search_keeps_game_unchanged() {
+        let mut game = Game::new();
+        let result = Search::new().iterative_deepening(&mut game, &SearchLimits::depth(3));
+        assert_eq!(result.depth, 3);
+        assert_eq!(result.pv.len(), 3);
+        assert_eq!(game.history.len(), 1);
+        assert_eq!(game.board.piece_list, Game::new().board.piece_list);
+    }
+
+    #[test]
+    fn test_search_records_cutoffs() {
+        let mut game = Game::new();
+        let mut search = Search::new();
+        search.iterative_deepening(&mut game, &SearchLimits::depth(4));
+        assert!((1..4).any(|ply| search.history.killers(ply)[0] != 0));
+        search.new_game();
+        assert_eq!(search.history.killers(1), [0, 0]);
+    }
+
+    #[test]
+    fn test_search_sees_recapture() {
+        // Qxd5 wins a pawn at depth 1 without quiescence, but the pawn on e6
+        // recaptures the queen
+        let mut game = Game::from_fen("4k3/8/4p3/3p4/8/8/3Q4/4K3 w - - 0 1").unwrap();
+        let result = Search::new().iterative_deepening(&mut game, &SearchLimits::depth(1));
+        assert_ne!(
+            result.best_move.map(|mv| mv.raw() & 0x0FFF),
+            Some(11 | (35 << 6))
+        );
+    }
+
+    #[test]
+    fn test_quiescence_in_check_detects_mate() {
+        // black is mated, quiescence must not stand pat in check
+        let mut game = Game::from_fen("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1").unwrap();
+        let mut search = Search::new();
+        assert_eq!(
+            search.quiescence(&mut game, 0, -INFINITY, INFINITY),
+            -MATE_SCORE
+        );
+    }
+
+    #[test]
+    fn test_search_finds_mate_in_two_with_transpositions() {
+        // 1. Ra6 bxa6 2. b7#
+        let mut game = Game::from_fen("kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1").unwrap();
+        let mut search = Search::new();
+        let result = search.iterative_deepening(&mut game, &SearchLimits::depth(4));
+        assert_eq!(result.best_move.map(|mv| mv.raw() & 0x0FFF), Some(40 << 6)); // a1a6
+        assert_eq!(result.score, MATE_SCORE - 3);
+        // searching again hits the stored entries, the mate distance must not change
+        let result = search.iterative_deepening(&mut game, &SearchLimits::depth(4));
+        assert_eq!(result.score, MATE_SCORE - 3);
+    }
+
+    #[test]
+    fn test_search_takes_perpetual_check() {
+        // black is a rook up, after 1. Qh5+ Kg8 2. Qe8+ Kh7 white repeats with Qh5+
+        let mut game = Game::from_fen("7k/6p1/6Q1/8/8/1r6/q5PP/7K w - - 0 1").unwrap();
+        for mv in [
+            46 | (39 << 6),
+            63 | (62 << 6),
+            39 | (60 << 6),
+            62 | (55 << 6),
+        ] {
+            game.make_move(mv);
+        }
+        let result = Search::new().iterative_deepening(&mut game, &SearchLimits::depth(2));
+        assert_eq!(
+            result.best_move.map(|mv| mv.raw() & 0x0FFF),
+            Some(60 | (39 << 6))
+        );
+        assert_eq!(result.score, 0);
+    }
+
+    #[test]
+    fn test_search_stalemate_is_draw() {
+        let mut game = Game::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        let result = Search::new().iterative_deepening(&mut game, &SearchLimits::depth(2));
+        assert_eq!(result.best_move, None);
+        assert_eq!(result.score, 0);
+    }
+
+    #[test]
+    fn test_search_respects_node_limit() {
+        let mut game = Game::new();
+        let limits = SearchLimits {
+            nodes: Some(5000),
+            ..SearchLimits::default()
+        };
+        let result = Search::new().iterative_deepening(&mut game, &limits);
+        assert!(result.best_move.is_some());
+        // the limit is checked at every node once the first iteration is done
+        assert!(result.nodes <= 5000);
+    }
+
+    #[test]
+    fn test_search_respects_move_time() {
+        let mut game = Game::new();
+        let limits = SearchLimits {
+            move_time: Some(Duration::from_millis(50)),
+            ..SearchLimits::default()
+        };
+        let start = std::time::Instant::now();
+        let result = Search::new().iterative_deepening(&mut game, &limits);
+        assert!(result.best_move.is_some());
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_search_moves_restrict_root() {
+        // Nxd4 wins the queen, but only a3 and h3 may be searched
+        let mut game =
+            Game::from_fen("rnb1kbnr/pppp1ppp/8/4p3/3q4/5N2/PPPPPPPP/RNBQKB1R w KQkq - 0 3")
+                .unwrap();
+        let a3 = Move::from_raw(8 | (16 << 6));
+        let h3 = Move::from_raw(15 | (23 << 6));
+        let limits = SearchLimits {
+            depth: Some(2),
+            search_moves: vec![a3, h3],
+            ..SearchLimits::default()
+        };
+        let result = Search::new().iterative_deepening(&mut game, &limits);
+        assert!([Some(a3), Some(h3)].contains(&result.best_move));
+    }
+
+    #[test]
+    fn test_search_mate_limit() {
+        let mut game =
+            Game::from_fen("r1bqkbnr/pppp1ppp/2n5/4p3/2B1P3/5Q2/PPPP1PPP/RNB1K1NR w KQkq - 4 4")
+                .unwrap();
+        let limits = SearchLimits {
+            mate: Some(1),
+            ..SearchLimits::default()
+        };
+        let result = Search::new().iterative_deepening(&mut game, &limits);
+        assert_eq!(result.depth, 1);
+        assert_eq!(result.score, MATE_SCORE - 1);
+    }
+
+    #[test]
+    fn test_ponder_move_is_legal_reply() {
+        let mut game = Game::new();
+        let mut search = Search::new();
+        let result = search.iterative_deepening(&mut game, &SearchLimits::depth(3));
+        let ponder_move = search.ponder_move(&mut game, &result).unwrap();
+        game.make_move(result.best_move.unwrap().raw());
+        assert!(game.legal_moves().contains(&ponder_move));
+        // with a principal variation of one move the reply comes from the table
+        let short = SearchResult {
+            pv: result.pv[..1].to_vec(),
+            ..result
+        };
+        game.undo_move();
+        assert_eq!(search.ponder_move(&mut game, &short), Some(ponder_move));
+    }
+
+    #[test]
+    fn test_search_multi_pv() {
+        // Nxd4 wins the queen and is the best of the lines
+        let mut game =
+            Game::from_fen("rnb1kbnr/pppp1ppp/8/4p3/3q4/5N2/PPPPPPPP/RNBQKB1R w KQkq - 0 3")
+                .unwrap();
+        let mut search = Search::new();
+        search.set_multi_pv(4);
+        let result = search.iterative_deepening(&mut game, &SearchLimits::depth(3));
+        assert_eq!(result.lines.len(), 4);
+        assert_eq!(
+            result.best_move.map(|mv| mv.raw() & 0x0FFF),
+            Some(21 | (27 << 6))
+        );
+        assert_eq!(result.lines[0].pv, result.pv);
+        let mut root_moves: Vec<Move> = result.lines.iter().map(|line| line.pv[0]).collect();
+        root_moves.dedup();
+        assert_eq!(root_moves.len(), 4);
+        assert!(
+            result
+                .lines
+                .windows(2)
+                .all(|pair| pair[0].score >= pair[1].score)
+        );
+    }
+
+    #[test]
+    fn test_multi_pv_with_few_moves() {
+        // the king has only three moves
+        let mut game = Game::from_fen("k7/8/8/8/8/8/2R5/7K b - - 0 1").unwrap();
+        let mut search = Search::new();
+        search.set_multi_pv(5);
+        let result = search.iterative_deepening(&mut game, &SearchLimits::depth(2));
+        assert_eq!(result.lines.len(), 3);
+    }
+
+    static REPORTED_LINES: AtomicUsize = AtomicUsize::new(0);
+
+    fn count_lines(report: &SearchReport) {
+        if let SearchReport::Line {
+            multipv, seldepth, ..
+        } = report
+        {
+            assert!((1..=2).contains(multipv));
+            assert!(*seldepth > 0);
+            REPORTED_LINES.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_search_reports_lines() {
+        let mut game = Game::new();
+        let mut search = Search::new();
+        search.set_multi_pv(2);
+        search.set_reporter(count_lines);
+        search.iterative_deepening(&mut game, &SearchLimits::depth(3));
+        // one line per iteration and pv, aspiration windows only start at depth 4
+        assert_eq!(REPORTED_LINES.load(Ordering::Relaxed), 6);
+    }
+
+    #[test]
+    fn test_selective_search_saves_nodes() {
+        let fen = "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4";
+        let mut nodes = Vec::new();
+        for parameters in [SearchParameters::default(), SearchParameters::disabled()] {
+            let mut game = Game::from_fen(fen).unwrap();
+            let mut search = Search::new();
+            search.set_parameters(parameters);
+            let result = search.iterative_deepening(&mut game, &SearchLimits::depth(5));
+            assert!(result.best_move.is_some());
+            nodes.push(search.nodes());
+        }
+        assert!(nodes[0] * 2 < nodes[1], "{:?}", nodes);
+    }
+
+    #[test]
+    fn test_set_parameter_rebuilds_reductions() {
+        let mut search = Search::new();
+        assert_eq!(search.reductions[1][1], 0);
+        search.set_parameter("LmrBase", "200").unwrap();
+        assert_eq!(search.reductions[1][1], 2);
+        assert_eq!(
+            search.set_parameter("LmrBase", "-1"),
+            Err(ParameterError::InvalidValue("-1".to_string()))
+        );
+        assert_eq!(search.parameters().lmr_base, 200);
+    }
+
+    #[test]
+    fn test_aspiration_search_agrees_with_full_window() {
+        let mut game =
+            Game::from_fen("r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5Q2/PPPP1PPP/RNB1K1NR w KQkq - 4 4")
+                .unwrap();
+        // pruning depends on the window, so only a plain alpha-beta search is
+        // guaranteed to find the same score
+        let mut search = Search::new();
+        search.set_parameters(SearchParameters::disabled());
+        let score = search.negamax(&mut game, 4, 0, -INFINITY, INFINITY, &[]);
+        // a far too optimistic and a far too pessimistic previous score make
+        // the first window fail low and high
+        for previous_score in [score + 500, score - 500] {
+            let previous = PvLine {
+                score: previous_score,
+                pv: Vec::new(),
+            };
+            let mut search = Search::new();
+            search.set_parameters(SearchParameters::disabled());
+            assert_eq!(
+                search.aspiration_search(&mut game, 4, 1, Some(&previous)),
+                score
+            );
+        }
+    }
+}
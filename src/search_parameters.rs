@@ -0,0 +1,238 @@
+use std::fmt;
+use std::str::FromStr;
+
+// The pruning and reduction rules of the search, each with its own switch
+// and tuning values, so that they can be tested against each other with
+// `setoption`. Margins are in centipawns, depths in plies.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchParameters {
+    // null move pruning: if passing still fails high, a move will too
+    pub null_move: bool,
+    pub null_move_min_depth: u32,
+    // the null move is searched with `base + depth / divisor` plies less
+    pub null_move_base_reduction: u32,
+    pub null_move_depth_divisor: u32,
+    // from this depth on a null move cutoff is verified by a reduced search
+    // without null moves, which catches zugzwang
+    pub null_move_verification_depth: u32,
+
+    // late move reductions: moves ordered late are searched with less depth
+    pub lmr: bool,
+    pub lmr_min_depth: u32,
+    // moves searched before the reductions start
+    pub lmr_min_moves: usize,
+    // the reduction is `base + ln(depth) * ln(move number) / divisor`, both
+    // in hundredths of a ply
+    pub lmr_base: u32,
+    pub lmr_divisor: u32,
+
+    // reverse futility pruning: a static evaluation this far above beta
+    // fails high without a search
+    pub reverse_futility: bool,
+    pub reverse_futility_max_depth: u32,
+    pub reverse_futility_margin: i32,
+
+    // futility pruning: quiet moves are skipped when the static evaluation
+    // and a margin cannot reach alpha
+    pub futility: bool,
+    pub futility_max_depth: u32,
+    pub futility_base: i32,
+    pub futility_margin: i32,
+
+    // razoring: a static evaluation `base + margin * depth * depth` below
+    // alpha drops into the quiescence search
+    pub razoring: bool,
+    pub razoring_max_depth: u32,
+    pub razoring_base: i32,
+    pub razoring_margin: i32,
+
+    // late move pruning: only `base + depth * depth` quiet moves are searched
+    pub late_move_pruning: bool,
+    pub late_move_pruning_max_depth: u32,
+    pub late_move_pruning_base: usize,
+
+    // positions in check are searched one ply deeper
+    pub check_extensions: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParameterError {
+    UnknownParameter(String),
+    InvalidValue(String),
+}
+
+impl fmt::Display for ParameterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParameterError::UnknownParameter(name) => write!(f, "unknown parameter {}", name),
+            ParameterError::InvalidValue(value) => write!(f, "invalid value '{}'", value),
+        }
+    }
+}
+
+impl std::error::Error for ParameterError {}
+
+impl Default for SearchParameters {
+    fn default() -> Self {
+        SearchParameters {
+            null_move: true,
+            null_move_min_depth: 3,
+            null_move_base_reduction: 2,
+            null_move_depth_divisor: 4,
+            null_move_verification_depth: 12,
+            lmr: true,
+            lmr_min_depth: 3,
+            lmr_min_moves: 3,
+            lmr_base: 75,
+            lmr_divisor: 225,
+            reverse_futility: true,
+            reverse_futility_max_depth: 7,
+            reverse_futility_margin: 80,
+            futility: true,
+            futility_max_depth: 4,
+            futility_base: 100,
+            futility_margin: 100,
+            razoring: true,
+            razoring_max_depth: 3,
+            razoring_base: 450,
+            razoring_margin: 250,
+            late_move_pruning: true,
+            late_move_pruning_max_depth: 6,
+            late_move_pruning_base: 3,
+            check_extensions: true,
+        }
+    }
+}
+
+impl SearchParameters {
+    // all of the selective search switched off, a plain alpha-beta search
+    pub fn disabled() -> SearchParameters {
+        SearchParameters {
+            null_move: false,
+            lmr: false,
+            reverse_futility: false,
+            futility: false,
+            razoring: false,
+            late_move_pruning: false,
+            check_extensions: false,
+            ..SearchParameters::default()
+        }
+    }
+
+    // Sets a parameter by its name without underscores, ignoring case, e.g.
+    // `NullMoveMinDepth`. The switches take `true` or `false`.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), ParameterError> {
+        match name.to_lowercase().as_str() {
+            "nullmove" => self.null_move = parse(value)?,
+            "nullmovemindepth" => self.null_move_min_depth = parse(value)?,
+            "nullmovebasereduction" => self.null_move_base_reduction = parse(value)?,
+            "nullmovedepthdivisor" => self.null_move_depth_divisor = positive(value)?,
+            "nullmoveverificationdepth" => self.null_move_verification_depth = parse(value)?,
+            "lmr" => self.lmr = parse(value)?,
+            "lmrmindepth" => self.lmr_min_depth = parse(value)?,
+            "lmrminmoves" => self.lmr_min_moves = parse(value)?,
+            "lmrbase" => self.lmr_base = parse(value)?,
+            "lmrdivisor" => self.lmr_divisor = positive(value)?,
+            "reversefutility" => self.reverse_futility = parse(value)?,
+            "reversefutilitymaxdepth" => self.reverse_futility_max_depth = parse(value)?,
+            "reversefutilitymargin" => self.reverse_futility_margin = parse(value)?,
+            "futility" => self.futility = parse(value)?,
+            "futilitymaxdepth" => self.futility_max_depth = parse(value)?,
+            "futilitybase" => self.futility_base = parse(value)?,
+            "futilitymargin" => self.futility_margin = parse(value)?,
+            "razoring" => self.razoring = parse(value)?,
+            "razoringmaxdepth" => self.razoring_max_depth = parse(value)?,
+            "razoringbase" => self.razoring_base = parse(value)?,
+            "razoringmargin" => self.razoring_margin = parse(value)?,
+            "latemovepruning" => self.late_move_pruning = parse(value)?,
+            "latemovepruningmaxdepth" => self.late_move_pruning_max_depth = parse(value)?,
+            "latemovepruningbase" => self.late_move_pruning_base = parse(value)?,
+            "checkextensions" => self.check_extensions = parse(value)?,
+            _ => return Err(ParameterError::UnknownParameter(name.to_string())),
+        }
+        Ok(())
+    }
+
+    // The late move reductions by depth and move number, both capped at 63.
+    pub fn late_move_reductions(&self) -> [[u8; 64]; 64] {
+        let mut table = [[0; 64]; 64];
+        for (depth, reductions) in table.iter_mut().enumerate().skip(1) {
+            for (moves, reduction) in reductions.iter_mut().enumerate().skip(1) {
+                let scaled = self.lmr_base as f64
+                    + (depth as f64).ln() * (moves as f64).ln() * 10000.0 / self.lmr_divisor as f64;
+                *reduction = (scaled / 100.0).min(u8::MAX as f64) as u8;
+            }
+        }
+        table
+    }
+}
+
+fn parse<T: FromStr>(value: &str) -> Result<T, ParameterError> {
+    value
+        .parse()
+        .map_err(|_| ParameterError::InvalidValue(value.to_string()))
+}
+
+// divisors must not be zero
+fn positive(value: &str) -> Result<u32, ParameterError> {
+    match parse(value)? {
+        0 => Err(ParameterError::InvalidValue(value.to_string())),
+        number => Ok(number),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_parameters() {
+        let mut parameters = SearchParameters::default();
+        parameters.set("NullMove", "false").unwrap();
+        parameters.set("LmrBase", "50").unwrap();
+        parameters.set("futilitymargin", "-20").unwrap();
+        assert!(!parameters.null_move);
+        assert_eq!(parameters.lmr_base, 50);
+        assert_eq!(parameters.futility_margin, -20);
+
+        let unchanged = parameters.clone();
+        assert_eq!(
+            parameters.set("Lmr", "yes"),
+            Err(ParameterError::InvalidValue("yes".to_string()))
+        );
+        assert_eq!(
+            parameters.set("LmrDivisor", "0"),
+            Err(ParameterError::InvalidValue("0".to_string()))
+        );
+        assert_eq!(
+            parameters.set("NullMoveMinDepth", "-1"),
+            Err(ParameterError::InvalidValue("-1".to_string()))
+        );
+        assert_eq!(
+            parameters.set("Hash", "16"),
+            Err(ParameterError::UnknownParameter("Hash".to_string()))
+        );
+        assert_eq!(parameters, unchanged);
+    }
+
+    #[test]
+    fn test_late_move_reductions() {
+        let table = SearchParameters::default().late_move_reductions();
+        // the first move is never reduced, nor anything at depth 1
+        assert_eq!(table[10][0], 0);
+        assert_eq!(table[1][40], 0);
+        assert!(table[3][3] >= 1);
+        // reductions grow with the depth and the move number
+        for depth in 1..64 {
+            for moves in 1..64 {
+                assert!(table[depth][moves] >= table[depth - 1][moves]);
+                assert!(table[depth][moves] >= table[depth][moves - 1]);
+            }
+        }
+
+        let mut parameters = SearchParameters::default();
+        parameters.set("LmrDivisor", "10000").unwrap();
+        parameters.set("LmrBase", "100").unwrap();
+        assert_eq!(parameters.late_move_reductions()[63][63], 1);
+    }
+}
@@ -0,0 +1,1845 @@
+use crate::constants::Piece;
+use crate::game::Game;
+use crate::types::Move;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+// Syzygy tables exist for up to 7 pieces including the kings
+pub const MAX_PIECES: usize = 7;
+// larger than any distance to zeroing stored in the tables
+const MAX_DTZ: i32 = 1 << 18;
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xE8, 0x23, 0x5D];
+const DTZ_MAGIC: [u8; 4] = [0xD7, 0x66, 0x0C, 0xA5];
+
+// flags of the compressed tables, all but the last are only used by DTZ
+const FLAG_STM: u8 = 1;
+const FLAG_MAPPED: u8 = 2;
+const FLAG_WIN_PLIES: u8 = 4;
+const FLAG_LOSS_PLIES: u8 = 8;
+const FLAG_WIDE: u8 = 16;
+const FLAG_SINGLE_VALUE: u8 = 128;
+
+// The result of a position for the side to move. Cursed wins and blessed
+// losses are drawn by the 50-move rule.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Wdl {
+    Loss = -2,
+    BlessedLoss = -1,
+    Draw = 0,
+    CursedWin = 1,
+    Win = 2,
+}
+
+impl Wdl {
+    fn from_value(value: i32) -> Wdl {
+        match value {
+            ..=-2 => Wdl::Loss,
+            -1 => Wdl::BlessedLoss,
+            0 => Wdl::Draw,
+            1 => Wdl::CursedWin,
+            _ => Wdl::Win,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TableKind {
+    Wdl,
+    Dtz,
+}
+
+// DTZ tables only store one side to move, the other is found by a search
+enum Lookup {
+    Value(i32),
+    OtherSide,
+}
+
+// Endgame tablebases in the Syzygy format. The files are found when the
+// tablebases are opened, but only read on first use, and then only the
+// parts needed for a probe.
+pub struct Tablebases {
+    tables: Vec<Table>,
+    // both material keys of each table point to it
+    by_key: HashMap<u64, usize>,
+    max_pieces: usize,
+}
+
+impl Tablebases {
+    // Looks for tables in the directories of `paths`, which are separated
+    // like in the PATH environment variable.
+    pub fn open(paths: &str) -> Tablebases {
+        let mut tablebases = Tablebases {
+            tables: Vec::new(),
+            by_key: HashMap::new(),
+            max_pieces: 0,
+        };
+        let directories: Vec<PathBuf> = env::split_paths(paths).collect();
+        for directory in &directories {
+            let Ok(entries) = fs::read_dir(directory) else {
+                continue;
+            };
+            let mut names: Vec<String> = entries
+                .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                .filter_map(|name| Some(name.strip_suffix(".rtbw")?.to_string()))
+                .collect();
+            names.sort();
+            for name in names {
+                let dtz_path = directories
+                    .iter()
+                    .map(|directory| directory.join(format!("{}.rtbz", name)))
+                    .find(|path| path.is_file());
+                let wdl_path = directory.join(format!("{}.rtbw", name));
+                if let Some(table) = Table::new(&name, wdl_path, dtz_path) {
+                    tablebases.add(table);
+                }
+            }
+        }
+        tablebases
+    }
+
+    // a table found in an earlier directory takes precedence
+    fn add(&mut self, table: Table) {
+        if self.by_key.contains_key(&table.key) {
+            return;
+        }
+        self.max_pieces = self.max_pieces.max(table.piece_count);
+        self.by_key.insert(table.key, self.tables.len());
+        self.by_key.insert(table.key2, self.tables.len());
+        self.tables.push(table);
+    }
+
+    // number of tables found
+    pub fn len(&self) -> usize {
+        self.tables.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+
+    // the number of pieces of the largest tables
+    pub fn max_pieces(&self) -> usize {
+        self.max_pieces
+    }
+
+    // Positions with castling rights are not in the tables.
+    fn can_probe(&self, game: &Game) -> bool {
+        game.board.piece_count() as usize <= self.max_pieces
+            && game.history.current_state().castling_rights == 0
+    }
+
+    // The result of the position for the side to move, None if it is not in
+    // the tables.
+    pub fn probe_wdl(&self, game: &mut Game) -> Option<Wdl> {
+        if !self.can_probe(game) {
+            return None;
+        }
+        let (wdl, _) = self.search(game, false)?;
+        Some(Wdl::from_value(wdl))
+    }
+
+    // The distance to zeroing the 50-move counter in plies with the sign of
+    // the result: positive if the side to move wins, 0 for a draw. Values
+    // beyond 100 are cursed wins or blessed losses. The distance can be one
+    // ply too long, a win is certain if it plus the 50-move counter is at
+    // most 99.
+    pub fn probe_dtz(&self, game: &mut Game) -> Option<i32> {
+        if !self.can_probe(game) {
+            return None;
+        }
+        let (wdl, zeroing_best) = self.search(game, true)?;
+        // draws are not stored
+        if wdl == 0 {
+            return Some(0);
+        }
+        // the table does not store a meaningful value when a capture or pawn
+        // move is best
+        if zeroing_best {
+            return Some(dtz_before_zeroing(wdl));
+        }
+        match self.probe_table(game, TableKind::Dtz, wdl)? {
+            Lookup::Value(dtz) => {
+                let cursed = if wdl.abs() == 1 { 100 } else { 0 };
+                Some((dtz + cursed) * wdl.signum())
+            }
+            // the best distance over the moves of the side to move
+            Lookup::OtherSide => {
+                let mut best = None;
+                for mv in game.generate_legal_moves() {
+                    let zeroing = is_zeroing(game, mv);
+                    game.make_move(mv);
+                    // for zeroing moves the distance before them is wanted
+                    let dtz = if zeroing {
+                        self.search(game, false)
+                            .map(|(wdl, _)| -dtz_before_zeroing(wdl))
+                    } else {
+                        self.probe_dtz(game).map(|dtz| -dtz)
+                    };
+                    let mates = dtz == Some(1) && is_checkmate(game);
+                    game.undo_move();
+                    let mut dtz = dtz?;
+                    if !zeroing {
+                        dtz += dtz.signum();
+                    }
+                    if mates {
+                        best = Some(1);
+                    } else if dtz.signum() == wdl.signum() && best.is_none_or(|best| dtz < best) {
+                        best = Some(dtz);
+                    }
+                }
+                // without legal moves the side to move is mated
+                Some(best.unwrap_or(-1))
+            }
+        }
+    }
+
+    // Ranks each of the root moves by its result, None if not all of them
+    // are in the tables. A win is only certain within the 50-move rule, so
+    // wins are ranked by their distance to zeroing to make progress, losses
+    // the same unless a 50-move draw is in reach.
+    pub(crate) fn rank_root_moves(&self, game: &mut Game, moves: &[u16]) -> Option<Vec<i32>> {
+        if !self.can_probe(game) {
+            return None;
+        }
+        let counter = game.history.current_state().reversible_move_counter as i32;
+        let mut ranks = Vec::with_capacity(moves.len());
+        for &mv in moves {
+            game.make_move(mv);
+            let dtz = if game.history.current_state().reversible_move_counter == 0 {
+                self.probe_wdl(game)
+                    .map(|wdl| dtz_before_zeroing(-(wdl as i32)))
+            } else if game.is_threefold_repetition() || game.is_fifty_move_rule() {
+                Some(0)
+            } else {
+                self.probe_dtz(game).map(|dtz| -dtz - dtz.signum())
+            };
+            let mates = dtz == Some(2) && is_checkmate(game);
+            game.undo_move();
+            let dtz = if mates { 1 } else { dtz? };
+            ranks.push(match dtz {
+                1.. if dtz + counter <= 99 => MAX_DTZ - dtz,
+                1.. => MAX_DTZ / 2 - (dtz + counter),
+                ..0 if -dtz * 2 + counter < 100 => -MAX_DTZ,
+                ..0 => -MAX_DTZ + (-dtz + counter),
+                0 => 0,
+            });
+        }
+        Some(ranks)
+    }
+
+    // Ranks the root moves with the WDL tables only, for when the DTZ tables
+    // are missing.
+    pub(crate) fn rank_root_moves_wdl(&self, game: &mut Game, moves: &[u16]) -> Option<Vec<i32>> {
+        if !self.can_probe(game) {
+            return None;
+        }
+        let mut ranks = Vec::with_capacity(moves.len());
+        for &mv in moves {
+            game.make_move(mv);
+            let wdl = self.probe_wdl(game);
+            game.undo_move();
+            ranks.push(match wdl? {
+                Wdl::Loss => MAX_DTZ,
+                Wdl::BlessedLoss => MAX_DTZ - 101,
+                Wdl::Draw => 0,
+                Wdl::CursedWin => -MAX_DTZ + 101,
+                Wdl::Win => -MAX_DTZ,
+            });
+        }
+        Some(ranks)
+    }
+
+    // The tables may store any result for a position in which a capture (or
+    // with `zeroing_moves` a pawn move) is best, so these are searched
+    // first. Returns the result and whether such a move is best.
+    fn search(&self, game: &mut Game, zeroing_moves: bool) -> Option<(i32, bool)> {
+        let moves = game.generate_legal_moves();
+        let mut best = -2;
+        let mut searched = 0;
+        for &mv in &moves {
+            let searched_move = if zeroing_moves {
+                is_zeroing(game, mv)
+            } else {
+                Move::from_raw(mv).is_capture()
+            };
+            if !searched_move {
+                continue;
+            }
+            searched += 1;
+            game.make_move(mv);
+            let result = self.search(game, false);
+            game.undo_move();
+            let value = -result?.0;
+            if value > best {
+                best = value;
+                if value == 2 {
+                    return Some((value, true));
+                }
+            }
+        }
+
+        // the tables do not know en passant rights, but if all moves were
+        // searched they are not needed
+        let all_searched = searched > 0 && searched == moves.len();
+        let value = if all_searched {
+            best
+        } else {
+            let Lookup::Value(value) = self.probe_table(game, TableKind::Wdl, 0)? else {
+                return None;
+            };
+            value
+        };
+        if best >= value {
+            Some((best, best > 0 || all_searched))
+        } else {
+            Some((value, false))
+        }
+    }
+
+    // Looks the position up in its table. `wdl` is the result of the
+    // position, which DTZ values depend on.
+    fn probe_table(&self, game: &Game, kind: TableKind, wdl: i32) -> Option<Lookup> {
+        let pieces: Vec<(usize, u8)> = (0..64)
+            .map(|square| (square, piece_code(game.board.piece_list[square])))
+            .filter(|&(_, code)| code != 0)
+            .collect();
+        if pieces.len() == 2 {
+            return Some(Lookup::Value(0));
+        }
+        let key = material_key(pieces.iter().map(|&(_, code)| code));
+        let table = &self.tables[*self.by_key.get(&key)?];
+        let file = table.file(kind)?;
+        let white_to_move = game.history.current_state().white_to_move;
+        let Some((side, tb_file, index)) =
+            table.index(&file.pairs, kind, &pieces, white_to_move, key)
+        else {
+            return Some(Lookup::OtherSide);
+        };
+        let value = file.decompress(&file.pairs[side][tb_file], index).ok()?;
+        let value = match kind {
+            TableKind::Wdl => value as i32 - 2,
+            TableKind::Dtz => file.map_dtz(tb_file, value, wdl)?,
+        };
+        Some(Lookup::Value(value))
+    }
+}
+
+// Captures and pawn moves reset the 50-move counter.
+fn is_zeroing(game: &Game, mv: u16) -> bool {
+    Move::from_raw(mv).is_capture()
+        || matches!(
+            game.board.piece_list[(mv & 0x3F) as usize],
+            Piece::WhitePawn | Piece::BlackPawn
+        )
+}
+
+fn is_checkmate(game: &Game) -> bool {
+    game.in_check() && game.generate_legal_moves().is_empty()
+}
+
+// The distance to zeroing of the move before a capture or pawn move that
+// leads to a position with this result.
+fn dtz_before_zeroing(wdl: i32) -> i32 {
+    match wdl {
+        2 => 1,
+        1 => 101,
+        -1 => -101,
+        -2 => -1,
+        _ => 0,
+    }
+}
+
+// The piece numbering of the table files, black pieces have bit 3 set.
+fn piece_code(piece: Piece) -> u8 {
+    match piece {
+        Piece::Empty => 0,
+        Piece::WhitePawn => 1,
+        Piece::WhiteKnight => 2,
+        Piece::WhiteBishop => 3,
+        Piece::WhiteRook => 4,
+        Piece::WhiteQueen => 5,
+        Piece::WhiteKing => 6,
+        Piece::BlackPawn => 9,
+        Piece::BlackKnight => 10,
+        Piece::BlackBishop => 11,
+        Piece::BlackRook => 12,
+        Piece::BlackQueen => 13,
+        Piece::BlackKing => 14,
+    }
+}
+
+// Identifies the material of a position: a 4 bit count per piece.
+fn material_key(codes: impl IntoIterator<Item = u8>) -> u64 {
+    codes
+        .into_iter()
+        .map(|code| 1 << (4 * ((code & 7) as u64 - 1 + 6 * (code >> 3) as u64)))
+        .sum()
+}
+
+fn off_diagonal(square: usize) -> i32 {
+    (square >> 3) as i32 - (square & 7) as i32
+}
+
+fn is_adjacent_or_equal(a: usize, b: usize) -> bool {
+    (a >> 3).abs_diff(b >> 3) <= 1 && (a & 7).abs_diff(b & 7) <= 1
+}
+
+// One table file, like KRvK for king and rook against king. The first side
+// in the name is the stronger one, which is white in the tables.
+struct Table {
+    key: u64,
+    // the material key with the colors swapped
+    key2: u64,
+    piece_count: usize,
+    has_pawns: bool,
+    // some side has a piece other than the king that it has only once
+    has_unique_pieces: bool,
+    // pawns of the leading color first, the one with fewer pawns
+    pawn_count: [usize; 2],
+    wdl_path: PathBuf,
+    dtz_path: Option<PathBuf>,
+    // read on first use, None if the file is missing or corrupt
+    wdl: OnceLock<Option<TableFile>>,
+    dtz: OnceLock<Option<TableFile>>,
+}
+
+impl Table {
+    fn new(name: &str, wdl_path: PathBuf, dtz_path: Option<PathBuf>) -> Option<Table> {
+        let (white, black) = name.split_once('v')?;
+        let side = |pieces: &str| -> Option<Vec<u8>> {
+            let codes = pieces
+                .chars()
+                .map(|c| match c {
+                    'P' => Some(1),
+                    'N' => Some(2),
+                    'B' => Some(3),
+                    'R' => Some(4),
+                    'Q' => Some(5),
+                    'K' => Some(6),
+                    _ => None,
+                })
+                .collect::<Option<Vec<u8>>>()?;
+            (codes.iter().filter(|&&code| code == 6).count() == 1).then_some(codes)
+        };
+        let (white, black) = (side(white)?, side(black)?);
+        let piece_count = white.len() + black.len();
+        if piece_count > MAX_PIECES {
+            return None;
+        }
+        let count = |codes: &[u8], code| codes.iter().filter(|&&c| c == code).count();
+        let has_unique_pieces =
+            (1..6).any(|code| count(&white, code) == 1 || count(&black, code) == 1);
+        let (white_pawns, black_pawns) = (count(&white, 1), count(&black, 1));
+        let white_leads = black_pawns == 0 || (white_pawns > 0 && black_pawns >= white_pawns);
+        Some(Table {
+            key: material_key(
+                white
+                    .iter()
+                    .copied()
+                    .chain(black.iter().map(|code| code | 8)),
+            ),
+            key2: material_key(
+                white
+                    .iter()
+                    .map(|code| code | 8)
+                    .chain(black.iter().copied()),
+            ),
+            piece_count,
+            has_pawns: white_pawns + black_pawns > 0,
+            has_unique_pieces,
+            pawn_count: if white_leads {
+                [white_pawns, black_pawns]
+            } else {
+                [black_pawns, white_pawns]
+            },
+            wdl_path,
+            dtz_path,
+            wdl: OnceLock::new(),
+            dtz: OnceLock::new(),
+        })
+    }
+
+    fn file(&self, kind: TableKind) -> Option<&TableFile> {
+        let (file, path) = match kind {
+            TableKind::Wdl => (&self.wdl, Some(&self.wdl_path)),
+            TableKind::Dtz => (&self.dtz, self.dtz_path.as_ref()),
+        };
+        file.get_or_init(|| TableFile::open(path?, self, kind).ok())
+            .as_ref()
+    }
+
+    // DTZ tables store one side to move, unless both sides have the same
+    // pieces and it does not matter
+    fn stores_side(&self, pairs: &[[PairsData; 4]; 2], side: usize, file: usize) -> bool {
+        (pairs[0][file].flags & FLAG_STM) as usize == side
+            || (self.key == self.key2 && !self.has_pawns)
+    }
+
+    // Computes the index of a position with the `pieces` on the given
+    // squares, in ascending order of squares. Returns the side and file of
+    // the subtable with the index, or None if a DTZ table does not store
+    // this side to move.
+    //
+    // The position is first mirrored so that the stronger side is white,
+    // then so that the leading piece is in the a1-d1-d4 triangle, or for
+    // tables with pawns the leading pawn on the a to d files. Groups of
+    // pieces are then numbered together, k pieces of one kind on squares
+    // s1 < ... < sk as the sum of binomial(i, si).
+    fn index(
+        &self,
+        pairs: &[[PairsData; 4]; 2],
+        kind: TableKind,
+        pieces: &[(usize, u8)],
+        white_to_move: bool,
+        material_key: u64,
+    ) -> Option<(usize, usize, u64)> {
+        let encoding = Encoding::get();
+        // a table with the same pieces on both sides only stores white to move
+        let symmetric_black_to_move = self.key == self.key2 && !white_to_move;
+        let flip = symmetric_black_to_move || material_key != self.key;
+        let (flip_color, flip_squares) = if flip { (8, 56) } else { (0, 0) };
+        let side = (flip == white_to_move) as usize;
+
+        let mut squares = [0usize; MAX_PIECES];
+        let mut codes = [0u8; MAX_PIECES];
+        let mut size = 0;
+        let mut file = 0;
+        let lead = pairs[0][0].pieces[0] ^ flip_color;
+        if self.has_pawns {
+            for &(square, _) in pieces.iter().filter(|&&(_, code)| code == lead) {
+                squares[size] = square ^ flip_squares;
+                size += 1;
+            }
+            // the pawn closest to the edge and then to the first rank
+            let leading = (0..size)
+                .max_by_key(|&i| encoding.map_pawns[squares[i]])
+                .unwrap();
+            squares.swap(0, leading);
+            file = (squares[0] & 7).min(7 - (squares[0] & 7));
+        }
+        let lead_pawns = size;
+        if kind == TableKind::Dtz && !self.stores_side(pairs, side, file) {
+            return None;
+        }
+        for &(square, code) in pieces {
+            if !(self.has_pawns && code == lead) {
+                squares[size] = square ^ flip_squares;
+                codes[size] = code ^ flip_color;
+                size += 1;
+            }
+        }
+        let side = if kind == TableKind::Wdl { side } else { 0 };
+        let d = &pairs[side][file];
+
+        // order the pieces like in the table
+        for i in lead_pawns..size.saturating_sub(1) {
+            if let Some(j) = (i + 1..size).find(|&j| codes[j] == d.pieces[i]) {
+                codes.swap(i, j);
+                squares.swap(i, j);
+            }
+        }
+        if squares[0] & 7 > 3 {
+            for square in &mut squares[..size] {
+                *square ^= 7;
+            }
+        }
+
+        let mut index;
+        if self.has_pawns {
+            index = encoding.lead_pawn_index[lead_pawns][squares[0]];
+            squares[1..lead_pawns].sort_by_key(|&square| encoding.map_pawns[square]);
+            for (i, &square) in squares.iter().enumerate().take(lead_pawns).skip(1) {
+                index += encoding.binomial[i][encoding.map_pawns[square]];
+            }
+        } else {
+            if squares[0] >> 3 > 3 {
+                for square in &mut squares[..size] {
+                    *square ^= 56;
+                }
+            }
+            // the first piece of the leading group off the a1-h8 diagonal
+            // goes below it
+            if let Some(i) = (0..d.group_len[0]).find(|&i| off_diagonal(squares[i]) != 0)
+                && off_diagonal(squares[i]) > 0
+            {
+                for square in &mut squares[i..size] {
+                    *square = ((*square >> 3) | (*square << 3)) & 63;
+                }
+            }
+            if self.has_unique_pieces {
+                // three unique pieces numbered together
+                let [s0, s1, s2] = [squares[0], squares[1], squares[2]];
+                let adjust1 = (s1 > s0) as u64;
+                let adjust2 = (s2 > s0) as u64 + (s2 > s1) as u64;
+                let (s0, s1, s2) = (s0 as u64, s1 as u64, s2 as u64);
+                index = if off_diagonal(squares[0]) != 0 {
+                    (encoding.map_a1d1d4[squares[0]] as u64 * 63 + s1 - adjust1) * 62 + s2 - adjust2
+                } else if off_diagonal(squares[1]) != 0 {
+                    (6 * 63 + (s0 >> 3) * 28 + encoding.map_b1h1h7[squares[1]]) * 62 + s2 - adjust2
+                } else if off_diagonal(squares[2]) != 0 {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + (s0 >> 3) * 7 * 28
+                        + ((s1 >> 3) - adjust1) * 28
+                        + encoding.map_b1h1h7[squares[2]]
+                } else {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + 4 * 7 * 28
+                        + (s0 >> 3) * 7 * 6
+                        + ((s1 >> 3) - adjust1) * 6
+                        + ((s2 >> 3) - adjust2)
+                };
+            } else {
+                index = encoding.map_kk[encoding.map_a1d1d4[squares[0]]][squares[1]];
+            }
+        }
+
+        // the remaining groups, squares taken by earlier groups are skipped
+        index *= d.group_index[0];
+        let mut start = d.group_len[0];
+        let mut remaining_pawns = self.has_pawns && self.pawn_count[1] > 0;
+        let mut next = 1;
+        while d.group_len[next] != 0 {
+            let len = d.group_len[next];
+            squares[start..start + len].sort_unstable();
+            let mut n = 0;
+            for i in 0..len {
+                let square = squares[start + i];
+                let taken = squares[..start].iter().filter(|&&s| square > s).count();
+                let first = if remaining_pawns { 8 } else { 0 };
+                n += encoding.binomial[i + 1][square - taken - first];
+            }
+            remaining_pawns = false;
+            index += n * d.group_index[next];
+            start += len;
+            next += 1;
+        }
+        Some((side, file, index))
+    }
+}
+
+// Indexing information of one subtable. The values are Huffman coded
+// symbols, each of which stands for a value or a pair of other symbols.
+#[derive(Default)]
+struct PairsData {
+    flags: u8,
+    // also the value of single value tables
+    min_sym_len: u8,
+    block_size: u64,
+    // every `span` values there is an entry in the sparse index
+    span: u64,
+    num_blocks: u64,
+    // the number of block lengths, padded beyond the blocks
+    block_length_size: u64,
+    // the lowest symbol of each code length from `min_sym_len` on
+    lowest_sym: Vec<u16>,
+    // the lowest code of each length, left aligned
+    base64: Vec<u64>,
+    // the left and right symbol a symbol stands for
+    btree: Vec<(u16, u16)>,
+    // the number of values of a symbol minus one
+    sym_len: Vec<u8>,
+    // file offsets of the sparse index, block lengths and compressed data
+    sparse_index: u64,
+    block_lengths: u64,
+    data: u64,
+    // the pieces in their order in the index and how they are grouped
+    pieces: [u8; MAX_PIECES],
+    group_len: [usize; MAX_PIECES + 1],
+    group_index: [u64; MAX_PIECES + 1],
+    // offsets of the DTZ value maps for win, loss, cursed win and blessed loss
+    map_index: [usize; 4],
+}
+
+struct TableFile {
+    file: fs::File,
+    // by side to move and file of the leading pawn, tables without pawns
+    // only use the first file and DTZ tables only the first side
+    pairs: [[PairsData; 4]; 2],
+    // maps DTZ tables' values, which are sorted by frequency, to distances
+    map: Vec<u8>,
+}
+
+impl TableFile {
+    // Reads the header, the compressed values are read as needed.
+    fn open(path: &Path, table: &Table, kind: TableKind) -> io::Result<TableFile> {
+        let mut reader = Reader {
+            inner: BufReader::new(fs::File::open(path)?),
+            offset: 0,
+        };
+        let magic = match kind {
+            TableKind::Wdl => WDL_MAGIC,
+            TableKind::Dtz => DTZ_MAGIC,
+        };
+        let flags = reader.u8s(5)?;
+        let split = flags[4] & 1 != 0;
+        let has_pawns = flags[4] & 2 != 0;
+        if flags[..4] != magic || split != (table.key != table.key2) || has_pawns != table.has_pawns
+        {
+            return Err(corrupt());
+        }
+
+        let sides = if kind == TableKind::Wdl && split {
+            2
+        } else {
+            1
+        };
+        let files = if table.has_pawns { 4 } else { 1 };
+        let both_pawns = table.has_pawns && table.pawn_count[1] > 0;
+        let mut pairs: [[PairsData; 4]; 2] = Default::default();
+        for file in 0..files {
+            let order = reader.u8s(1 + both_pawns as usize)?;
+            let second = |order: u8| if both_pawns { order } else { 0xF };
+            let orders = [
+                [order[0] & 0xF, second(order[order.len() - 1] & 0xF)],
+                [order[0] >> 4, second(order[order.len() - 1] >> 4)],
+            ];
+            let pieces = reader.u8s(table.piece_count)?;
+            for (side, side_pairs) in pairs.iter_mut().enumerate().take(sides) {
+                let d = &mut side_pairs[file];
+                for (k, piece) in pieces.iter().enumerate() {
+                    d.pieces[k] = if side == 0 { piece & 0xF } else { piece >> 4 };
+                }
+                set_groups(table, d, orders[side], file);
+            }
+        }
+        reader.align(2)?;
+
+        for file in 0..files {
+            for side in pairs.iter_mut().take(sides) {
+                read_sizes(&mut side[file], &mut reader)?;
+            }
+        }
+        let map = if kind == TableKind::Dtz {
+            read_dtz_map(&mut pairs, &mut reader, files)?
+        } else {
+            Vec::new()
+        };
+        for file in 0..files {
+            for side in pairs.iter_mut().take(sides) {
+                side[file].sparse_index = reader.offset;
+                reader.skip(side[file].sparse_index_size() * 6)?;
+            }
+        }
+        for file in 0..files {
+            for side in pairs.iter_mut().take(sides) {
+                side[file].block_lengths = reader.offset;
+                reader.skip(side[file].block_length_size * 2)?;
+            }
+        }
+        let length = reader.inner.get_ref().metadata()?.len();
+        for file in 0..files {
+            for side in pairs.iter_mut().take(sides) {
+                let d = &mut side[file];
+                reader.align(64)?;
+                d.data = reader.offset;
+                reader.skip(d.num_blocks * d.block_size)?;
+                if d.num_blocks > 0 && reader.offset > length {
+                    return Err(corrupt());
+                }
+            }
+        }
+
+        Ok(TableFile {
+            file: reader.inner.into_inner(),
+            pairs,
+            map,
+        })
+    }
+
+    // Finds the value at `index`. The sparse index gives a block close to
+    // the one that holds it, from there the lengths of the blocks are
+    // followed. Then the symbols of the block are decoded until the one
+    // that covers the index, which is expanded into its pairs.
+    fn decompress(&self, d: &PairsData, index: u64) -> io::Result<u16> {
+        if d.flags & FLAG_SINGLE_VALUE != 0 {
+            return Ok(d.min_sym_len as u16);
+        }
+        let mut entry = [0; 6];
+        read_at(&self.file, d.sparse_index + index / d.span * 6, &mut entry)?;
+        let mut block = u32::from_le_bytes(entry[..4].try_into().unwrap()) as u64;
+        let mut offset = u16::from_le_bytes(entry[4..].try_into().unwrap()) as i64;
+        // the entry is for the value in the middle of its span
+        offset += (index % d.span) as i64 - (d.span / 2) as i64;
+        while offset < 0 {
+            block = block.checked_sub(1).ok_or_else(corrupt)?;
+            offset += self.block_length(d, block)? + 1;
+        }
+        loop {
+            let length = self.block_length(d, block)?;
+            if offset <= length {
+                break;
+            }
+            offset -= length + 1;
+            block += 1;
+        }
+
+        let mut data = vec![0; d.block_size as usize];
+        read_at(&self.file, d.data + block * d.block_size, &mut data)?;
+        let word = |i: usize| {
+            data.get(4 * i..4 * i + 4).map_or(0, |word| {
+                u32::from_be_bytes(word.try_into().unwrap()) as u64
+            })
+        };
+        let mut buffer = (word(0) << 32) | word(1);
+        let mut next_word = 2;
+        let mut buffer_size = 64;
+        let min_sym_len = d.min_sym_len as usize;
+        let mut symbol;
+        loop {
+            // longer codes have lower values
+            let mut len = 0;
+            while buffer < d.base64[len] {
+                len += 1;
+            }
+            let code = (buffer - d.base64[len]) >> (64 - len - min_sym_len);
+            symbol = (code as u16).wrapping_add(d.lowest_sym[len]) as usize;
+            let values = *d.sym_len.get(symbol).ok_or_else(corrupt)? as i64 + 1;
+            if offset < values {
+                break;
+            }
+            offset -= values;
+            buffer <<= len + min_sym_len;
+            buffer_size -= len + min_sym_len;
+            if buffer_size <= 32 {
+                buffer_size += 32;
+                buffer |= word(next_word) << (64 - buffer_size);
+                next_word += 1;
+            }
+        }
+        while d.sym_len[symbol] != 0 {
+            let (left, right) = d.btree[symbol];
+            let values = d.sym_len[left as usize] as i64 + 1;
+            if offset < values {
+                symbol = left as usize;
+            } else {
+                offset -= values;
+                symbol = right as usize;
+            }
+        }
+        Ok(d.btree[symbol].0)
+    }
+
+    fn block_length(&self, d: &PairsData, block: u64) -> io::Result<i64> {
+        if block >= d.block_length_size {
+            return Err(corrupt());
+        }
+        let mut length = [0; 2];
+        read_at(&self.file, d.block_lengths + block * 2, &mut length)?;
+        Ok(u16::from_le_bytes(length) as i64)
+    }
+
+    // Turns a DTZ value into plies, the tables may store moves.
+    fn map_dtz(&self, file: usize, value: u16, wdl: i32) -> Option<i32> {
+        let d = &self.pairs[0][file];
+        let mut value = value as usize;
+        if d.flags & FLAG_MAPPED != 0 {
+            let map = d.map_index[match wdl {
+                2 => 0,
+                -2 => 1,
+                1 => 2,
+                _ => 3,
+            }];
+            value = if d.flags & FLAG_WIDE != 0 {
+                let bytes = self.map.get(map + 2 * value..map + 2 * value + 2)?;
+                u16::from_le_bytes(bytes.try_into().unwrap()) as usize
+            } else {
+                *self.map.get(map + value)? as usize
+            };
+        }
+        let in_plies = match wdl {
+            2 => d.flags & FLAG_WIN_PLIES != 0,
+            -2 => d.flags & FLAG_LOSS_PLIES != 0,
+            _ => false,
+        };
+        let value = value as i32;
+        Some(if in_plies { value } else { value * 2 } + 1)
+    }
+}
+
+impl PairsData {
+    fn sparse_index_size(&self) -> u64 {
+        if self.flags & FLAG_SINGLE_VALUE != 0 {
+            return 0;
+        }
+        let size = self.group_index[self.group_len.iter().position(|&len| len == 0).unwrap()];
+        size.div_ceil(self.span)
+    }
+}
+
+// Splits the pieces into the groups that are numbered together: pieces of
+// the same kind, except for the leading group, which has the first three
+// pieces if there are unique pieces and else the kings. Pawn tables start
+// with the leading pawns. `order` gives the order of the leading group and
+// the other color's pawns among the groups.
+fn set_groups(table: &Table, d: &mut PairsData, order: [u8; 2], file: usize) {
+    let encoding = Encoding::get();
+    let mut first_len: i32 = if table.has_pawns {
+        0
+    } else if table.has_unique_pieces {
+        3
+    } else {
+        2
+    };
+    let mut n = 0;
+    d.group_len[0] = 1;
+    for i in 1..table.piece_count {
+        first_len -= 1;
+        if first_len > 0 || d.pieces[i] == d.pieces[i - 1] {
+            d.group_len[n] += 1;
+        } else {
+            n += 1;
+            d.group_len[n] = 1;
+        }
+    }
+    n += 1;
+    d.group_len[n] = 0;
+
+    let both_pawns = table.has_pawns && table.pawn_count[1] > 0;
+    let mut next = if both_pawns { 2 } else { 1 };
+    let mut free_squares = 64 - d.group_len[0] - if both_pawns { d.group_len[1] } else { 0 };
+    let mut index = 1;
+    let mut k = 0;
+    while next < n || k == order[0] as usize || k == order[1] as usize {
+        if k == order[0] as usize {
+            d.group_index[0] = index;
+            index *= if table.has_pawns {
+                encoding.lead_pawns_size[d.group_len[0]][file]
+            } else if table.has_unique_pieces {
+                31332
+            } else {
+                462
+            };
+        } else if k == order[1] as usize {
+            d.group_index[1] = index;
+            index *= encoding.binomial[d.group_len[1]][48 - d.group_len[0]];
+        } else {
+            d.group_index[next] = index;
+            index *= encoding.binomial[d.group_len[next]][free_squares];
+            free_squares -= d.group_len[next];
+            next += 1;
+        }
+        k += 1;
+    }
+    d.group_index[n] = index;
+}
+
+fn read_sizes(d: &mut PairsData, reader: &mut Reader) -> io::Result<()> {
+    d.flags = reader.u8s(1)?[0];
+    if d.flags & FLAG_SINGLE_VALUE != 0 {
+        d.min_sym_len = reader.u8s(1)?[0];
+        return Ok(());
+    }
+    let sizes = reader.u8s(9)?;
+    d.block_size = 1u64.checked_shl(sizes[0] as u32).ok_or_else(corrupt)?;
+    d.span = 1u64.checked_shl(sizes[1] as u32).ok_or_else(corrupt)?;
+    let padding = sizes[2] as u64;
+    d.num_blocks = u32::from_le_bytes(sizes[3..7].try_into().unwrap()) as u64;
+    d.block_length_size = d.num_blocks + padding;
+    let (max_sym_len, min_sym_len) = (sizes[7], sizes[8]);
+    if max_sym_len < min_sym_len || max_sym_len > 32 || min_sym_len == 0 {
+        return Err(corrupt());
+    }
+    d.min_sym_len = min_sym_len;
+    let lengths = (max_sym_len - min_sym_len + 1) as usize;
+    d.lowest_sym = reader
+        .u8s(2 * lengths)?
+        .chunks_exact(2)
+        .map(|sym| u16::from_le_bytes([sym[0], sym[1]]))
+        .collect();
+
+    // canonical Huffman codes: the codes of one length are consecutive and
+    // longer codes are lower
+    d.base64 = vec![0; lengths];
+    for i in (0..lengths - 1).rev() {
+        d.base64[i] = d.base64[i + 1]
+            .wrapping_add(d.lowest_sym[i] as u64)
+            .wrapping_sub(d.lowest_sym[i + 1] as u64)
+            / 2;
+    }
+    for (i, base) in d.base64.iter_mut().enumerate() {
+        *base <<= 64 - i - min_sym_len as usize;
+    }
+
+    let symbols = u16::from_le_bytes(reader.u8s(2)?.try_into().unwrap()) as usize;
+    d.btree = reader
+        .u8s(3 * symbols)?
+        .chunks_exact(3)
+        .map(|lr| {
+            let left = ((lr[1] as u16 & 0xF) << 8) | lr[0] as u16;
+            let right = ((lr[2] as u16) << 4) | (lr[1] as u16 >> 4);
+            (left, right)
+        })
+        .collect();
+    if symbols % 2 == 1 {
+        reader.skip(1)?;
+    }
+    let leaf = |right| right == 0xFFF;
+    if d.btree.iter().any(|&(left, right)| {
+        !leaf(right) && (left as usize >= symbols || right as usize >= symbols)
+    }) {
+        return Err(corrupt());
+    }
+    d.sym_len = vec![0; symbols];
+    let mut visited = vec![false; symbols];
+    for symbol in 0..symbols {
+        set_sym_len(d, symbol, &mut visited);
+    }
+    Ok(())
+}
+
+// the number of values a symbol stands for, minus one
+fn set_sym_len(d: &mut PairsData, symbol: usize, visited: &mut [bool]) -> u8 {
+    if !visited[symbol] {
+        // the pairs form a tree, so marking the symbol first is safe
+        visited[symbol] = true;
+        let (left, right) = d.btree[symbol];
+        if right != 0xFFF {
+            let left = set_sym_len(d, left as usize, visited);
+            let right = set_sym_len(d, right as usize, visited);
+            d.sym_len[symbol] = left.wrapping_add(right).wrapping_add(1);
+        }
+    }
+    d.sym_len[symbol]
+}
+
+// The DTZ value maps of the subtables that use them, four per file, each
+// with its length first. Returns all of them.
+fn read_dtz_map(
+    pairs: &mut [[PairsData; 4]; 2],
+    reader: &mut Reader,
+    files: usize,
+) -> io::Result<Vec<u8>> {
+    let start = reader.offset;
+    for d in pairs[0].iter_mut().take(files) {
+        if d.flags & FLAG_MAPPED == 0 {
+            continue;
+        }
+        for map_index in &mut d.map_index {
+            if d.flags & FLAG_WIDE != 0 {
+                reader.align(2)?;
+                *map_index = (reader.offset - start) as usize + 2;
+                let len = u16::from_le_bytes(reader.u8s(2)?.try_into().unwrap());
+                reader.skip(2 * len as u64)?;
+            } else {
+                *map_index = (reader.offset - start) as usize + 1;
+                let len = reader.u8s(1)?[0];
+                reader.skip(len as u64)?;
+            }
+        }
+    }
+    reader.align(2)?;
+    let mut map = vec![0; (reader.offset - start) as usize];
+    read_at(reader.inner.get_ref(), start, &mut map)?;
+    Ok(map)
+}
+
+fn corrupt() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "corrupt table")
+}
+
+#[cfg(unix)]
+fn read_at(file: &fs::File, offset: u64, buffer: &mut [u8]) -> io::Result<()> {
+    use std::os::unix::fs::FileExt;
+    file.read_exact_at(buffer, offset)
+}
+
+#[cfg(windows)]
+fn read_at(file: &fs::File, mut offset: u64, mut buffer: &mut [u8]) -> io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buffer.is_empty() {
+        match file.seek_read(buffer, offset)? {
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            read => {
+                buffer = &mut buffer[read..];
+                offset += read as u64;
+            }
+        }
+    }
+    Ok(())
+}
+
+// reads the header of a table front to back
+struct Reader {
+    inner: BufReader<fs::File>,
+    offset: u64,
+}
+
+impl Reader {
+    fn u8s(&mut self, len: usize) -> io::Result<Vec<u8>> {
+        let mut bytes = vec![0; len];
+        self.inner.read_exact(&mut bytes)?;
+        self.offset += len as u64;
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, len: u64) -> io::Result<()> {
+        self.inner.seek_relative(len as i64)?;
+        self.offset += len;
+        Ok(())
+    }
+
+    fn align(&mut self, alignment: u64) -> io::Result<()> {
+        self.skip(self.offset.next_multiple_of(alignment) - self.offset)
+    }
+}
+
+// Tables to number the placements of pieces.
+struct Encoding {
+    // the squares below the a1-h8 diagonal
+    map_b1h1h7: [u64; 64],
+    // the a1-d1-d4 triangle, the diagonal last
+    map_a1d1d4: [usize; 64],
+    // the 462 placements of two kings with the first in the triangle and,
+    // if it is on the diagonal, the second not above it
+    map_kk: [[u64; 64]; 10],
+    // binomial[k][n] is n choose k
+    binomial: [[u64; 64]; 6],
+    // the squares a2 to h7, the higher the closer to the edge and the first
+    // rank, which is the order for the leading pawn
+    map_pawns: [usize; 64],
+    // by number of leading pawns and square of the leading pawn
+    lead_pawn_index: [[u64; 64]; 6],
+    // by number of leading pawns and file a to d
+    lead_pawns_size: [[u64; 4]; 6],
+}
+
+static ENCODING: OnceLock<Encoding> = OnceLock::new();
+
+impl Encoding {
+    fn get() -> &'static Encoding {
+        ENCODING.get_or_init(Encoding::new)
+    }
+
+    fn new() -> Encoding {
+        let mut encoding = Encoding {
+            map_b1h1h7: [0; 64],
+            map_a1d1d4: [0; 64],
+            map_kk: [[0; 64]; 10],
+            binomial: [[0; 64]; 6],
+            map_pawns: [0; 64],
+            lead_pawn_index: [[0; 64]; 6],
+            lead_pawns_size: [[0; 4]; 6],
+        };
+
+        let below_diagonal = (0..64).filter(|&square| off_diagonal(square) < 0);
+        for (code, square) in below_diagonal.enumerate() {
+            encoding.map_b1h1h7[square] = code as u64;
+        }
+
+        let triangle = [0, 1, 2, 3, 9, 10, 11, 18, 19, 27];
+        let below = triangle.iter().filter(|&&square| off_diagonal(square) < 0);
+        let diagonal = triangle.iter().filter(|&&square| off_diagonal(square) == 0);
+        for (code, &square) in below.chain(diagonal).enumerate() {
+            encoding.map_a1d1d4[square] = code;
+        }
+
+        let mut code = 0;
+        let mut both_on_diagonal = Vec::new();
+        for index in 0..10 {
+            let first = triangle
+                .into_iter()
+                .find(|&square| encoding.map_a1d1d4[square] == index)
+                .unwrap();
+            for second in 0..64 {
+                if is_adjacent_or_equal(first, second)
+                    || (off_diagonal(first) == 0 && off_diagonal(second) > 0)
+                {
+                    continue;
+                }
+                if off_diagonal(first) == 0 && off_diagonal(second) == 0 {
+                    both_on_diagonal.push((index, second));
+                } else {
+                    encoding.map_kk[index][second] = code;
+                    code += 1;
+                }
+            }
+        }
+        for (index, second) in both_on_diagonal {
+            encoding.map_kk[index][second] = code;
+            code += 1;
+        }
+
+        encoding.binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..6.min(n + 1) {
+                encoding.binomial[k][n] = if k > 0 {
+                    encoding.binomial[k - 1][n - 1]
+                } else {
+                    0
+                } + if k < n {
+                    encoding.binomial[k][n - 1]
+                } else {
+                    0
+                };
+            }
+        }
+
+        // a2, h2, a3, h3, ..., then the b and g files and so on
+        let mut available = 48;
+        for file in 0..4 {
+            for rank in 1..7 {
+                for square in [rank * 8 + file, rank * 8 + 7 - file] {
+                    available -= 1;
+                    encoding.map_pawns[square] = available;
+                }
+            }
+        }
+        for lead_pawns in 1..6 {
+            for file in 0..4 {
+                let mut index = 0;
+                for rank in 1..7 {
+                    let square = rank * 8 + file;
+                    encoding.lead_pawn_index[lead_pawns][square] = index;
+                    index += encoding.binomial[lead_pawns - 1][encoding.map_pawns[square]];
+                }
+                encoding.lead_pawns_size[lead_pawns][file] = index;
+            }
+        }
+        encoding
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::{Search, SearchLimits, TB_WIN_SCORE};
+    use std::process;
+
+    // white king, white rook and black king, in table order
+    const KRK: [u8; 3] = [6, 4, 14];
+    const BLOCK_SIZE: usize = 64;
+    const SPAN: usize = 1024;
+
+    static KRK_WDL: OnceLock<Vec<u8>> = OnceLock::new();
+    static KRK_DTZ: OnceLock<Vec<u8>> = OnceLock::new();
+    static KRK_DISTANCES: OnceLock<[Vec<Option<u8>>; 2]> = OnceLock::new();
+
+    fn krk_table() -> Table {
+        Table::new("KRvK", PathBuf::new(), None).unwrap()
+    }
+
+    fn krk_pairs(table: &Table) -> [[PairsData; 4]; 2] {
+        let mut pairs: [[PairsData; 4]; 2] = Default::default();
+        for side in &mut pairs {
+            side[0].pieces[..3].copy_from_slice(&KRK);
+            set_groups(table, &mut side[0], [0, 0xF], 0);
+        }
+        pairs
+    }
+
+    fn rook_attacks(rook: usize, blocker: usize, square: usize) -> bool {
+        if square == rook || (square >> 3 != rook >> 3 && square & 7 != rook & 7) {
+            return false;
+        }
+        let step: i32 = match (square >> 3 == rook >> 3, square > rook) {
+            (true, true) => 1,
+            (true, false) => -1,
+            (false, true) => 8,
+            (false, false) => -8,
+        };
+        let mut between = rook as i32 + step;
+        while between != square as i32 {
+            if between == blocker as i32 {
+                return false;
+            }
+            between += step;
+        }
+        true
+    }
+
+    // KRvK by simple rules: white to move wins, black to move only escapes
+    // by taking the rook or by stalemate
+    fn krk_value(white_king: usize, rook: usize, black_king: usize, white_to_move: bool) -> u8 {
+        if white_to_move {
+            return 4;
+        }
+        let adjacent = |a: usize, b: usize| a != b && is_adjacent_or_equal(a, b);
+        if adjacent(black_king, rook) && !is_adjacent_or_equal(rook, white_king) {
+            return 2;
+        }
+        let can_move = (0..64).any(|square| {
+            adjacent(black_king, square)
+                && !is_adjacent_or_equal(square, white_king)
+                && !rook_attacks(rook, white_king, square)
+        });
+        if !can_move && !rook_attacks(rook, white_king, black_king) {
+            2
+        } else {
+            0
+        }
+    }
+
+    fn krk_position(white_king: usize, rook: usize, black_king: usize) -> usize {
+        (white_king * 64 + rook) * 64 + black_king
+    }
+
+    fn is_legal(white_king: usize, rook: usize, black_king: usize, white_to_move: bool) -> bool {
+        white_king != rook
+            && rook != black_king
+            && !is_adjacent_or_equal(white_king, black_king)
+            && (!white_to_move || !rook_attacks(rook, white_king, black_king))
+    }
+
+    // the squares the black king can go to, None if it can take the rook
+    fn black_king_moves(white_king: usize, rook: usize, black_king: usize) -> Option<Vec<usize>> {
+        let mut moves = Vec::new();
+        for square in (0..64).filter(|&square| {
+            square != black_king
+                && is_adjacent_or_equal(square, black_king)
+                && !is_adjacent_or_equal(square, white_king)
+        }) {
+            if square == rook {
+                return None;
+            }
+            if !rook_attacks(rook, white_king, square) {
+                moves.push(square);
+            }
+        }
+        Some(moves)
+    }
+
+    // the positions with black to move after each white move
+    fn white_moves(white_king: usize, rook: usize, black_king: usize) -> Vec<usize> {
+        let mut moves = Vec::new();
+        for square in 0..64 {
+            if square != white_king
+                && square != rook
+                && is_adjacent_or_equal(square, white_king)
+                && !is_adjacent_or_equal(square, black_king)
+            {
+                moves.push(krk_position(square, rook, black_king));
+            }
+        }
+        for (file_step, rank_step) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+            let (mut file, mut rank) = ((rook % 8) as i32, (rook / 8) as i32);
+            loop {
+                file += file_step;
+                rank += rank_step;
+                let square = (rank * 8 + file) as usize;
+                if !(0..8).contains(&file)
+                    || !(0..8).contains(&rank)
+                    || square == white_king
+                    || square == black_king
+                {
+                    break;
+                }
+                moves.push(krk_position(white_king, square, black_king));
+            }
+        }
+        moves
+    }
+
+    // The plies to mate in KRvK with white and with black to move, found by
+    // retrograde analysis. None for draws and illegal positions. Since
+    // white has no captures, the distance to mate is the distance to
+    // zeroing that the DTZ tables store.
+    fn krk_distances() -> &'static [Vec<Option<u8>>; 2] {
+        KRK_DISTANCES.get_or_init(|| {
+            let mut white = vec![None; 64 * 64 * 64];
+            let mut black = vec![None; 64 * 64 * 64];
+            let squares = |position: usize| (position / 4096, position / 64 % 64, position % 64);
+            // the positions found in the last step, starting with black mated
+            let mut found: Vec<usize> = (0..64 * 64 * 64)
+                .filter(|&position| {
+                    let (white_king, rook, black_king) = squares(position);
+                    is_legal(white_king, rook, black_king, false)
+                        && rook_attacks(rook, white_king, black_king)
+                        && black_king_moves(white_king, rook, black_king)
+                            .is_some_and(|moves| moves.is_empty())
+                })
+                .collect();
+            for &position in &found {
+                black[position] = Some(0);
+            }
+            let mut plies = 0;
+            while !found.is_empty() {
+                let mut next = Vec::new();
+                for position in found {
+                    let (white_king, rook, black_king) = squares(position);
+                    if plies % 2 == 0 {
+                        // white's moves can be taken back, so the positions
+                        // before them are the ones after them
+                        for previous in white_moves(white_king, rook, black_king) {
+                            let (white_king, rook, black_king) = squares(previous);
+                            if white[previous].is_none()
+                                && is_legal(white_king, rook, black_king, true)
+                            {
+                                white[previous] = Some(plies + 1);
+                                next.push(previous);
+                            }
+                        }
+                        continue;
+                    }
+                    // black is lost once all its moves are, the last one to
+                    // be found is the longest
+                    for from in 0..64 {
+                        let previous = krk_position(white_king, rook, from);
+                        if from == black_king
+                            || !is_adjacent_or_equal(from, black_king)
+                            || !is_legal(white_king, rook, from, false)
+                            || black[previous].is_some()
+                        {
+                            continue;
+                        }
+                        let lost = black_king_moves(white_king, rook, from).is_some_and(|moves| {
+                            moves
+                                .into_iter()
+                                .all(|to| white[krk_position(white_king, rook, to)].is_some())
+                        });
+                        if lost {
+                            black[previous] = Some(plies + 1);
+                            next.push(previous);
+                        }
+                    }
+                }
+                found = next;
+                plies += 1;
+            }
+            [white, black]
+        })
+    }
+
+    // The values of one side with a code of `bits` bits for each of the
+    // `symbols` values, padded to full blocks, in the layout of the table
+    // file.
+    fn compress(values: &[u8], bits: usize, symbols: u8) -> [Vec<u8>; 4] {
+        let values_per_block = BLOCK_SIZE * 8 / bits;
+        let num_blocks = values.len().div_ceil(values_per_block);
+        let mut sparse_index = Vec::new();
+        let mut total_blocks = num_blocks;
+        for k in 0..values.len().div_ceil(SPAN) {
+            let index = k * SPAN + SPAN / 2;
+            let block = index / values_per_block;
+            total_blocks = total_blocks.max(block + 1);
+            sparse_index.extend_from_slice(&(block as u32).to_le_bytes());
+            sparse_index.extend_from_slice(&((index % values_per_block) as u16).to_le_bytes());
+        }
+        let block_lengths = (0..total_blocks)
+            .flat_map(|_| (values_per_block as u16 - 1).to_le_bytes())
+            .collect();
+        let mut data = vec![0; num_blocks * BLOCK_SIZE];
+        for (i, &value) in values.iter().enumerate() {
+            let bit = (i / values_per_block) * BLOCK_SIZE * 8 + (i % values_per_block) * bits;
+            for j in 0..bits {
+                if value & (1 << (bits - 1 - j)) != 0 {
+                    data[(bit + j) / 8] |= 0x80 >> ((bit + j) % 8);
+                }
+            }
+        }
+
+        // all symbols have the same length, each is a value
+        let mut sizes = vec![0, 6, 10, (total_blocks - num_blocks) as u8];
+        sizes.extend_from_slice(&(num_blocks as u32).to_le_bytes());
+        sizes.extend_from_slice(&[bits as u8, bits as u8, 0, 0, symbols, 0]);
+        for value in 0..symbols {
+            sizes.extend_from_slice(&[value, 0xF0, 0xFF]);
+        }
+        if symbols % 2 == 1 {
+            sizes.push(0);
+        }
+        [sizes, sparse_index, block_lengths, data]
+    }
+
+    // a table file of KRvK, with the values of both sides for WDL and only
+    // white to move for DTZ
+    fn krk_file(magic: [u8; 4], sides: &[[Vec<u8>; 4]]) -> Vec<u8> {
+        let mut bytes = magic.to_vec();
+        bytes.extend_from_slice(&[1, 0, 0x66, 0x44, 0xEE, 0]);
+        for part in 0..3 {
+            for side in sides {
+                bytes.extend_from_slice(&side[part]);
+            }
+        }
+        for side in sides {
+            bytes.resize(bytes.len().next_multiple_of(64), 0);
+            bytes.extend_from_slice(&side[3]);
+        }
+        bytes
+    }
+
+    // the table values of all placements with the given side to move,
+    // `value` is called with the white king, rook and black king
+    fn krk_values(
+        kind: TableKind,
+        white_to_move: bool,
+        value: impl Fn(usize, usize, usize) -> u8,
+    ) -> (usize, Vec<u8>) {
+        let table = krk_table();
+        let pairs = krk_pairs(&table);
+        let mut side = 0;
+        let mut values = vec![None; 31332];
+        for white_king in 0..64 {
+            for rook in (0..64).filter(|&rook| rook != white_king) {
+                for black_king in (0..64).filter(|&king| king != white_king && king != rook) {
+                    let mut pieces = [(white_king, 6), (rook, 4), (black_king, 14)];
+                    pieces.sort();
+                    let (table_side, _, index) = table
+                        .index(&pairs, kind, &pieces, white_to_move, table.key)
+                        .unwrap();
+                    side = table_side;
+                    let value = value(white_king, rook, black_king);
+                    let slot = &mut values[index as usize];
+                    assert!(slot.is_none_or(|slot| slot == value), "{:?}", pieces);
+                    *slot = Some(value);
+                }
+            }
+        }
+        // placements that do not occur are free to take any value
+        (
+            side,
+            values.iter().map(|value| value.unwrap_or(0)).collect(),
+        )
+    }
+
+    fn krk_wdl() -> &'static [u8] {
+        KRK_WDL.get_or_init(|| {
+            let mut sides: [[Vec<u8>; 4]; 2] = Default::default();
+            for white_to_move in [true, false] {
+                let (side, values) = krk_values(TableKind::Wdl, white_to_move, |wk, rook, bk| {
+                    krk_value(wk, rook, bk, white_to_move)
+                });
+                sides[side] = compress(&values, 3, 5);
+            }
+            krk_file(WDL_MAGIC, &sides)
+        })
+    }
+
+    // The distances of white to move in moves, from which the odd numbers
+    // of plies follow. Draws do not occur, the values of illegal positions
+    // do not matter.
+    fn krk_dtz() -> &'static [u8] {
+        KRK_DTZ.get_or_init(|| {
+            let distances = &krk_distances()[0];
+            let (_, values) = krk_values(TableKind::Dtz, true, |wk, rook, bk| {
+                distances[krk_position(wk, rook, bk)].map_or(0, |plies| plies / 2)
+            });
+            krk_file(DTZ_MAGIC, &[compress(&values, 4, 16)])
+        })
+    }
+
+    fn table_dir(name: &str, dtz: bool) -> PathBuf {
+        let dir = env::temp_dir().join(format!("rusty_chess_syzygy_{}_{}", process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("KRvK.rtbw"), krk_wdl()).unwrap();
+        if dtz {
+            fs::write(dir.join("KRvK.rtbz"), krk_dtz()).unwrap();
+        }
+        dir
+    }
+
+    fn open(dir: &Path) -> Tablebases {
+        Tablebases::open(dir.to_str().unwrap())
+    }
+
+    fn find_move(game: &Game, uci: &str) -> Move {
+        game.parse_uci(uci).unwrap()
+    }
+
+    #[test]
+    fn test_encoding() {
+        let encoding = Encoding::get();
+        assert_eq!(encoding.map_kk.iter().flatten().max(), Some(&461));
+        assert_eq!(encoding.map_a1d1d4[27], 9);
+        assert_eq!(encoding.map_pawns[8], 47);
+        assert_eq!(encoding.map_pawns[15], 46);
+        assert_eq!(encoding.binomial[3][10], 120);
+        assert_eq!(encoding.lead_pawns_size[1][0], 6);
+        assert_eq!(31332, 6 * 63 * 62 + 4 * 28 * 62 + 4 * 7 * 28 + 4 * 7 * 6);
+    }
+
+    #[test]
+    fn test_open_tablebases() {
+        let dir = table_dir("open", false);
+        for name in ["KQRNvKQRN.rtbw", "KRvR.rtbw", "KXvK.rtbw", "notes.txt"] {
+            fs::write(dir.join(name), []).unwrap();
+        }
+        let tablebases = open(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(tablebases.len(), 1);
+        assert_eq!(tablebases.max_pieces(), 3);
+        assert!(Tablebases::open("/nonexistent/syzygy").is_empty());
+    }
+
+    #[test]
+    fn test_probe_wdl() {
+        let dir = table_dir("wdl", false);
+        let tablebases = open(&dir);
+        let probe = |fen| tablebases.probe_wdl(&mut Game::from_fen(fen).unwrap());
+        assert_eq!(probe("8/8/8/8/8/2k5/8/R6K w - - 0 1"), Some(Wdl::Win));
+        assert_eq!(probe("8/8/8/8/8/2k5/8/R6K b - - 0 1"), Some(Wdl::Loss));
+        // the rook hangs
+        assert_eq!(probe("8/8/8/8/8/2k5/1R6/7K b - - 0 1"), Some(Wdl::Draw));
+        // stalemate
+        assert_eq!(probe("k7/8/K7/8/8/8/8/1R6 b - - 0 1"), Some(Wdl::Draw));
+        // black is the stronger side
+        assert_eq!(probe("8/8/8/8/8/2K5/8/r6k w - - 0 1"), Some(Wdl::Loss));
+        assert_eq!(probe("k7/8/8/8/8/8/8/4K2R w K - 0 1"), None);
+        assert_eq!(probe("8/8/8/8/8/2k5/8/RR5K w - - 0 1"), None);
+        // no DTZ tables
+        let mut game = Game::from_fen("8/8/8/8/8/2k5/8/R6K w - - 0 1").unwrap();
+        assert_eq!(tablebases.probe_dtz(&mut game), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_krk_distances() {
+        let [white, black] = krk_distances();
+        // the longest KRvK win is mate in 16
+        assert_eq!(white.iter().flatten().max(), Some(&31));
+        assert_eq!(black.iter().flatten().max(), Some(&32));
+        // the same results as the simple rules the WDL table is made from
+        for white_king in 0..64 {
+            for rook in 0..64 {
+                for black_king in 0..64 {
+                    let position = krk_position(white_king, rook, black_king);
+                    if is_legal(white_king, rook, black_king, true) {
+                        assert!(white[position].is_some());
+                    }
+                    if is_legal(white_king, rook, black_king, false) {
+                        let value = krk_value(white_king, rook, black_king, false);
+                        assert_eq!(black[position].is_some(), value == 0);
+                    }
+                }
+            }
+        }
+    }
+
+    fn krk_fen(white_king: usize, rook: usize, black_king: usize, white_to_move: bool) -> String {
+        let mut ranks = Vec::new();
+        for rank in (0..8).rev() {
+            let mut text = String::new();
+            let mut empty = 0;
+            for square in rank * 8..rank * 8 + 8 {
+                let piece = match square {
+                    _ if square == white_king => 'K',
+                    _ if square == rook => 'R',
+                    _ if square == black_king => 'k',
+                    _ => {
+                        empty += 1;
+                        continue;
+                    }
+                };
+                if empty > 0 {
+                    text.push_str(&empty.to_string());
+                    empty = 0;
+                }
+                text.push(piece);
+            }
+            if empty > 0 {
+                text.push_str(&empty.to_string());
+            }
+            ranks.push(text);
+        }
+        let side = if white_to_move { "w" } else { "b" };
+        format!("{} {} - - 0 1", ranks.join("/"), side)
+    }
+
+    #[test]
+    fn test_probe_dtz() {
+        let dir = table_dir("dtz", true);
+        let tablebases = open(&dir);
+        let probe = |fen: &str| tablebases.probe_dtz(&mut Game::from_fen(fen).unwrap());
+        // Ra8# and the only move before it
+        assert_eq!(probe("7k/8/6K1/8/8/8/8/R7 w - - 0 1"), Some(1));
+        assert_eq!(probe("7k/8/6K1/8/8/8/8/R7 b - - 0 1"), Some(-2));
+        assert_eq!(probe("8/8/8/8/8/2k5/1R6/7K b - - 0 1"), Some(0));
+        // every position with the white king on the a1-d1-d4 triangle, the
+        // others are mirrored onto these
+        let [white, black] = krk_distances();
+        for white_king in [0, 1, 2, 3, 9, 10, 11, 18, 19, 27] {
+            for rook in 0..64 {
+                for black_king in 0..64 {
+                    let position = krk_position(white_king, rook, black_king);
+                    if is_legal(white_king, rook, black_king, true) {
+                        let fen = krk_fen(white_king, rook, black_king, true);
+                        let expected = white[position].unwrap() as i32;
+                        assert_eq!(probe(&fen), Some(expected), "{}", fen);
+                    }
+                    if is_legal(white_king, rook, black_king, false) {
+                        let fen = krk_fen(white_king, rook, black_king, false);
+                        // a mated side has -1 by convention
+                        let expected = match black[position] {
+                            Some(0) => -1,
+                            Some(plies) => -(plies as i32),
+                            None => 0,
+                        };
+                        assert_eq!(probe(&fen), Some(expected), "{}", fen);
+                    }
+                }
+            }
+        }
+
+        // the moves that mate the fastest rank highest
+        let mut game = Game::from_fen("8/8/8/8/8/2k5/R7/7K w - - 0 1").unwrap();
+        let moves = game.generate_legal_moves();
+        let ranks = tablebases.rank_root_moves(&mut game, &moves).unwrap();
+        for (&mv, rank) in moves.iter().zip(ranks) {
+            game.make_move(mv);
+            let fen = game.to_fen();
+            game.undo_move();
+            let expected = match probe(&fen).unwrap() {
+                0 => 0,
+                dtz => MAX_DTZ + dtz - 1,
+            };
+            assert_eq!(rank, expected, "{}", fen);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rank_root_moves_wdl() {
+        let dir = table_dir("rank", false);
+        let tablebases = open(&dir);
+        let mut game = Game::from_fen("8/8/8/8/8/2k5/R7/7K w - - 0 1").unwrap();
+        let moves = [
+            find_move(&game, "a2a1").raw(),
+            find_move(&game, "a2b2").raw(),
+        ];
+        assert_eq!(tablebases.rank_root_moves(&mut game, &moves), None);
+        assert_eq!(
+            tablebases.rank_root_moves_wdl(&mut game, &moves),
+            Some(vec![MAX_DTZ, 0])
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_search_probes_tablebases() {
+        let dir = table_dir("search", false);
+        let mut search = Search::new();
+        search.set_tablebases(Some(open(&dir)));
+        // taking the knight leads to a won KRvK
+        let mut game = Game::from_fen("8/8/8/1k1n4/8/8/8/3R3K w - - 0 1").unwrap();
+        let result = search.iterative_deepening(&mut game, &SearchLimits::depth(2));
+        assert_eq!(result.best_move, Some(find_move(&game, "d1d5")));
+        assert_eq!(result.score, TB_WIN_SCORE - 1);
+        assert!(search.tb_hits() > 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_search_keeps_fastest_root_moves() {
+        let dir = table_dir("root", true);
+        let tablebases = open(&dir);
+        let mut game = Game::from_fen("8/8/8/8/8/2k5/R7/7K w - - 0 1").unwrap();
+        // the moves after which black is mated the soonest
+        let mut fastest = Vec::new();
+        let mut best = i32::MAX;
+        for mv in game.legal_moves() {
+            game.make_move(mv.raw());
+            let dtz = tablebases.probe_dtz(&mut game).unwrap();
+            game.undo_move();
+            if dtz < 0 && -dtz <= best {
+                if -dtz < best {
+                    fastest.clear();
+                }
+                best = -dtz;
+                fastest.push(mv);
+            }
+        }
+
+        let mut search = Search::new();
+        search.set_tablebases(Some(tablebases));
+        search.set_multi_pv(256);
+        let result = search.iterative_deepening(&mut game, &SearchLimits::depth(1));
+        let mut moves: Vec<Move> = result.lines.iter().map(|line| line.pv[0]).collect();
+        moves.sort_by_key(|mv| mv.raw());
+        fastest.sort_by_key(|mv| mv.raw());
+        assert_eq!(moves, fastest);
+        assert!(search.tb_hits() > 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // The tests below read the real KRvK, KPvK and KQvKR tables, see
+    // tests/syzygy/README.md. Run them with `cargo test -- --ignored`.
+    fn real_tables() -> Tablebases {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/syzygy");
+        let tablebases = Tablebases::open(dir);
+        assert!(tablebases.len() >= 3, "tables missing in {}", dir);
+        tablebases
+    }
+
+    fn probe_real(tablebases: &Tablebases, fen: &str) -> (Option<Wdl>, Option<i32>) {
+        let mut game = Game::from_fen(fen).unwrap();
+        (
+            tablebases.probe_wdl(&mut game),
+            tablebases.probe_dtz(&mut game),
+        )
+    }
+
+    #[test]
+    #[ignore = "needs the real tables in tests/syzygy"]
+    fn test_real_tables() {
+        let tablebases = real_tables();
+        let probe = |fen| probe_real(&tablebases, fen);
+        // Ra8# and the only move before it
+        assert_eq!(
+            probe("7k/8/6K1/8/8/8/8/R7 w - - 0 1"),
+            (Some(Wdl::Win), Some(1))
+        );
+        assert_eq!(
+            probe("7k/8/6K1/8/8/8/8/R7 b - - 0 1"),
+            (Some(Wdl::Loss), Some(-2))
+        );
+        assert_eq!(
+            probe("8/8/8/8/8/2k5/1R6/7K b - - 0 1"),
+            (Some(Wdl::Draw), Some(0))
+        );
+        // the pawn promotes, or with white to move the opposition is lost
+        assert_eq!(
+            probe("8/4P3/8/8/8/8/k7/4K3 w - - 0 1"),
+            (Some(Wdl::Win), Some(1))
+        );
+        assert_eq!(
+            probe("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1"),
+            (Some(Wdl::Draw), Some(0))
+        );
+        let (wdl, dtz) = probe("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1");
+        assert_eq!(wdl, Some(Wdl::Loss));
+        assert!(dtz.unwrap() < 0);
+        // the queen takes the rook, or with black to move the rook takes the
+        // queen with check
+        assert_eq!(
+            probe("4k3/8/8/8/8/8/8/r2QK3 w - - 0 1"),
+            (Some(Wdl::Win), Some(1))
+        );
+        assert_eq!(
+            probe("4k3/8/8/8/8/8/8/r2QK3 b - - 0 1"),
+            (Some(Wdl::Draw), Some(0))
+        );
+        // colors swapped
+        assert_eq!(
+            probe("R2qk3/8/8/8/8/8/8/4K3 b - - 0 1"),
+            (Some(Wdl::Win), Some(1))
+        );
+    }
+
+    #[test]
+    #[ignore = "needs the real tables in tests/syzygy"]
+    fn test_real_krk_table() {
+        let tablebases = real_tables();
+        // every position with black to move against the simple rules
+        for white_king in 0..64 {
+            for rook in (0..64).filter(|&rook| rook != white_king) {
+                for black_king in 0..64 {
+                    if is_adjacent_or_equal(black_king, white_king) || black_king == rook {
+                        continue;
+                    }
+                    let fen = krk_fen(white_king, rook, black_king, false);
+                    let expected = match krk_value(white_king, rook, black_king, false) {
+                        0 => Wdl::Loss,
+                        _ => Wdl::Draw,
+                    };
+                    assert_eq!(probe_real(&tablebases, &fen).0, Some(expected), "{}", fen);
+                }
+            }
+        }
+    }
+}
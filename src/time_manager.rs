@@ -0,0 +1,174 @@
+use crate::search::SearchLimits;
+use std::time::{Duration, Instant};
+
+// time kept back per move for communication delays between GUI and engine
+pub const DEFAULT_MOVE_OVERHEAD_MS: u64 = 10;
+// assumed number of moves until the next time control when none is given
+const DEFAULT_MOVES_TO_GO: u64 = 30;
+const MAX_MOVES_TO_GO: u64 = 50;
+
+// Decides how long to think about a move. The soft limit is checked between
+// iterations, no new iteration is started once it has passed. The hard limit
+// aborts a running iteration.
+#[derive(Clone, Copy, Debug)]
+pub struct TimeManager {
+    start: Instant,
+    soft_limit: Option<Duration>,
+    hard_limit: Option<Duration>,
+}
+
+impl TimeManager {
+    pub fn new(limits: &SearchLimits, white_to_move: bool, move_overhead: Duration) -> Self {
+        let (soft_limit, hard_limit) = match budget(limits, white_to_move, move_overhead) {
+            Some((soft, hard)) => (Some(soft), Some(hard)),
+            None => (None, None),
+        };
+        TimeManager {
+            start: Instant::now(),
+            soft_limit,
+            hard_limit,
+        }
+    }
+
+    // starts counting the time again, used when pondering ends
+    pub fn restart(&mut self) {
+        self.start = Instant::now();
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    pub fn soft_limit_reached(&self) -> bool {
+        self.soft_limit.is_some_and(|limit| self.elapsed() >= limit)
+    }
+
+    pub fn hard_limit_reached(&self) -> bool {
+        self.hard_limit.is_some_and(|limit| self.elapsed() >= limit)
+    }
+}
+
+// Returns the soft and hard limit for the move, or None if the search is not
+// limited by time.
+fn budget(
+    limits: &SearchLimits,
+    white_to_move: bool,
+    move_overhead: Duration,
+) -> Option<(Duration, Duration)> {
+    if limits.infinite {
+        return None;
+    }
+    if let Some(move_time) = limits.move_time {
+        let time = move_time.saturating_sub(move_overhead);
+        return Some((time, time));
+    }
+
+    let (time, increment) = if white_to_move {
+        (
+            limits.white_time?,
+            limits.white_increment.unwrap_or_default(),
+        )
+    } else {
+        (
+            limits.black_time?,
+            limits.black_increment.unwrap_or_default(),
+        )
+    };
+    let available = time.saturating_sub(move_overhead);
+    let moves_to_go = limits
+        .moves_to_go
+        .unwrap_or(DEFAULT_MOVES_TO_GO)
+        .clamp(1, MAX_MOVES_TO_GO);
+
+    // the increment is mostly spent right away, it is added again after the move
+    let soft = available / moves_to_go as u32 + increment * 3 / 4;
+    // never risk more than a fraction of the clock on a single move, unless
+    // it is the last move before the time control
+    let maximum = if moves_to_go == 1 {
+        available * 9 / 10
+    } else {
+        available / 2
+    };
+    let hard = (soft * 3).min(maximum);
+    Some((soft.min(hard), hard))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overhead() -> Duration {
+        Duration::from_millis(DEFAULT_MOVE_OVERHEAD_MS)
+    }
+
+    #[test]
+    fn test_no_time_limit() {
+        assert_eq!(budget(&SearchLimits::depth(5), true, overhead()), None);
+        let limits = SearchLimits {
+            white_time: Some(Duration::from_secs(60)),
+            infinite: true,
+            ..SearchLimits::default()
+        };
+        assert_eq!(budget(&limits, true, overhead()), None);
+    }
+
+    #[test]
+    fn test_move_time() {
+        let limits = SearchLimits {
+            move_time: Some(Duration::from_millis(1000)),
+            ..SearchLimits::default()
+        };
+        let expected = Duration::from_millis(990);
+        assert_eq!(
+            budget(&limits, false, overhead()),
+            Some((expected, expected))
+        );
+    }
+
+    #[test]
+    fn test_budget_uses_clock_of_side_to_move() {
+        let limits = SearchLimits {
+            white_time: Some(Duration::from_millis(60_010)),
+            black_time: Some(Duration::from_millis(3_010)),
+            white_increment: Some(Duration::from_millis(1000)),
+            ..SearchLimits::default()
+        };
+        let (soft, hard) = budget(&limits, true, overhead()).unwrap();
+        assert_eq!(soft, Duration::from_millis(2000 + 750));
+        assert_eq!(hard, Duration::from_millis(3 * 2750));
+        let (soft, hard) = budget(&limits, false, overhead()).unwrap();
+        assert_eq!(soft, Duration::from_millis(100));
+        assert_eq!(hard, Duration::from_millis(300));
+    }
+
+    #[test]
+    fn test_budget_stays_within_clock() {
+        // little time left but a large increment
+        let limits = SearchLimits {
+            white_time: Some(Duration::from_millis(1010)),
+            white_increment: Some(Duration::from_millis(2000)),
+            ..SearchLimits::default()
+        };
+        let (soft, hard) = budget(&limits, true, overhead()).unwrap();
+        assert_eq!(hard, Duration::from_millis(500));
+        assert_eq!(soft, hard);
+        // the last move before the time control may use most of the clock
+        let limits = SearchLimits {
+            white_time: Some(Duration::from_millis(1010)),
+            moves_to_go: Some(1),
+            ..SearchLimits::default()
+        };
+        let (soft, hard) = budget(&limits, true, overhead()).unwrap();
+        assert_eq!(soft, Duration::from_millis(900));
+        assert_eq!(hard, Duration::from_millis(900));
+        // the overhead is larger than the remaining time
+        let limits = SearchLimits {
+            white_time: Some(Duration::from_millis(5)),
+            ..SearchLimits::default()
+        };
+        assert_eq!(
+            budget(&limits, true, overhead()),
+            Some((Duration::ZERO, Duration::ZERO))
+        );
+    }
+}
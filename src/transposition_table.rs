@@ -0,0 +1,200 @@
+use crate::search::TB_WIN_THRESHOLD;
+
+pub const DEFAULT_HASH_SIZE_MB: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    // the score is at least this value (fail high)
+    Lower,
+    // the score is at most this value (fail low)
+    Upper,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub key: u64,
+    pub best_move: Option<u16>,
+    pub score: i32,
+    pub depth: u8,
+    pub bound: Bound,
+    pub age: u8,
+}
+
+pub struct TranspositionTable {
+    entries: Vec<Option<Entry>>,
+    // incremented for every new search, entries from older searches are
+    // replaced first
+    age: u8,
+}
+
+impl TranspositionTable {
+    pub fn new(size_mb: usize) -> Self {
+        let count = (size_mb * 1024 * 1024 / std::mem::size_of::<Option<Entry>>()).max(1);
+        TranspositionTable {
+            entries: vec![None; count],
+            age: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.fill(None);
+        self.age = 0;
+    }
+
+    pub fn new_search(&mut self) {
+        self.age = self.age.wrapping_add(1);
+    }
+
+    // permille of the entries written by the current search, estimated from
+    // the first thousand entries
+    pub fn hashfull(&self) -> usize {
+        let sample = &self.entries[..self.entries.len().min(1000)];
+        let used = sample
+            .iter()
+            .filter(|entry| entry.is_some_and(|entry| entry.age == self.age))
+            .count();
+        used * 1000 / sample.len()
+    }
+
+    fn index(&self, key: u64) -> usize {
+        // maps the key uniformly onto the table without a modulo
+        ((key as u128 * self.entries.len() as u128) >> 64) as usize
+    }
+
+    // Returns the entry stored for `key`, with mate scores converted from
+    // "distance to this node" back to "distance to the root" at `ply`.
+    pub fn probe(&self, key: u64, ply: usize) -> Option<Entry> {
+        let entry = self.entries[self.index(key)]?;
+        if entry.key != key {
+            return None;
+        }
+        Some(Entry {
+            score: score_from_tt(entry.score, ply),
+            ..entry
+        })
+    }
+
+    pub fn store(
+        &mut self,
+        key: u64,
+        best_move: Option<u16>,
+        score: i32,
+        depth: u32,
+        bound: Bound,
+        ply: usize,
+    ) {
+        let index = self.index(key);
+        if let Some(existing) = self.entries[index] {
+            // keep deeper results of the current search for other positions
+            if existing.key != key && existing.age == self.age && existing.depth as u32 > depth {
+                return;
+            }
+        }
+        // keep the old best move if the new search did not find one
+        let best_move = best_move.or_else(|| {
+            self.entries[index]
+                .filter(|existing| existing.key == key)
+                .and_then(|existing| existing.best_move)
+        });
+        self.entries[index] = Some(Entry {
+            key,
+            best_move,
+            score: score_to_tt(score, ply),
+            depth: depth.min(u8::MAX as u32) as u8,
+            bound,
+            age: self.age,
+        });
+    }
+}
+
+// Mate and tablebase win scores are stored relative to the node instead of
+// the root, so that the same position reached at a different ply reports the
+// correct distance.
+fn score_to_tt(score: i32, ply: usize) -> i32 {
+    if score >= TB_WIN_THRESHOLD {
+        score + ply as i32
+    } else if score <= -TB_WIN_THRESHOLD {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i32, ply: usize) -> i32 {
+    if score >= TB_WIN_THRESHOLD {
+        score - ply as i32
+    } else if score <= -TB_WIN_THRESHOLD {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::MATE_SCORE;
+
+    #[test]
+    fn test_store_and_probe() {
+        let mut tt = TranspositionTable::new(1);
+        tt.store(0x1234, Some(0x0E30), 42, 5, Bound::Exact, 3);
+        let entry = tt.probe(0x1234, 3).unwrap();
+        assert_eq!(entry.best_move, Some(0x0E30));
+        assert_eq!(entry.score, 42);
+        assert_eq!(entry.depth, 5);
+        assert_eq!(entry.bound, Bound::Exact);
+        assert_eq!(tt.probe(0x4321, 3), None);
+    }
+
+    #[test]
+    fn test_hashfull() {
+        let mut tt = TranspositionTable::new(1);
+        assert_eq!(tt.hashfull(), 0);
+        for key in 0..1000u64 {
+            // spread the keys over the whole table
+            tt.store(
+                key.wrapping_mul(0x9E3779B97F4A7C15),
+                None,
+                0,
+                1,
+                Bound::Exact,
+                0,
+            );
+        }
+        let hashfull = tt.hashfull();
+        assert!(hashfull > 0 && hashfull < 1000);
+        // entries of older searches do not count
+        tt.new_search();
+        assert_eq!(tt.hashfull(), 0);
+        tt.clear();
+        assert!(tt.probe(0, 0).is_none());
+    }
+
+    #[test]
+    fn test_mate_score_adjustment() {
+        let mut tt = TranspositionTable::new(1);
+        // mate in 3 plies from a node at ply 4 is mate in 7 plies from the root
+        tt.store(0x1234, None, MATE_SCORE - 7, 3, Bound::Exact, 4);
+        // reached again at ply 2, the mate is 5 plies away from the root
+        assert_eq!(tt.probe(0x1234, 2).unwrap().score, MATE_SCORE - 5);
+        tt.store(0x5678, None, -MATE_SCORE + 7, 3, Bound::Exact, 4);
+        assert_eq!(tt.probe(0x5678, 2).unwrap().score, -MATE_SCORE + 5);
+    }
+
+    #[test]
+    fn test_replacement_prefers_depth_of_current_search() {
+        let mut tt = TranspositionTable::new(1);
+        let other = 0x1234 + (1 << 40);
+        assert_eq!(tt.index(0x1234), tt.index(other));
+        tt.store(0x1234, None, 10, 8, Bound::Exact, 0);
+        tt.store(other, None, 20, 2, Bound::Exact, 0);
+        assert!(tt.probe(0x1234, 0).is_some());
+        // entries of an older search are always replaced
+        tt.new_search();
+        tt.store(other, None, 20, 2, Bound::Exact, 0);
+        assert!(tt.probe(other, 0).is_some());
+        assert!(tt.probe(0x1234, 0).is_none());
+    }
+}
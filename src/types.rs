@@ -0,0 +1,326 @@
+use crate::constants::Piece;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Color {
+    White,
+    Black,
+}
+
+impl Color {
+    pub fn opponent(self) -> Color {
+        match self {
+            Color::White => Color::Black,
+            Color::Black => Color::White,
+        }
+    }
+
+    pub fn is_white(self) -> bool {
+        self == Color::White
+    }
+}
+
+// in the same order as the pieces of each color in `Piece`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PieceType {
+    King,
+    Queen,
+    Rook,
+    Bishop,
+    Knight,
+    Pawn,
+}
+
+impl PieceType {
+    pub const ALL: [PieceType; 6] = [
+        PieceType::King,
+        PieceType::Queen,
+        PieceType::Rook,
+        PieceType::Bishop,
+        PieceType::Knight,
+        PieceType::Pawn,
+    ];
+
+    // the lowercase letter used in FEN strings and UCI promotions
+    pub fn to_char(self) -> char {
+        match self {
+            PieceType::King => 'k',
+            PieceType::Queen => 'q',
+            PieceType::Rook => 'r',
+            PieceType::Bishop => 'b',
+            PieceType::Knight => 'n',
+            PieceType::Pawn => 'p',
+        }
+    }
+}
+
+impl Piece {
+    #[cfg(test)]
+    pub fn new(color: Color, piece_type: PieceType) -> Piece {
+        let offset = match color {
+            Color::White => 0,
+            Color::Black => 6,
+        };
+        match piece_type as u8 + offset {
+            0 => Piece::WhiteKing,
+            1 => Piece::WhiteQueen,
+            2 => Piece::WhiteRook,
+            3 => Piece::WhiteBishop,
+            4 => Piece::WhiteKnight,
+            5 => Piece::WhitePawn,
+            6 => Piece::BlackKing,
+            7 => Piece::BlackQueen,
+            8 => Piece::BlackRook,
+            9 => Piece::BlackBishop,
+            10 => Piece::BlackKnight,
+            _ => Piece::BlackPawn,
+        }
+    }
+
+    pub fn color(self) -> Option<Color> {
+        match self as u8 {
+            0 => None,
+            1..=6 => Some(Color::White),
+            _ => Some(Color::Black),
+        }
+    }
+
+    pub fn piece_type(self) -> Option<PieceType> {
+        match self {
+            Piece::Empty => None,
+            piece => Some(PieceType::ALL[(piece as usize - 1) % 6]),
+        }
+    }
+}
+
+// a file from a (0) to h (7)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct File(u8);
+
+impl File {
+    pub fn new(index: u8) -> Option<File> {
+        (index < 8).then_some(File(index))
+    }
+
+    pub fn from_char(file: char) -> Option<File> {
+        ('a'..='h').contains(&file).then(|| File(file as u8 - b'a'))
+    }
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    pub fn to_char(self) -> char {
+        (b'a' + self.0) as char
+    }
+}
+
+// a rank from 1 (0) to 8 (7)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Rank(u8);
+
+impl Rank {
+    pub fn new(index: u8) -> Option<Rank> {
+        (index < 8).then_some(Rank(index))
+    }
+
+    pub fn from_char(rank: char) -> Option<Rank> {
+        ('1'..='8').contains(&rank).then(|| Rank(rank as u8 - b'1'))
+    }
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    pub fn to_char(self) -> char {
+        (b'1' + self.0) as char
+    }
+}
+
+// a square from a1 (0) to h8 (63), the bit index in the board's bitboards
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Square(u8);
+
+impl Square {
+    pub fn new(index: u8) -> Option<Square> {
+        (index < 64).then_some(Square(index))
+    }
+
+    pub fn from_coords(file: File, rank: Rank) -> Square {
+        Square(rank.0 * 8 + file.0)
+    }
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    pub fn file(self) -> File {
+        File(self.0 % 8)
+    }
+
+    pub fn rank(self) -> Rank {
+        Rank(self.0 / 8)
+    }
+}
+
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.file().to_char(), self.rank().to_char())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseSquareError(pub String);
+
+impl fmt::Display for ParseSquareError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid square '{}'", self.0)
+    }
+}
+
+impl std::error::Error for ParseSquareError {}
+
+impl FromStr for Square {
+    type Err = ParseSquareError;
+
+    fn from_str(square: &str) -> Result<Self, Self::Err> {
+        let mut chars = square.chars();
+        match (
+            chars.next().and_then(File::from_char),
+            chars.next().and_then(Rank::from_char),
+            chars.next(),
+        ) {
+            (Some(file), Some(rank), None) => Ok(Square::from_coords(file, rank)),
+            _ => Err(ParseSquareError(square.to_string())),
+        }
+    }
+}
+
+// A move as encoded by the move generator:
+//   bits 0-5: from square
+//   bits 6-11: to square
+//   bits 12-15: flags, see `Move::flags`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[repr(transparent)]
+pub struct Move(u16);
+
+impl Move {
+    pub const fn from_raw(raw: u16) -> Move {
+        Move(raw)
+    }
+
+    pub const fn raw(self) -> u16 {
+        self.0
+    }
+
+    pub fn from(self) -> Square {
+        Square((self.0 & 0x3F) as u8)
+    }
+
+    pub fn to(self) -> Square {
+        Square(((self.0 >> 6) & 0x3F) as u8)
+    }
+
+    // bit 3 promotion, bit 2 capture, bits 0-1 depend on the others:
+    // the promotion piece (queen, rook, bishop, knight), en passant for
+    // captures, or double pawn push, kingside and queenside castling
+    pub fn flags(self) -> u8 {
+        (self.0 >> 12) as u8
+    }
+
+    pub fn promotion(self) -> Option<PieceType> {
+        if self.flags() & 0x8 == 0 {
+            return None;
+        }
+        Some(match self.flags() & 0x3 {
+            0 => PieceType::Queen,
+            1 => PieceType::Rook,
+            2 => PieceType::Bishop,
+            _ => PieceType::Knight,
+        })
+    }
+
+    pub fn is_capture(self) -> bool {
+        self.flags() & 0x4 != 0
+    }
+
+    pub fn is_en_passant(self) -> bool {
+        self.flags() == 0x5
+    }
+
+    pub fn is_double_pawn_push(self) -> bool {
+        self.flags() == 0x1
+    }
+
+    pub fn is_castling(self) -> bool {
+        matches!(self.flags(), 0x2 | 0x3)
+    }
+}
+
+// UCI notation like e2e4 or e7e8q
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.from(), self.to())?;
+        if let Some(promotion) = self.promotion() {
+            write!(f, "{}", promotion.to_char())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_square() {
+        let e4: Square = "e4".parse().unwrap();
+        assert_eq!(e4.index(), 28);
+        assert_eq!(e4.file(), File::from_char('e').unwrap());
+        assert_eq!(e4.rank(), Rank::new(3).unwrap());
+        assert_eq!(e4.to_string(), "e4");
+        assert_eq!(Square::new(64), None);
+        for invalid in ["", "e", "e9", "i1", "e44"] {
+            assert!(invalid.parse::<Square>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_piece_conversion() {
+        for color in [Color::White, Color::Black] {
+            for piece_type in PieceType::ALL {
+                let piece = Piece::new(color, piece_type);
+                assert_eq!(piece.color(), Some(color));
+                assert_eq!(piece.piece_type(), Some(piece_type));
+            }
+        }
+        assert_eq!(
+            Piece::new(Color::Black, PieceType::Knight),
+            Piece::BlackKnight
+        );
+        assert_eq!(Piece::Empty.color(), None);
+        assert_eq!(Piece::Empty.piece_type(), None);
+    }
+
+    #[test]
+    fn test_move_accessors() {
+        // e2e4
+        let mv = Move::from_raw(0x1000 | (28 << 6) | 12);
+        assert_eq!(mv.from().to_string(), "e2");
+        assert_eq!(mv.to().to_string(), "e4");
+        assert!(mv.is_double_pawn_push());
+        assert!(!mv.is_capture());
+        assert_eq!(mv.promotion(), None);
+        assert_eq!(mv.to_string(), "e2e4");
+        // g7xh8=N
+        let mv = Move::from_raw(0xF000 | (63 << 6) | 54);
+        assert!(mv.is_capture());
+        assert!(!mv.is_en_passant());
+        assert_eq!(mv.promotion(), Some(PieceType::Knight));
+        assert_eq!(mv.to_string(), "g7h8n");
+        // e5xd6 en passant and O-O-O
+        assert!(Move::from_raw(0x5000 | (43 << 6) | 36).is_en_passant());
+        assert!(Move::from_raw(0x3000 | (2 << 6) | 4).is_castling());
+    }
+}
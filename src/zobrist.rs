@@ -1,5 +1,7 @@
+use crate::constants::Piece;
 use crate::game::Game;
 use rand::prelude::*;
+use std::sync::OnceLock;
 
 pub struct Zobrist {
     // store Zobrist hash values
@@ -9,22 +11,36 @@ pub struct Zobrist {
     side_to_move: u64,
 }
 
+static KEYS: OnceLock<Zobrist> = OnceLock::new();
+
+impl Default for Zobrist {
+    fn default() -> Self {
+        Zobrist::new()
+    }
+}
+
 impl Zobrist {
+    // the shared keys used for the incrementally updated hashes
+    pub fn keys() -> &'static Zobrist {
+        KEYS.get_or_init(Zobrist::new)
+    }
+
     pub fn new() -> Self {
         let mut rng = StdRng::seed_from_u64(0xDEADBEEF); // fixed seed for reproducibility
         let mut piece_table = [[0u64; 64]; 13];
-        for piece in 0..12 {
-            for square in 0..64 {
-                piece_table[piece][square] = rng.random::<u64>();
+        // Piece::Empty (index 0) does not contribute to the hash
+        for keys in piece_table.iter_mut().skip(1) {
+            for key in keys.iter_mut() {
+                *key = rng.random::<u64>();
             }
         }
         let mut castling_rights = [0u64; 4];
-        for i in 0..4 {
-            castling_rights[i] = rng.random::<u64>();
+        for key in castling_rights.iter_mut() {
+            *key = rng.random::<u64>();
         }
         let mut en_passant_square = [0u64; 64];
-        for i in 0..16 {
-            en_passant_square[i] = rng.random::<u64>();
+        for key in en_passant_square.iter_mut().take(16) {
+            *key = rng.random::<u64>();
         }
         let side_to_move = rng.random::<u64>();
         Zobrist {
@@ -35,26 +51,43 @@ impl Zobrist {
         }
     }
 
-    pub fn zobrist_hash(&self, game: Game) -> u64 {
+    pub fn piece_key(&self, piece: Piece, square: usize) -> u64 {
+        self.piece_table[piece as usize][square]
+    }
+
+    pub fn castling_key(&self, castling_rights: u8) -> u64 {
+        let mut key = 0;
+        for (i, castling_key) in self.castling_rights.iter().enumerate() {
+            if (castling_rights & (1 << i)) != 0 {
+                key ^= castling_key;
+            }
+        }
+        key
+    }
+
+    pub fn en_passant_key(&self, en_passant_square: Option<u8>) -> u64 {
+        match en_passant_square {
+            Some(square) => self.en_passant_square[(square % 8) as usize],
+            None => 0,
+        }
+    }
+
+    pub fn side_to_move_key(&self) -> u64 {
+        self.side_to_move
+    }
+
+    // Computes the hash from scratch. The game keeps the hash up to date
+    // incrementally, this is the reference to check it against.
+    pub fn zobrist_hash(&self, game: &Game) -> u64 {
         let mut hash = 0u64;
         // Pieces on squares
-        for square in 0..64 {
-            let piece = game.board.piece_list[square] as usize;
-            hash ^= self.piece_table[piece][square];
+        for (square, &piece) in game.board.piece_list.iter().enumerate() {
+            hash ^= self.piece_key(piece, square);
         }
         // get latest game state
         let state = game.history.current_state();
-        // Castling rights
-        for i in 0..4 {
-            if (state.castling_rights & (1 << i)) != 0 {
-                hash ^= self.castling_rights[i];
-            }
-        }
-        // En passant square
-        if state.en_passant_square.is_some() {
-            let file = state.en_passant_square.unwrap() % 8;
-            hash ^= self.en_passant_square[file as usize];
-        }
+        hash ^= self.castling_key(state.castling_rights);
+        hash ^= self.en_passant_key(state.en_passant_square);
         // Side to move
         if state.white_to_move {
             hash ^= self.side_to_move;
@@ -71,7 +104,34 @@ mod tests {
     fn test_zobrist_hash() {
         let zobrist = Zobrist::new();
         let game = Game::new();
-        let hash = zobrist.zobrist_hash(game);
+        let hash = zobrist.zobrist_hash(&game);
         assert_ne!(hash, 0);
     }
+
+    #[test]
+    fn test_zobrist_hash_transposition() {
+        // 1. Nf3 Nf6 2. Nc3 and 1. Nc3 Nf6 2. Nf3 reach the same position
+        let zobrist = Zobrist::new();
+        let mut first = Game::new();
+        let mut second = Game::new();
+        for mv in [6 | (21 << 6), 62 | (45 << 6), 1 | (18 << 6)] {
+            first.make_move(mv);
+        }
+        for mv in [1 | (18 << 6), 62 | (45 << 6), 6 | (21 << 6)] {
+            second.make_move(mv);
+        }
+        assert_eq!(zobrist.zobrist_hash(&first), zobrist.zobrist_hash(&second));
+        assert_ne!(
+            zobrist.zobrist_hash(&first),
+            zobrist.zobrist_hash(&Game::new())
+        );
+    }
+
+    #[test]
+    fn test_zobrist_hash_distinguishes_colors() {
+        let zobrist = Zobrist::new();
+        let white = Game::from_fen("4k3/8/8/8/8/8/P7/4K3 w - - 0 1").unwrap();
+        let black = Game::from_fen("4k3/8/8/8/8/8/p7/4K3 w - - 0 1").unwrap();
+        assert_ne!(zobrist.zobrist_hash(&white), zobrist.zobrist_hash(&black));
+    }
 }